@@ -1,8 +1,8 @@
 #%%
-from .clib import CQRel, CDataset, CModel, query_json
+from .clib import CQRel, CDataset, CModel, FastRankError, query_json
 from .training import TrainRequest
 
 VERSION_TUPLE = (0,7,0)
 __version__ = '{}.{}.{}'.format(*VERSION_TUPLE)
 
-__all__ = ['clib', 'training', 'CQRel', 'CDataset', 'CModel', 'query_json', 'TrainRequest']
\ No newline at end of file
+__all__ = ['clib', 'training', 'CQRel', 'CDataset', 'CModel', 'FastRankError', 'query_json', 'TrainRequest']
\ No newline at end of file
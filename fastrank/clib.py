@@ -1,9 +1,23 @@
 import json
 from .fastrank import lib, ffi
-from typing import Dict, Set, List
+from typing import Any, Dict, Set, List
 
 # Keep in sync with fastrank/src/model.rs : fastrank::model::ModelEnum
-_MODEL_TYPES = ["SingleFeature", "Linear", "DecisionTree", "Ensemble"]
+_MODEL_TYPES = ["SingleFeature", "Linear", "DecisionTree", "Ensemble", "MLP"]
+
+
+class FastRankError(Exception):
+    """
+    Raised for any error reported by the Rust library.
+
+    ``code`` says what kind of error it was, so callers need not match on messages: one of "parse", "nan", "missing_feature", "empty_dataset", "invalid_parameter", "io", "panic" (a bug, caught before it could crash Python) or "error".
+    """
+
+    def __init__(self, error: str, code: str, context: str):
+        super().__init__("{0}: {1}".format(error, context))
+        self.error = error
+        self.code = code
+        self.context = context
 
 
 def _handle_rust_str(result) -> str:
@@ -45,9 +59,11 @@ def _maybe_raise_error_str(rust_error_string):
     if "{" in rust_error_string:
         response = json.loads(rust_error_string)
         if "error" in response and "context" in response:
-            raise Exception("{0}: {1}".format(response["error"], response["context"]))
+            raise FastRankError(
+                response["error"], response.get("code", "error"), response["context"]
+            )
     else:
-        raise Exception(rust_error_string)
+        raise FastRankError("error", "error", rust_error_string)
 
 
 def _maybe_raise_error_json(response):
@@ -55,10 +71,25 @@ def _maybe_raise_error_json(response):
         return
     if isinstance(response, dict):
         if "error" in response and "context" in response:
-            raise Exception("{0}: {1}".format(response["error"], response["context"]))
+            raise FastRankError(
+                response["error"], response.get("code", "error"), response["context"]
+            )
     return
 
 
+# Numpy arrays that Rust is reading in place, by handle; see CDataset.from_numpy.
+_borrowed_arrays = {}
+
+
+def _handle_key(handle) -> int:
+    return int(ffi.cast("uintptr_t", handle))
+
+
+@ffi.callback("void(const void *)")
+def _release_borrowed(owner):
+    _borrowed_arrays.pop(_handle_key(owner), None)
+
+
 class CQRel:
     """
     This class represents a loaded set of TREC relevance judgments.
@@ -205,6 +236,44 @@ class CModel:
         """
         return self._query_json("to_json")
 
+    def feature_importance(self, dataset: "CDataset" = None) -> Dict[str, Dict[str, float]]:
+        """
+        Report how much this model relies on each feature: ``splits`` and total ``gain`` for trees, and an ``importance`` share that sums to 1 (normalized absolute weights for linear models).
+
+        :param dataset: If given, features are reported by name rather than by number.
+        :type dataset: CDataset
+
+        >>> model.feature_importance(dataset)["pagerank"]["importance"]
+        """
+        report = self._query_json("feature_importance")
+        if dataset is None:
+            return report
+        names = dataset.feature_index_to_name()
+        return {names.get(int(fid), fid): fi for fid, fi in report.items()}
+
+    def explain(self, dataset: "CDataset", instance_ids: List[int]) -> List[Dict[str, Any]]:
+        """
+        Explain the scores of some documents with SHAP values (exact TreeSHAP for trees, linear SHAP for linear models), using the whole dataset as background.
+
+        Each explanation has the ``instance``, its ``score``, the model's ``expected_value`` and per-feature ``contributions`` that add up to the score.
+
+        >>> top = dataset.instances_by_query()["321"][0]
+        >>> model.explain(dataset, [top])[0]["contributions"]
+        """
+        self._require_init()
+        dataset._require_init()
+        response = json.loads(
+            _handle_rust_str(
+                lib.explain(
+                    self.pointer,
+                    dataset.pointer,
+                    json.dumps(list(instance_ids)).encode("utf-8"),
+                )
+            )
+        )
+        _maybe_raise_error_json(response)
+        return response
+
     def __str__(self):
         return str(self.to_dict())
 
@@ -221,14 +290,11 @@ class CDataset:
 
     def __init__(self, pointer=None):
         self.pointer = pointer
-        # need to hold onto any numpy arrays...
-        self.numpy_arrays_to_keep = []
 
     def __del__(self):
         if self.pointer is not None:
             lib.free_dataset(self.pointer)
             self.pointer = None
-        self.numpy_arrays_to_keep = []
 
     @staticmethod
     def open_ranksvm(data_path, feature_names_path=None) -> "CDataset":
@@ -252,16 +318,28 @@ class CDataset:
         )
 
     @staticmethod
-    def from_numpy(X, y, qid) -> "CDataset":
+    def from_numpy(
+        X, y, qid, weights=None, copy=False, docids=None, feature_names=None
+    ) -> "CDataset":
         """
-        Construct a dataset from in-memory numpy arrays. This class will hold on to them so that Rust points at them. If you delete them, bad things will happen.
+        Construct a dataset from in-memory numpy arrays.
+
+        Unless ``copy`` is set, Rust reads ``X`` in place and keeps it alive until this dataset and everything derived from it are gone; don't modify it in the meantime. Labels, qids and weights are always copied.
 
-        :param X: The feature matrix, a (NxD) float32 matrix; N instances, D features. Must be dense for now.
+        :param X: The feature matrix, a (NxD) float32 or float64 matrix; N instances, D features. C or Fortran order; other dtypes or layouts are converted (and so copied). Must be dense for now.
         :type X: numpy.array
-        :param y: The judgment vector, a 1xN or Nx1 float64 matrix.
+        :param y: The judgment vector, a 1xN or Nx1 matrix.
         :type y: numpy.array
-        :param qid: The numeric representations of query ids (not possible for all datasets, I know.) 1xN or Nx1 int64 matrix.
+        :param qid: The query of each instance, a 1xN or Nx1 matrix of integers or strings.
         :type qid: numpy.array
+        :param weights: Per-instance weights, a 1xN or Nx1 matrix (optional).
+        :type weights: numpy.array
+        :param copy: Give Rust its own copy of X, so the array may be changed or freed afterwards.
+        :type copy: bool
+        :param docids: One document id per instance, needed by :func:`~predict_trecrun` (optional).
+        :type docids: List[str]
+        :param feature_names: One name per column of X, so features can be referred to by name (optional).
+        :type feature_names: List[str]
 
         We can then construct our own numpy arrays, or use the sklearn loader:
 
@@ -270,31 +348,72 @@ class CDataset:
         >>> X = X.todense()
         >>> dataset = CDataset.from_numpy(X, y, qid)
         """
+        import numpy as np
+
+        X = np.asarray(X)
         (N, D) = X.shape
-        assert N > 0
-        assert D > 0
-        assert len(y) == N
-        assert len(qid) == N
-        # TODO: be more flexible here!
-        assert X.dtype == "float32"
-        assert y.dtype == "float64"
-        assert qid.dtype == "int64"
-        # Since Rust just has a pointer to them, have python keep them!
-        numpy_arrays_to_keep = [X, y, qid]
-        # Pass pointers to these arrays to Rust!
-        dataset = CDataset(
-            _handle_c_result(
-                lib.make_dense_dataset_f32_f64_i64(
-                    N,
-                    D,
-                    ffi.cast("float *", X.ctypes.data),
+        if X.dtype not in (np.float32, np.float64):
+            X = X.astype(np.float64)
+        if X.flags["C_CONTIGUOUS"]:
+            order = "C"
+        elif X.flags["F_CONTIGUOUS"]:
+            order = "F"
+        else:
+            X = np.ascontiguousarray(X)
+            order = "C"
+        y = np.ascontiguousarray(y, dtype=np.float64).ravel()
+        qid = np.asarray(qid).ravel()
+        qid_names = None
+        if qid.dtype.kind in "OUS":
+            # Rust wants integers, so hand it positions in the sorted list of names.
+            uniques, qid = np.unique(qid, return_inverse=True)
+            qid_names = [
+                q.decode("utf-8") if isinstance(q, bytes) else str(q) for q in uniques
+            ]
+        if qid.dtype not in (np.int32, np.int64):
+            qid = qid.astype(np.int64)
+        qid = np.ascontiguousarray(qid)
+        weights_ptr = ffi.NULL
+        if weights is not None:
+            weights = np.ascontiguousarray(weights, dtype=np.float64).ravel()
+            weights_ptr = ffi.cast("double *", weights.ctypes.data)
+
+        spec = {
+            "n": N,
+            "d": D,
+            "x_dtype": str(X.dtype),
+            "order": order,
+            "qid_dtype": str(qid.dtype),
+            "qid_names": qid_names,
+            "copy": copy,
+            "docids": None if docids is None else [str(d) for d in docids],
+            "feature_names": None
+            if feature_names is None
+            else [str(f) for f in feature_names],
+        }
+        owner, release = ffi.NULL, ffi.NULL
+        if not copy:
+            # Rust calls _release_borrowed(owner) once nothing points at X any more.
+            owner, release = ffi.new_handle(X), _release_borrowed
+            _borrowed_arrays[_handle_key(owner)] = owner
+        try:
+            pointer = _handle_c_result(
+                lib.make_dense_dataset(
+                    json.dumps(spec).encode("utf-8"),
+                    ffi.cast("void *", X.ctypes.data),
                     ffi.cast("double *", y.ctypes.data),
-                    ffi.cast("int64_t *", qid.ctypes.data),
+                    ffi.cast("void *", qid.ctypes.data),
+                    weights_ptr,
+                    owner,
+                    release,
                 )
             )
-        )
-        dataset.numpy_arrays_to_keep = numpy_arrays_to_keep
-        return dataset
+        except FastRankError:
+            # Rust may have failed before it took the borrow.
+            if owner != ffi.NULL:
+                _borrowed_arrays.pop(_handle_key(owner), None)
+            raise
+        return CDataset(pointer)
 
     def _require_init(self):
         if self.pointer is None:
@@ -318,8 +437,6 @@ class CDataset:
                     )
                 )
         child = CDataset()
-        # keep those alive if need-be in case they lose the parent!
-        child.numpy_arrays_to_keep = self.numpy_arrays_to_keep
         request = json.dumps(queries).encode("utf-8")
         child.pointer = _handle_c_result(
             lib.dataset_query_sampling(self.pointer, request)
@@ -344,18 +461,166 @@ class CDataset:
         child = CDataset(
             _handle_c_result(lib.dataset_feature_sampling(self.pointer, fnums_str))
         )
-        child.numpy_arrays_to_keep = self.numpy_arrays_to_keep
         return child
 
-    def train_model(self, train_req: 'TrainRequest') -> CModel:
+    def with_weights(
+        self, instance_weights_path: str = None, query_weights_path: str = None
+    ) -> "CDataset":
+        """
+        Weight instances and/or queries from side files; weighted data counts more in training and evaluation.
+
+        :param instance_weights_path: A file with one weight per line, in instance order (optional).
+        :type instance_weights_path: str
+        :param query_weights_path: A file of "qid weight" lines; unlisted queries weigh 1 (optional).
+        :type query_weights_path: str
+        """
+        self._require_init()
+        paths = [instance_weights_path, query_weights_path]
+        paths = [ffi.NULL if p is None else p.encode("utf-8") for p in paths]
+        child = CDataset(
+            _handle_c_result(lib.dataset_with_weights(self.pointer, paths[0], paths[1]))
+        )
+        return child
+
+    def train_model(
+        self, train_req: "TrainRequest", validation: "CDataset" = None
+    ) -> CModel:
         """
         Train a Model on this Dataset.
+
+        If a validation dataset is given, learners pick their best size (trees, rounds, epochs) on it and stop early according to ``train_req.validation``.
         """
         self._require_init()
         train_req_str = json.dumps(train_req.to_dict()).encode("utf-8")
-        train_resp = _handle_c_result(lib.train_model(train_req_str, self.pointer))
+        if validation is None:
+            train_resp = _handle_c_result(lib.train_model(train_req_str, self.pointer))
+        else:
+            validation._require_init()
+            train_resp = _handle_c_result(
+                lib.train_model_with_validation(
+                    train_req_str, self.pointer, validation.pointer
+                )
+            )
         return CModel(train_resp, train_req)
 
+    def cross_validate(
+        self, train_req: "TrainRequest", num_folds: int = 5, seed: int = 42
+    ) -> Dict[str, Any]:
+        """
+        Train and test one model per fold, where folds partition this dataset's queries.
+
+        Returns a dictionary with the overall ``mean``, the ``fold_mean``, per-query ``scores``, and a list of ``folds``, each with its ``test_queries``, ``scores``, ``mean`` and trained ``model`` (a :class:`CModel`).
+
+        >>> result = dataset.cross_validate(TrainRequest.random_forest(), num_folds=5)
+        >>> print(result["mean"])
+        """
+        self._require_init()
+        request = {
+            "train_request": train_req.to_dict(),
+            "num_folds": num_folds,
+            "seed": seed,
+        }
+        response = json.loads(
+            _handle_rust_str(
+                lib.cross_validate(json.dumps(request).encode("utf-8"), self.pointer)
+            )
+        )
+        _maybe_raise_error_json(response)
+        for fold in response["folds"]:
+            fold["model"] = CModel.from_dict(fold["model"])
+        return response
+
+    def select_features(
+        self,
+        train_req: "TrainRequest",
+        direction: str = "Forward",
+        features: List[str] = None,
+        validation: "CDataset" = None,
+        fraction: float = 0.2,
+        seed: int = 42,
+        patience: int = None,
+    ) -> Dict[str, Any]:
+        """
+        Greedily add ("Forward") or remove ("Backward") one feature at a time, retraining ``train_req`` for every candidate and scoring it on validation queries.
+
+        Returns a dictionary with the ``selected`` feature names, their ``score``, and the ``trajectory`` of every step (the feature ``changed``, the ``features`` after it, and its ``score``).
+
+        :param validation: Queries to score on; without one, a ``fraction`` of this dataset is held out.
+        :type validation: CDataset
+        :param patience: Stop after this many steps without a new best score (optional).
+        :type patience: int
+
+        >>> result = dataset.select_features(TrainRequest.coordinate_ascent())
+        >>> print(result["selected"])
+        """
+        self._require_init()
+        validation_pointer = ffi.NULL
+        if validation is not None:
+            validation._require_init()
+            validation_pointer = validation.pointer
+        request = {
+            "train_request": train_req.to_dict(),
+            "direction": direction,
+            "features": features,
+            "fraction": fraction,
+            "seed": seed,
+            "patience": patience,
+        }
+        response = json.loads(
+            _handle_rust_str(
+                lib.select_features(
+                    json.dumps(request).encode("utf-8"), self.pointer, validation_pointer
+                )
+            )
+        )
+        _maybe_raise_error_json(response)
+        return response
+
+    def tune(
+        self,
+        train_req: "TrainRequest",
+        space: Dict[str, Any],
+        num_samples: int = None,
+        holdout: float = 0.2,
+        num_folds: int = None,
+        seed: int = 42,
+    ) -> Dict[str, Any]:
+        """
+        Search over the parameters of ``train_req``, training configurations in parallel inside Rust.
+
+        Each entry of ``space`` maps a parameter name to a list of values to choose from, or to a distribution such as ``{"LogUniform": {"low": 1e-3, "high": 1.0}}`` or ``{"IntRange": {"low": 2, "high": 8}}``.
+        Without ``num_samples`` every combination is tried (a grid search); otherwise that many random configurations are.
+        Configurations are scored on a ``holdout`` fraction of queries, or with ``num_folds``-fold cross-validation if given.
+
+        Returns a dictionary with a ``leaderboard`` (best first) and the ``best`` TrainRequest.
+
+        >>> result = dataset.tune(TrainRequest.random_forest(), {"max_depth": [4, 8], "num_trees": [50, 100]})
+        >>> model = dataset.train_model(result["best"])
+        """
+        from .training import TrainRequest
+
+        self._require_init()
+        request = {
+            "train_request": train_req.to_dict(),
+            "space": {
+                name: {"Choice": dist} if isinstance(dist, list) else dist
+                for name, dist in space.items()
+            },
+            "strategy": "Grid"
+            if num_samples is None
+            else {"Random": {"num_samples": num_samples}},
+            "validation": {"Holdout": {"fraction": holdout}}
+            if num_folds is None
+            else {"CrossValidation": {"num_folds": num_folds}},
+            "seed": seed,
+        }
+        response = json.loads(
+            _handle_rust_str(lib.tune(json.dumps(request).encode("utf-8"), self.pointer))
+        )
+        _maybe_raise_error_json(response)
+        response["best"] = TrainRequest.from_dict(response["best"])
+        return response
+
     def _query_json(self, message="num_features"):
         self._require_init()
         response = json.loads(
@@ -438,6 +703,82 @@ class CDataset:
         _maybe_raise_error_json(response)
         return response
 
+    def permutation_importance(
+        self,
+        model: CModel,
+        evaluator: str,
+        qrel: CQRel = None,
+        features: List[str] = None,
+        num_repeats: int = 5,
+        num_bootstrap: int = 200,
+        seed: int = 42,
+    ) -> Dict[str, Any]:
+        """
+        Shuffle each feature's values within queries and measure how much the model's mean score drops.
+
+        Returns a dictionary with the unshuffled ``baseline`` and, per feature name, the mean ``drop`` and the ``lower`` and ``upper`` (5th and 95th percentile) bootstrap bounds on it.
+
+        :param model: The model to explain; any kind works.
+        :type model: CModel
+        :param evaluator: The evaluator to use. Supports "ndcg", "ndcg@5", etc.
+        :type evaluator: str
+        :param features: Names of the features to shuffle; all of them by default.
+        :type features: List[str]
+
+        >>> result = dataset.permutation_importance(model, "ndcg@5")
+        >>> print(result["features"]["pagerank"]["drop"])
+        """
+        self._require_init()
+        model._require_init()
+        request = {
+            "measure": evaluator,
+            "judgments": qrel.to_dict() if qrel is not None else None,
+            "features": features,
+            "num_repeats": num_repeats,
+            "num_bootstrap": num_bootstrap,
+            "seed": seed,
+        }
+        response = json.loads(
+            _handle_rust_str(
+                lib.permutation_importance(
+                    json.dumps(request).encode("utf-8"), model.pointer, self.pointer
+                )
+            )
+        )
+        _maybe_raise_error_json(response)
+        return response
+
+    def partial_dependence(
+        self, model: CModel, feature: str, num_points: int = 20, ice: bool = False
+    ) -> Dict[str, Any]:
+        """
+        Sweep one feature over its quantiles in this dataset, holding every other feature fixed.
+
+        Returns a dictionary with the ``grid`` of feature values, the ``average`` score at each (partial dependence), and, if ``ice`` is set, each instance's own curve under ``ice``.
+
+        :param model: The model to probe.
+        :type model: CModel
+        :param feature: The name or number of the feature to sweep.
+        :type feature: str
+
+        >>> curve = dataset.partial_dependence(model, "pagerank")
+        >>> print(list(zip(curve["grid"], curve["average"])))
+        """
+        self._require_init()
+        model._require_init()
+        request = {"feature": feature, "num_points": num_points, "ice": ice}
+        response = json.loads(
+            _handle_rust_str(
+                lib.partial_dependence(
+                    json.dumps(request).encode("utf-8"), model.pointer, self.pointer
+                )
+            )
+        )
+        _maybe_raise_error_json(response)
+        if response.get("ice") is not None:
+            response["ice"] = dict((int(k), v) for k, v in response["ice"].items())
+        return response
+
     def predict_scores(self, model: CModel) -> Dict[int, float]:
         return model.predict_scores(self)
 
@@ -1,5 +1,5 @@
 import attr
-from typing import Union, Any, Dict
+from typing import Union, Any, Dict, Optional
 import random
 from .clib import CQRel, query_json
 
@@ -18,6 +18,8 @@ class CoordinateAscentParams:
     normalize = attr.ib(type=bool, default=True)
     init_random = attr.ib(type=bool, default=True)
     output_ensemble = attr.ib(type=bool, default=False)
+    # Feature name or number to {"Increasing": []} or {"Decreasing": []}.
+    monotone_constraints = attr.ib(type=Dict[str, Any], factory=dict)
     seed = attr.ib(type=int, default=random.randint(0, (1 << 64) - 1))
     quiet = attr.ib(type=bool, default=False)
 
@@ -42,9 +44,17 @@ class RandomForestParams:
     weight_trees = attr.ib(type=bool, default=True)
     split_method = attr.ib(type=str, default="SquaredError")
     instance_sampling_rate = attr.ib(type=float, default=0.5)
+    # {"WithoutReplacement": []}, {"QueryBootstrap": []} or {"InstanceBootstrap": []}.
+    instance_sampling = attr.ib(type=Any, default="WithoutReplacement")
     feature_sampling_rate = attr.ib(type=float, default=0.25)
     min_leaf_support = attr.ib(type=int, default=10)
     split_candidates = attr.ib(type=int, default=3)
+    split_finding = attr.ib(type=str, default="EvenlySpaced")
+    # {"All": []}, {"Sqrt": []}, {"Log2": []} or {"Fraction": 0.3}: how many features each node may split on.
+    features_per_split = attr.ib(type=Any, default="All")
+    missing_values = attr.ib(type=str, default="AsZero")
+    # Feature name or number to {"Increasing": []} or {"Decreasing": []}.
+    monotone_constraints = attr.ib(type=Dict[str, Any], factory=dict)
     max_depth = attr.ib(type=int, default=8)
     seed = attr.ib(type=int, default=random.randint(0, (1 << 64) - 1))
     quiet = attr.ib(type=bool, default=False)
@@ -60,6 +70,180 @@ class RandomForestParams:
         return RandomForestParams(**params)
 
 
+@attr.s
+class LambdaMARTParams:
+    """
+    This class represents the configuration and parameters available for FastRank's LambdaMART Model.
+    """
+
+    num_trees = attr.ib(type=int, default=100)
+    learning_rate = attr.ib(type=float, default=0.1)
+    instance_sampling_rate = attr.ib(type=float, default=1.0)
+    feature_sampling_rate = attr.ib(type=float, default=1.0)
+    min_leaf_support = attr.ib(type=int, default=1)
+    split_candidates = attr.ib(type=int, default=32)
+    split_finding = attr.ib(type=str, default="EvenlySpaced")
+    missing_values = attr.ib(type=str, default="AsZero")
+    # Feature name or number to {"Increasing": []} or {"Decreasing": []}.
+    monotone_constraints = attr.ib(type=Dict[str, Any], factory=dict)
+    max_depth = attr.ib(type=int, default=5)
+    seed = attr.ib(type=int, default=random.randint(0, (1 << 64) - 1))
+    quiet = attr.ib(type=bool, default=False)
+
+    def name(self):
+        return "LambdaMART"
+
+    def to_dict(self):
+        return attr.asdict(self, recurse=True)
+
+    @staticmethod
+    def from_dict(params) -> "LambdaMARTParams":
+        return LambdaMARTParams(**params)
+
+
+@attr.s
+class MARTParams:
+    """
+    This class represents the configuration and parameters available for FastRank's MART (gradient-boosted regression trees) Model.
+    """
+
+    num_trees = attr.ib(type=int, default=100)
+    learning_rate = attr.ib(type=float, default=0.1)
+    instance_sampling_rate = attr.ib(type=float, default=0.5)
+    feature_sampling_rate = attr.ib(type=float, default=1.0)
+    min_leaf_support = attr.ib(type=int, default=1)
+    split_candidates = attr.ib(type=int, default=32)
+    split_finding = attr.ib(type=str, default="EvenlySpaced")
+    missing_values = attr.ib(type=str, default="AsZero")
+    # Feature name or number to {"Increasing": []} or {"Decreasing": []}.
+    monotone_constraints = attr.ib(type=Dict[str, Any], factory=dict)
+    max_depth = attr.ib(type=int, default=5)
+    seed = attr.ib(type=int, default=random.randint(0, (1 << 64) - 1))
+    quiet = attr.ib(type=bool, default=False)
+
+    def name(self):
+        return "MART"
+
+    def to_dict(self):
+        return attr.asdict(self, recurse=True)
+
+    @staticmethod
+    def from_dict(params) -> "MARTParams":
+        return MARTParams(**params)
+
+
+@attr.s
+class NeuralParams:
+    """
+    This class represents the configuration and parameters available for FastRank's RankNet and ListNet Models.
+
+    Set ``hidden_units`` to zero to learn a linear model instead of a small multi-layer perceptron.
+    """
+
+    loss = attr.ib(type=str, default="RankNet")
+    hidden_units = attr.ib(type=int, default=10)
+    num_epochs = attr.ib(type=int, default=50)
+    learning_rate = attr.ib(type=float, default=0.01)
+    l2 = attr.ib(type=float, default=0.0)
+    seed = attr.ib(type=int, default=random.randint(0, (1 << 64) - 1))
+    quiet = attr.ib(type=bool, default=False)
+
+    def name(self):
+        return "Neural"
+
+    def to_dict(self):
+        return attr.asdict(self, recurse=True)
+
+    @staticmethod
+    def from_dict(params) -> "NeuralParams":
+        return NeuralParams(**params)
+
+
+@attr.s
+class RankSVMParams:
+    """
+    This class represents the configuration and parameters available for FastRank's linear RankSVM Model.
+    """
+
+    regularization = attr.ib(type=float, default=1e-4)
+    num_epochs = attr.ib(type=int, default=20)
+    seed = attr.ib(type=int, default=random.randint(0, (1 << 64) - 1))
+    quiet = attr.ib(type=bool, default=False)
+
+    def name(self):
+        return "RankSVM"
+
+    def to_dict(self):
+        return attr.asdict(self, recurse=True)
+
+    @staticmethod
+    def from_dict(params) -> "RankSVMParams":
+        return RankSVMParams(**params)
+
+
+@attr.s
+class AdaRankParams:
+    """
+    This class represents the configuration and parameters available for FastRank's AdaRank Model.
+    """
+
+    num_rounds = attr.ib(type=int, default=100)
+    tolerance = attr.ib(type=float, default=0.002)
+    max_consecutive_selections = attr.ib(type=int, default=5)
+    quiet = attr.ib(type=bool, default=False)
+
+    def name(self):
+        return "AdaRank"
+
+    def to_dict(self):
+        return attr.asdict(self, recurse=True)
+
+    @staticmethod
+    def from_dict(params) -> "AdaRankParams":
+        return AdaRankParams(**params)
+
+
+@attr.s
+class RankBoostParams:
+    """
+    This class represents the configuration and parameters available for FastRank's RankBoost Model.
+    """
+
+    num_rounds = attr.ib(type=int, default=300)
+    threshold_candidates = attr.ib(type=int, default=10)
+    quiet = attr.ib(type=bool, default=False)
+
+    def name(self):
+        return "RankBoost"
+
+    def to_dict(self):
+        return attr.asdict(self, recurse=True)
+
+    @staticmethod
+    def from_dict(params) -> "RankBoostParams":
+        return RankBoostParams(**params)
+
+
+@attr.s
+class ValidationParams:
+    """
+    This class represents how a TrainRequest holds out queries to select models and stop early.
+
+    The fraction is ignored when a separate validation dataset is passed to :func:`~fastrank.clib.CDataset.train_model`.
+    """
+
+    fraction = attr.ib(type=float, default=0.2)
+    patience = attr.ib(type=Optional[int], default=10)
+    seed = attr.ib(type=int, default=random.randint(0, (1 << 64) - 1))
+
+    def to_dict(self):
+        return attr.asdict(self, recurse=True)
+
+    @staticmethod
+    def from_dict(params) -> "ValidationParams":
+        return ValidationParams(**params)
+
+
 @attr.s
 class TrainRequest:
     """
@@ -69,14 +253,31 @@ class TrainRequest:
 
     - `~coordinate_ascent`
     - `~random_forest`
+    - `~lambdamart`
+    - `~mart`
+    - `~ranknet`
+    - `~listnet`
+    - `~ranksvm`
+    - `~adarank`
+    - `~rankboost`
     """
 
     measure = attr.ib(type=str, default="ndcg")
     params = attr.ib(
-        type=Union[CoordinateAscentParams, RandomForestParams],
+        type=Union[
+            CoordinateAscentParams,
+            RandomForestParams,
+            LambdaMARTParams,
+            MARTParams,
+            NeuralParams,
+            RankSVMParams,
+            AdaRankParams,
+            RankBoostParams,
+        ],
         factory=CoordinateAscentParams,
     )
     judgments = attr.ib(type=CQRel, default=None)
+    validation = attr.ib(type=Optional[ValidationParams], default=None)
 
     def to_dict(self) -> Dict[str, Any]:
         """
@@ -86,10 +287,14 @@ class TrainRequest:
         judgments = None
         if self.judgments is not None:
             judgments = self.judgments.to_dict()
+        validation = None
+        if self.validation is not None:
+            validation = self.validation.to_dict()
         return {
             "measure": self.measure,
             "params": wrapped_params,
             "judgments": judgments,
+            "validation": validation,
         }
 
     def clone(self) -> "TrainRequest":
@@ -112,6 +317,55 @@ class TrainRequest:
         """
         return TrainRequest.from_dict(query_json("random_forest_defaults"))
 
+    @staticmethod
+    def lambdamart() -> "TrainRequest":
+        """
+        Get a new TrainRequest with default parameters for a LambdaMART Model.
+        """
+        return TrainRequest.from_dict(query_json("lambdamart_defaults"))
+
+    @staticmethod
+    def mart() -> "TrainRequest":
+        """
+        Get a new TrainRequest with default parameters for a MART Model.
+        """
+        return TrainRequest.from_dict(query_json("mart_defaults"))
+
+    @staticmethod
+    def ranknet() -> "TrainRequest":
+        """
+        Get a new TrainRequest with default parameters for a RankNet Model.
+        """
+        return TrainRequest.from_dict(query_json("ranknet_defaults"))
+
+    @staticmethod
+    def listnet() -> "TrainRequest":
+        """
+        Get a new TrainRequest with default parameters for a (linear) ListNet Model.
+        """
+        return TrainRequest.from_dict(query_json("listnet_defaults"))
+
+    @staticmethod
+    def ranksvm() -> "TrainRequest":
+        """
+        Get a new TrainRequest with default parameters for a RankSVM Model.
+        """
+        return TrainRequest.from_dict(query_json("ranksvm_defaults"))
+
+    @staticmethod
+    def adarank() -> "TrainRequest":
+        """
+        Get a new TrainRequest with default parameters for an AdaRank Model.
+        """
+        return TrainRequest.from_dict(query_json("adarank_defaults"))
+
+    @staticmethod
+    def rankboost() -> "TrainRequest":
+        """
+        Get a new TrainRequest with default parameters for a RankBoost Model.
+        """
+        return TrainRequest.from_dict(query_json("rankboost_defaults"))
+
     @staticmethod
     def from_dict(params) -> "TrainRequest":
         """
@@ -121,6 +375,9 @@ class TrainRequest:
         judgments = None
         if params["judgments"] is not None:
             judgments = CQRel.from_dict(params["judgments"])
+        validation = None
+        if params.get("validation") is not None:
+            validation = ValidationParams.from_dict(params["validation"])
         params_dict = params["params"]
         if len(params_dict) != 1:
             raise ValueError("What do I do with this?: {}".format(params_dict))
@@ -128,8 +385,20 @@ class TrainRequest:
             params = RandomForestParams.from_dict(params_dict["RandomForest"])
         elif "CoordinateAscent" in params_dict:
             params = CoordinateAscentParams.from_dict(params_dict["CoordinateAscent"])
+        elif "LambdaMART" in params_dict:
+            params = LambdaMARTParams.from_dict(params_dict["LambdaMART"])
+        elif "MART" in params_dict:
+            params = MARTParams.from_dict(params_dict["MART"])
+        elif "Neural" in params_dict:
+            params = NeuralParams.from_dict(params_dict["Neural"])
+        elif "RankSVM" in params_dict:
+            params = RankSVMParams.from_dict(params_dict["RankSVM"])
+        elif "AdaRank" in params_dict:
+            params = AdaRankParams.from_dict(params_dict["AdaRank"])
+        elif "RankBoost" in params_dict:
+            params = RankBoostParams.from_dict(params_dict["RankBoost"])
         else:
             raise ValueError(
                 "Python doesn't know about model-params: {}".format(params_dict)
             )
-        return TrainRequest(measure, params, judgments)
+        return TrainRequest(measure, params, judgments, validation)
@@ -0,0 +1,193 @@
+use crate::dataset::RankingDataset;
+use crate::evaluators::SetEvaluator;
+use crate::model::{ModelEnum, SingleFeatureModel, WeightedEnsemble};
+use crate::validation::{EarlyStopping, ValidationSet};
+use crate::Scored;
+use std::collections::HashMap;
+use std::error::Error;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdaRankParams {
+    pub quiet: bool,
+    pub num_rounds: u32,
+    /// Stop once the training measure changes by less than this.
+    pub tolerance: f64,
+    /// How many rounds in a row the same weak ranker may be picked.
+    pub max_consecutive_selections: u32,
+}
+
+impl Default for AdaRankParams {
+    fn default() -> Self {
+        Self {
+            quiet: false,
+            num_rounds: 100,
+            tolerance: 0.002,
+            max_consecutive_selections: 5,
+        }
+    }
+}
+
+/// Sum the measure of each query, weighted by its AdaRank distribution.
+fn weighted_performance(
+    per_query: &HashMap<String, f64>,
+    query_weights: &HashMap<String, f64>,
+) -> f64 {
+    query_weights
+        .iter()
+        .map(|(qid, w)| w * per_query.get(qid).cloned().unwrap_or(0.0))
+        .sum()
+}
+
+/// AdaRank (Xu & Li, 2007): boost single-feature rankers, re-weighting queries toward those the
+/// current ensemble handles poorly, as judged by the training measure itself.
+pub fn learn(
+    params: &AdaRankParams,
+    dataset: &dyn RankingDataset,
+    evaluator: &SetEvaluator,
+    validation: Option<&ValidationSet>,
+) -> Result<ModelEnum, Box<dyn Error>> {
+    let queries = dataset.queries();
+    let mut query_weights: HashMap<String, f64> = queries
+        .iter()
+        .map(|qid| (qid.clone(), 1.0 / (queries.len() as f64)))
+        .collect();
+
+    // Weak rankers never change, so score each of them once up front.
+    let mut fids = dataset.features();
+    fids.sort_unstable();
+    let weak_rankers: Vec<(SingleFeatureModel, HashMap<String, f64>)> = fids
+        .iter()
+        .flat_map(|fid| {
+            [1.0, -1.0].iter().map(move |dir| SingleFeatureModel {
+                fid: *fid,
+                dir: *dir,
+            })
+        })
+        .map(|m| {
+            let per_query = evaluator.evaluate_to_map(&m);
+            (m, per_query)
+        })
+        .collect();
+
+    let mut members: Vec<Scored<ModelEnum>> = Vec::new();
+    let mut last_choice: Option<usize> = None;
+    let mut consecutive = 0;
+    let mut previous_score = 0.0;
+    // Like RankLib, keep the prefix of the ensemble that scored best on the training data.
+    let mut best_prefix = Scored::new(0.0, 0);
+    let mut stopping = EarlyStopping::new(validation);
+
+    if !params.quiet {
+        println!("----------------------------------------");
+        println!(
+            "|{:>7}|{:<16}|{:>6}|{:>7}|",
+            "Round",
+            "Feature",
+            "Dir",
+            evaluator.name()
+        );
+        println!("----------------------------------------");
+    }
+
+    for round in 0..params.num_rounds {
+        let candidates = weak_rankers
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                last_choice != Some(*i) || consecutive < params.max_consecutive_selections
+            })
+            .map(|(i, (_, per_query))| {
+                Scored::try_new(weighted_performance(per_query, &query_weights), i)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let best = candidates.into_iter().max();
+        let best = match best {
+            Some(b) => b.item,
+            None => break,
+        };
+        if last_choice == Some(best) {
+            consecutive += 1;
+        } else {
+            last_choice = Some(best);
+            consecutive = 1;
+        }
+
+        let (model, per_query) = &weak_rankers[best];
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        for (qid, w) in query_weights.iter() {
+            let perf = per_query.get(qid).cloned().unwrap_or(0.0);
+            numerator += w * (1.0 + perf);
+            denominator += w * (1.0 - perf);
+        }
+        if denominator <= 0.0 {
+            // This feature ranks every query perfectly; nothing left to boost.
+            members.push(Scored::new(1.0, ModelEnum::SingleFeature(*model)));
+            best_prefix = Scored::new(1.0, members.len());
+            break;
+        }
+        let alpha = 0.5 * (numerator / denominator).ln();
+        members.push(Scored::try_new(alpha, ModelEnum::SingleFeature(*model))?);
+
+        let ensemble = WeightedEnsemble::new(members.clone());
+        let ensemble_per_query = evaluator.evaluate_to_map(&ensemble);
+        let mut total = 0.0;
+        for (qid, w) in query_weights.iter_mut() {
+            *w = (-ensemble_per_query.get(qid).cloned().unwrap_or(0.0)).exp();
+            total += *w;
+        }
+        for w in query_weights.values_mut() {
+            *w /= total;
+        }
+
+        let score = ensemble_per_query.values().sum::<f64>() / (ensemble_per_query.len() as f64);
+        if !params.quiet {
+            println!(
+                "|{:>7}|{:<16}|{:>6}|{:>7.3}|",
+                round + 1,
+                dataset.feature_name(model.fid),
+                model.dir,
+                score
+            );
+        }
+        best_prefix.replace_if_better(score, members.len());
+        if stopping.observe(members.len(), || ensemble.clone()) {
+            break;
+        }
+        if (score - previous_score).abs() < params.tolerance {
+            break;
+        }
+        previous_score = score;
+    }
+
+    if !params.quiet {
+        println!("----------------------------------------");
+        stopping.print_summary();
+    }
+
+    // Held-out queries, when we have them, overrule the training measure.
+    members.truncate(stopping.best_size().unwrap_or(best_prefix.item));
+    Ok(ModelEnum::Ensemble(WeightedEnsemble::new(members)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+
+    #[test]
+    fn test_adarank_beats_single_feature() {
+        let train_dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let eval = SetEvaluator::create(&train_dataset, "ndcg@5", None).unwrap();
+        let params = AdaRankParams {
+            quiet: true,
+            ..AdaRankParams::default()
+        };
+        let model = learn(&params, &train_dataset, &eval, None).unwrap();
+        // para-fraction alone gets 0.439 NDCG@5 on this data.
+        assert!(eval.evaluate_mean(&model) >= 0.439);
+    }
+}
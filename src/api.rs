@@ -0,0 +1,160 @@
+use crate::dataset;
+use crate::evaluators::SetEvaluator;
+use crate::io_helper;
+use crate::json_api;
+use crate::qrel;
+use std::error::Error;
+use std::io::Write;
+
+pub use crate::dataset::{DatasetRef, RankingDataset};
+pub use crate::error::FastRankError;
+pub use crate::instance::FeatureRead;
+pub use crate::json_api::{FastRankModelParams, TrainRequest};
+pub use crate::model::{Model, ModelEnum};
+pub use crate::qrel::QuerySetJudgments;
+pub use crate::{FeatureId, InstanceId};
+
+/// Load a RankLib/libsvm file, optionally naming its features from a JSON file of number to name.
+pub fn load_libsvm(
+    path: &str,
+    feature_names_path: Option<&str>,
+) -> Result<DatasetRef, Box<dyn Error>> {
+    let feature_names = match feature_names_path {
+        Some(path) => Some(dataset::load_feature_names_json(path)?),
+        None => None,
+    };
+    DatasetRef::load_libsvm(path, feature_names.as_ref())
+}
+
+/// Load TREC-style relevance judgments.
+pub fn load_qrel(path: &str) -> Result<QuerySetJudgments, Box<dyn Error>> {
+    qrel::read_file(path)
+}
+
+/// Load a model saved by `save_model`, the CLI, or Python's `CModel.to_dict`.
+pub fn load_model(path: &str) -> Result<ModelEnum, Box<dyn Error>> {
+    Ok(serde_json::from_reader(io_helper::open_reader(path)?)?)
+}
+
+pub fn model_from_json(json: &str) -> Result<ModelEnum, Box<dyn Error>> {
+    Ok(serde_json::from_str(json)?)
+}
+
+pub fn save_model(model: &ModelEnum, path: &str) -> Result<(), Box<dyn Error>> {
+    let mut out = io_helper::open_writer(path)?;
+    serde_json::to_writer(&mut out, model)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Train a model; validation queries, if any, are used for early stopping as the request asks.
+pub fn train(
+    request: TrainRequest,
+    dataset: &DatasetRef,
+    validation: Option<&DatasetRef>,
+) -> Result<ModelEnum, Box<dyn Error>> {
+    json_api::do_training(request, dataset, validation)
+}
+
+/// Dense feature vector, indexed by feature number.
+struct DenseRow<'a>(&'a [f64]);
+
+impl FeatureRead for DenseRow<'_> {
+    fn get(&self, idx: FeatureId) -> Option<f64> {
+        self.0.get(idx.to_index()).cloned()
+    }
+    fn dotp(&self, weights: &[f64]) -> f64 {
+        self.0.iter().zip(weights.iter()).map(|(x, w)| x * w).sum()
+    }
+}
+
+/// Score one document given its features by number; features past the end are missing.
+/// Every feature must be finite.
+pub fn score(model: &ModelEnum, features: &[f64]) -> Result<f64, FastRankError> {
+    if let Some(i) = features.iter().position(|x| x.is_nan()) {
+        return Err(FastRankError::NaN(format!("features[{}]", i)));
+    }
+    if let Some(i) = features.iter().position(|x| x.is_infinite()) {
+        return Err(FastRankError::InvalidParameter(format!(
+            "features[{}] is infinite",
+            i
+        )));
+    }
+    Ok(model.score(&DenseRow(features)).into_inner())
+}
+
+/// Score every instance, in the order of `dataset.instances()`.
+pub fn score_dataset(model: &ModelEnum, dataset: &dyn RankingDataset) -> Vec<f64> {
+    dataset
+        .instances()
+        .into_iter()
+        .map(|id| dataset.score(id, model).into_inner())
+        .collect()
+}
+
+/// Mean of a measure such as "ndcg@5" or "map" over the dataset's queries.
+pub fn evaluate(
+    model: &ModelEnum,
+    dataset: &DatasetRef,
+    measure: &str,
+    judgments: Option<QuerySetJudgments>,
+) -> Result<f64, Box<dyn Error>> {
+    let evaluator = SetEvaluator::create(dataset, measure, judgments)?;
+    Ok(evaluator.evaluate_mean(model))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coordinate_ascent::CoordinateAscentParams;
+
+    #[test]
+    fn test_facade_round_trip() {
+        let dataset = load_libsvm(
+            "examples/trec_news_2018.train",
+            Some("examples/trec_news_2018.features.json"),
+        )
+        .unwrap();
+        let request = TrainRequest {
+            measure: "ndcg@5".to_owned(),
+            params: FastRankModelParams::CoordinateAscent(CoordinateAscentParams {
+                num_restarts: 1,
+                num_max_iterations: 5,
+                seed: 42,
+                quiet: true,
+                ..CoordinateAscentParams::default()
+            }),
+            ..TrainRequest::default()
+        };
+        let model = train(request, &dataset, None).unwrap();
+        let loaded = model_from_json(&serde_json::to_string(&model).unwrap()).unwrap();
+        assert_eq!(
+            score_dataset(&model, &dataset),
+            score_dataset(&loaded, &dataset)
+        );
+
+        let id = dataset.instances()[0];
+        let row: Vec<f64> = (0..dataset.n_dim())
+            .map(|i| {
+                dataset
+                    .get_feature_value(id, FeatureId::from_index(i as usize))
+                    .unwrap_or(0.0)
+            })
+            .collect();
+        assert_eq!(
+            score_dataset(&model, &dataset)[0],
+            score(&loaded, &row).unwrap()
+        );
+        let mut bad = row.clone();
+        bad[0] = f64::NAN;
+        assert_eq!("nan", score(&loaded, &bad).unwrap_err().code());
+        bad[0] = f64::INFINITY;
+        assert_eq!(
+            "invalid_parameter",
+            score(&loaded, &bad).unwrap_err().code()
+        );
+
+        let ndcg5 = evaluate(&loaded, &dataset, "ndcg@5", None).unwrap();
+        assert!(ndcg5 > 0.0 && ndcg5 <= 1.0);
+    }
+}
@@ -0,0 +1,9 @@
+//! `best_single_feature` is kept for old scripts; it is `fastrank train --model best_single_feature`.
+#[path = "../fastrank/cli.rs"]
+mod cli;
+
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    cli::run(&["train", "--model", "best_single_feature"])
+}
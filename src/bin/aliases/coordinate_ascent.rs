@@ -0,0 +1,9 @@
+//! `coordinate_ascent` is kept for old scripts; it is `fastrank train --model coordinate_ascent`.
+#[path = "../fastrank/cli.rs"]
+mod cli;
+
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    cli::run(&["train", "--model", "coordinate_ascent"])
+}
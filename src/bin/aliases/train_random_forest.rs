@@ -0,0 +1,9 @@
+//! `train_random_forest` is kept for old scripts; it is `fastrank train --model random_forest`.
+#[path = "../fastrank/cli.rs"]
+mod cli;
+
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    cli::run(&["train", "--model", "random_forest"])
+}
@@ -0,0 +1,366 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use fastrank::api;
+use fastrank::dataset::{self, DatasetRef, LoadedRankingDataset, RankingDataset};
+use fastrank::error::FastRankError;
+use fastrank::evaluators::SetEvaluator;
+use fastrank::importance;
+use fastrank::io_helper;
+use fastrank::json_api::{self, FastRankModelParams, TrainRequest};
+use fastrank::model::{ModelEnum, SingleFeatureModel};
+use fastrank::qrel;
+use fastrank::tuning;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+
+const LEARNERS: &[&str] = &[
+    "coordinate_ascent",
+    "random_forest",
+    "lambdamart",
+    "mart",
+    "ranknet",
+    "listnet",
+    "ranksvm",
+    "adarank",
+    "rankboost",
+    "best_single_feature",
+];
+
+/// Parse the command line and run it; `preset` goes in front of the user's arguments,
+/// so the old single-purpose binaries can stand in for `fastrank train --model ...`.
+pub fn run(preset: &[&str]) -> Result<(), Box<dyn Error>> {
+    let mut argv: Vec<OsString> = env::args_os().collect();
+    argv.splice(1..1, preset.iter().map(OsString::from));
+    let feature_names = Arg::with_name("feature_names")
+        .long("feature_names")
+        .takes_value(true)
+        .help("JSON file mapping feature numbers to names.");
+    let matches = App::new("fastrank")
+        .about("Train, evaluate and apply learning-to-rank models on RankLib/libsvm files.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("train")
+                .about("Train a model, then report its performance on the train and test data.")
+                .arg(Arg::with_name("TRAIN").required(true))
+                .arg(
+                    Arg::with_name("model")
+                        .long("model")
+                        .takes_value(true)
+                        .possible_values(LEARNERS)
+                        .default_value("coordinate_ascent"),
+                )
+                .arg(Arg::with_name("test").long("test").takes_value(true))
+                .arg(feature_names.clone())
+                .arg(
+                    Arg::with_name("metric2t")
+                        .long("metric2t")
+                        .takes_value(true)
+                        .default_value("ndcg")
+                        .help("Measure to optimize on the training data."),
+                )
+                .arg(
+                    Arg::with_name("ignore")
+                        .short("i")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Feature name or number to leave out; may be repeated."),
+                )
+                .arg(
+                    Arg::with_name("params")
+                        .long("params")
+                        .takes_value(true)
+                        .help(
+                            "JSON file of learner params to override, e.g., {\"num_trees\": 50}.",
+                        ),
+                )
+                .arg(Arg::with_name("seed").long("seed").takes_value(true))
+                .arg(Arg::with_name("normalize_weights").long("normalize_weights"))
+                .arg(Arg::with_name("srate").long("srate").takes_value(true))
+                .arg(Arg::with_name("frate").long("frate").takes_value(true))
+                .arg(
+                    Arg::with_name("split_candidates")
+                        .long("split_candidates")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max_depth")
+                        .long("max_depth")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("num_trees")
+                        .long("num_trees")
+                        .takes_value(true),
+                )
+                .arg(Arg::with_name("quiet").long("quiet"))
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .takes_value(true)
+                        .help("Where to save the model as JSON."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("eval")
+                .about("Report a saved model's performance on a dataset.")
+                .arg(Arg::with_name("MODEL").required(true))
+                .arg(Arg::with_name("DATA").required(true))
+                .arg(feature_names.clone())
+                .arg(
+                    Arg::with_name("metric")
+                        .long("metric")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Only print these measures, without percentiles."),
+                )
+                .arg(
+                    Arg::with_name("qrel")
+                        .long("qrel")
+                        .takes_value(true)
+                        .help("TREC qrel file to judge with instead of the dataset labels."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("predict")
+                .about("Score a dataset with a saved model and write a trecrun file.")
+                .arg(Arg::with_name("MODEL").required(true))
+                .arg(Arg::with_name("DATA").required(true))
+                .arg(feature_names.clone())
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("system_name")
+                        .long("system_name")
+                        .takes_value(true)
+                        .default_value("fastrank"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Documents to keep per query; 0 keeps them all."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Describe a saved model and which features it relies on.")
+                .arg(Arg::with_name("MODEL").required(true))
+                .arg(feature_names),
+        )
+        .get_matches_from(argv);
+
+    match matches.subcommand() {
+        ("train", Some(args)) => train(args),
+        ("eval", Some(args)) => eval(args),
+        ("predict", Some(args)) => predict(args),
+        ("inspect", Some(args)) => inspect(args),
+        _ => unreachable!("clap requires a subcommand"),
+    }
+}
+
+fn load_dataset(path: &str, args: &ArgMatches) -> Result<LoadedRankingDataset, Box<dyn Error>> {
+    let feature_names = match args.value_of("feature_names") {
+        Some(path) => Some(dataset::load_feature_names_json(path)?),
+        None => None,
+    };
+    LoadedRankingDataset::load_libsvm(path, feature_names.as_ref())
+}
+
+/// Collect learner params from --params and the individual flags; flags win.
+fn settings(args: &ArgMatches) -> Result<BTreeMap<String, Value>, Box<dyn Error>> {
+    let mut settings: BTreeMap<String, Value> = match args.value_of("params") {
+        Some(path) => serde_json::from_reader(io_helper::open_reader(path)?)?,
+        None => BTreeMap::new(),
+    };
+    let numeric = &[
+        ("seed", "seed"),
+        ("srate", "instance_sampling_rate"),
+        ("frate", "feature_sampling_rate"),
+        ("split_candidates", "split_candidates"),
+        ("max_depth", "max_depth"),
+        ("num_trees", "num_trees"),
+    ];
+    for (flag, field) in numeric.iter() {
+        if let Some(text) = args.value_of(flag) {
+            let value: Value = serde_json::from_str(text)
+                .map_err(|_| format!("--{} should be a number, found {}", flag, text))?;
+            if !value.is_number() {
+                Err(format!("--{} should be a number, found {}", flag, text))?;
+            }
+            settings.insert(field.to_string(), value);
+        }
+    }
+    if args.is_present("normalize_weights") {
+        settings.insert("normalize".to_owned(), Value::Bool(true));
+    }
+    if args.is_present("quiet") {
+        settings.insert("quiet".to_owned(), Value::Bool(true));
+    }
+    Ok(settings)
+}
+
+/// Try every feature in both directions and keep the one that ranks best on its own.
+fn best_single_feature(
+    dataset: &DatasetRef,
+    evaluator: &SetEvaluator,
+    quiet: bool,
+) -> Result<ModelEnum, FastRankError> {
+    let mut best: Option<(f64, SingleFeatureModel)> = None;
+    for fid in dataset.features() {
+        for dir in &[1.0, -1.0] {
+            let model = SingleFeatureModel { fid, dir: *dir };
+            let score = evaluator.evaluate_mean(&model);
+            if !quiet {
+                println!("{}\t{}\t{:.3}", dataset.feature_name(fid), dir, score);
+            }
+            match best {
+                Some((best_score, _)) if best_score >= score => {}
+                _ => best = Some((score, model)),
+            }
+        }
+    }
+    match best {
+        Some((_, model)) => Ok(ModelEnum::SingleFeature(model)),
+        None => Err(FastRankError::EmptyDataset(
+            "No features left to choose from.".to_owned(),
+        )),
+    }
+}
+
+fn train(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let mut train = load_dataset(args.value_of("TRAIN").unwrap(), args)?;
+    let mut test = match args.value_of("test") {
+        Some(path) => Some(load_dataset(path, args)?),
+        None => None,
+    };
+    for name in args.values_of("ignore").into_iter().flatten() {
+        train.try_remove_feature(name)?;
+        if let Some(test) = test.as_mut() {
+            test.try_remove_feature(name)?;
+        }
+    }
+    let train = train.into_ref();
+    let test = test.map(|t| t.into_ref());
+    let measure = args.value_of("metric2t").unwrap();
+    let settings = settings(args)?;
+
+    let model = match args.value_of("model").unwrap() {
+        "best_single_feature" => {
+            if let Some(name) = settings.keys().find(|name| name.as_str() != "quiet") {
+                Err(format!(
+                    "best_single_feature takes no parameters, found {}",
+                    name
+                ))?;
+            }
+            let evaluator = SetEvaluator::create(&train, measure, None)?;
+            best_single_feature(&train, &evaluator, args.is_present("quiet"))?
+        }
+        name => {
+            let params = FastRankModelParams::defaults(name)
+                .ok_or_else(|| format!("Unknown model: {}", name))?;
+            let request = TrainRequest {
+                measure: measure.to_owned(),
+                params: tuning::override_params(&params, &settings)?,
+                ..TrainRequest::default()
+            };
+            json_api::do_training(request, &train, None)?
+        }
+    };
+
+    if let Some(path) = args.value_of("output") {
+        api::save_model(&model, path)?;
+    }
+    SetEvaluator::print_standard_eval("Train", &model, &train, &None);
+    if let Some(test) = test.as_ref() {
+        SetEvaluator::print_standard_eval("Test", &model, test, &None);
+    }
+    Ok(())
+}
+
+fn eval(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let model = api::load_model(args.value_of("MODEL").unwrap())?;
+    let data = load_dataset(args.value_of("DATA").unwrap(), args)?.into_ref();
+    let judgments = match args.value_of("qrel") {
+        Some(path) => Some(qrel::read_file(path)?),
+        None => None,
+    };
+    match args.values_of("metric") {
+        Some(measures) => {
+            for measure in measures {
+                let evaluator = SetEvaluator::create(&data, measure, judgments.clone())?;
+                println!(
+                    "{}\t{:.4}",
+                    evaluator.name(),
+                    evaluator.evaluate_mean(&model)
+                );
+            }
+        }
+        None => SetEvaluator::print_standard_eval("Eval", &model, &data, &judgments),
+    }
+    Ok(())
+}
+
+fn predict(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let model = api::load_model(args.value_of("MODEL").unwrap())?;
+    let data = load_dataset(args.value_of("DATA").unwrap(), args)?.into_ref();
+    let depth: usize = args
+        .value_of("depth")
+        .unwrap()
+        .parse()
+        .map_err(|_| "--depth should be a whole number")?;
+    let written = json_api::predict_to_trecrun(
+        &model,
+        &data,
+        args.value_of("output").unwrap(),
+        args.value_of("system_name").unwrap(),
+        depth,
+    )?;
+    println!("Wrote {} records.", written);
+    Ok(())
+}
+
+fn inspect(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let model = api::load_model(args.value_of("MODEL").unwrap())?;
+    match &model {
+        ModelEnum::SingleFeature(_) => println!("Single feature model"),
+        ModelEnum::Linear(m) => println!("Linear model over {} dimensions", m.weights.len()),
+        ModelEnum::DecisionTree(_) => println!("Decision tree"),
+        ModelEnum::Ensemble(e) => println!("Ensemble of {} models", e.len()),
+        ModelEnum::MLP(_) => println!("Neural network (MLP)"),
+    }
+    let names: HashMap<_, String> = match args.value_of("feature_names") {
+        Some(path) => dataset::load_feature_names_json(path)?,
+        None => HashMap::new(),
+    };
+    let report = match importance::feature_importance(&model) {
+        Ok(report) => report,
+        // Not every model can say which features it relies on; the summary above still stands.
+        Err(e) => {
+            println!("{}", e);
+            return Ok(());
+        }
+    };
+    let mut report: Vec<_> = report.into_iter().collect();
+    report.sort_by(|(_, a), (_, b)| b.importance.total_cmp(&a.importance));
+    println!("feature\timportance\tsplits\tgain");
+    for (fid, fi) in report {
+        let name = names
+            .get(&fid)
+            .cloned()
+            .unwrap_or_else(|| format!("{}", fid.to_index()));
+        println!(
+            "{}\t{:.4}\t{}\t{:.4}",
+            name, fi.importance, fi.splits, fi.gain
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,7 @@
+mod cli;
+
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    cli::run(&[])
+}
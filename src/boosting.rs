@@ -0,0 +1,169 @@
+use crate::dataset::{DatasetRef, RankingDataset, RelabeledDatasetRef};
+use crate::error::FastRankError;
+use crate::evaluators::SetEvaluator;
+use crate::model::{ModelEnum, TreeNode, WeightedEnsemble};
+use crate::monotone::{self, MonotoneConstraints};
+use crate::random_forest::{
+    self, MissingValues, RandomForestParams, SplitFinding, SplitSelectionStrategy,
+};
+use crate::sampling::DatasetSampling;
+use crate::validation::{EarlyStopping, ValidationSet};
+use crate::InstanceId;
+use crate::Scored;
+use oorandom::Rand64;
+use ordered_float::NotNan;
+use std::error::Error;
+
+/// How LambdaMART and MART grow and combine trees; they differ only in what the trees fit.
+#[derive(Clone, Debug)]
+pub struct BoostingParams {
+    pub seed: u64,
+    pub quiet: bool,
+    pub num_trees: u32,
+    pub learning_rate: f64,
+    pub instance_sampling_rate: f64,
+    pub feature_sampling_rate: f64,
+    pub min_leaf_support: u32,
+    pub split_candidates: u32,
+    pub split_finding: SplitFinding,
+    pub missing_values: MissingValues,
+    pub monotone_constraints: MonotoneConstraints,
+    pub max_depth: u32,
+}
+
+impl BoostingParams {
+    /// Each boosting round grows a single regression tree on the objective's targets.
+    fn tree_params(&self) -> RandomForestParams {
+        RandomForestParams {
+            seed: self.seed,
+            quiet: true,
+            num_trees: 1,
+            split_method: SplitSelectionStrategy::SquaredError(),
+            min_leaf_support: self.min_leaf_support,
+            split_candidates: self.split_candidates,
+            split_finding: self.split_finding.clone(),
+            missing_values: self.missing_values.clone(),
+            monotone_constraints: self.monotone_constraints.clone(),
+            max_depth: self.max_depth,
+            ..RandomForestParams::default()
+        }
+    }
+}
+
+/// What each boosting round fits: the gradients, and optionally how to set leaf outputs.
+pub trait BoostingObjective {
+    /// A constant every score starts from, kept as the ensemble's first member; zero if None.
+    fn initial_score(&self) -> Option<f64> {
+        None
+    }
+    /// The next tree's target for every instance, given current scores; both indexed by InstanceId.
+    fn targets(&mut self, scores: &[f64]) -> Result<Vec<f64>, FastRankError>;
+    /// Replace the leaf outputs of a tree grown on `ids`, e.g., with Newton steps.
+    /// Returns false to keep the mean target in each leaf.
+    fn refit_leaves(
+        &self,
+        _tree: &mut TreeNode,
+        _ids: &[InstanceId],
+    ) -> Result<bool, FastRankError> {
+        Ok(false)
+    }
+}
+
+/// Gradient boosting: each round fits a regression tree to the objective's targets and adds it,
+/// shrunk by the learning rate, stopping early and keeping the best prefix on validation queries.
+pub fn learn_ensemble(
+    params: &BoostingParams,
+    objective: &mut dyn BoostingObjective,
+    dataset: &DatasetRef,
+    evaluator: &SetEvaluator,
+    validation: Option<&ValidationSet>,
+) -> Result<WeightedEnsemble, Box<dyn Error>> {
+    if !params.learning_rate.is_finite() {
+        Err(FastRankError::InvalidParameter(format!(
+            "learning_rate must be finite: {}",
+            params.learning_rate
+        )))?;
+    }
+    let mut rand = Rand64::new(params.seed.into());
+    let tree_params = params.tree_params();
+    // Relabeling never changes feature values, so one index serves every round.
+    let index = random_forest::build_index(&tree_params, dataset)?;
+    let mut stopping = EarlyStopping::new(validation);
+
+    let instances = dataset.instances();
+    let n = instances
+        .iter()
+        .max()
+        .map(|id| id.to_index() + 1)
+        .unwrap_or(0);
+    let mut trees: Vec<Scored<ModelEnum>> = Vec::new();
+    let mut scores = match objective.initial_score() {
+        Some(initial) => {
+            let leaf = NotNan::new(initial)
+                .map_err(|_| FastRankError::NaN("initial score of the ensemble".to_owned()))?;
+            trees.push(Scored::new(
+                1.0,
+                ModelEnum::DecisionTree(TreeNode::LeafNode(leaf)),
+            ));
+            vec![initial; n]
+        }
+        None => vec![0.0; n],
+    };
+
+    if !params.quiet {
+        println!("-----------------------");
+        println!("|{:>7}|{:>7}|{:>7}|", "Tree", "Depth", evaluator.name());
+        println!("-----------------------");
+    }
+
+    for idx in 0..params.num_trees {
+        let targets = objective.targets(&scores)?;
+        let relabeled = RelabeledDatasetRef::new(dataset, &targets)?.into_ref();
+        let subsample = relabeled
+            .random_sample(
+                params.feature_sampling_rate,
+                params.instance_sampling_rate,
+                &mut rand,
+            )
+            .into_ref();
+        let mut tree =
+            random_forest::learn_decision_tree_indexed(&tree_params, &subsample, &index, &mut rand);
+        if objective.refit_leaves(&mut tree, &subsample.instances())? {
+            // New leaf outputs replace the clamped ones, so clamp again.
+            monotone::clamp_leaves(&mut tree, index.monotone());
+        }
+
+        for index in instances.iter().cloned() {
+            scores[index.to_index()] +=
+                params.learning_rate * dataset.score(index, &tree).into_inner();
+        }
+        let depth = tree.depth();
+        trees.push(Scored::try_new(
+            params.learning_rate,
+            ModelEnum::DecisionTree(tree),
+        )?);
+
+        if !params.quiet {
+            let model = WeightedEnsemble::new(trees.clone());
+            println!(
+                "|{:>7}|{:>7}|{:>7.3}|",
+                idx + 1,
+                depth,
+                evaluator.evaluate_mean(&model)
+            );
+        }
+        if stopping.observe(trees.len(), || WeightedEnsemble::new(trees.clone())) {
+            break;
+        }
+    }
+
+    if !params.quiet {
+        println!("-----------------------");
+        stopping.print_summary();
+    }
+
+    if let Some(size) = stopping.best_size() {
+        trees.truncate(size);
+    }
+    Ok(WeightedEnsemble::new(trees))
+}
@@ -1,12 +1,17 @@
 use crate::dataset::RankingDataset;
+use crate::error::FastRankError;
 use crate::evaluators::SetEvaluator;
 use crate::model::{DenseLinearRankingModel, ModelEnum, WeightedEnsemble};
+use crate::monotone::{self, MonotoneConstraints, Monotonicity};
 use crate::randutil::shuffle;
+use crate::validation::{EarlyStopping, ValidationSet};
 use crate::FeatureId;
 use crate::Scored;
 use oorandom::Rand64;
 use ordered_float::NotNan;
 use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CoordinateAscentParams {
@@ -20,6 +25,9 @@ pub struct CoordinateAscentParams {
     pub quiet: bool,
     pub init_random: bool,
     pub output_ensemble: bool,
+    /// Feature name or number to the sign its weight must keep.
+    #[serde(default)]
+    pub monotone_constraints: MonotoneConstraints,
 }
 
 impl Default for CoordinateAscentParams {
@@ -36,6 +44,7 @@ impl Default for CoordinateAscentParams {
             quiet: false,
             init_random: true,
             output_ensemble: false,
+            monotone_constraints: MonotoneConstraints::new(),
         }
     }
 }
@@ -69,6 +78,13 @@ impl DenseLinearRankingModel {
         assert_eq!(n_dim, self.weights.len());
     }
 
+    fn clamp_signs(&mut self, monotone: &HashMap<FeatureId, Monotonicity>) {
+        for (fid, direction) in monotone.iter() {
+            let w = &mut self.weights[fid.to_index()];
+            *w = direction.clamp_weight(*w);
+        }
+    }
+
     fn l1_normalize(&mut self) {
         let mut sum = 0.0;
         for w in self.weights.iter() {
@@ -90,25 +106,27 @@ fn optimize_inner(
     evaluator: &SetEvaluator,
     mut rand: Rand64,
     params: &CoordinateAscentParams,
-) -> Scored<DenseLinearRankingModel> {
+    tolerance: NotNan<f64>,
+    monotone: &HashMap<FeatureId, Monotonicity>,
+) -> Result<Scored<DenseLinearRankingModel>, FastRankError> {
     let quiet = params.quiet;
-    let tolerance = NotNan::new(params.tolerance).unwrap();
 
     let fids: Vec<FeatureId> = data.features().clone();
-    let model_dim = (fids
-        .iter()
-        .max()
-        .expect("Should be at least one feature!")
-        .to_index() as u32)
-        + 1;
+    let model_dim = match fids.iter().max() {
+        Some(fid) => fid.to_index() as u32 + 1,
+        None => Err(FastRankError::EmptyDataset(
+            "Coordinate ascent needs at least one feature.".to_owned(),
+        ))?,
+    };
 
     // Initialize to even weights:
     let mut model = DenseLinearRankingModel::new(model_dim);
     model.reset(params.init_random, &mut rand, &data.features());
+    model.clamp_signs(monotone);
 
     // Initialize this local best (within current restart cycle):
     let start_score = evaluator.evaluate_mean(&model);
-    let mut current_best = Scored::new(start_score, model.clone());
+    let mut current_best = Scored::try_new(start_score, model.clone())?;
 
     loop {
         let mut fids = fids.clone();
@@ -155,17 +173,18 @@ fn optimize_inner(
                     }
 
                     for _ in 0..num_iter {
-                        let w = orig_weight + total_step;
+                        let mut w = orig_weight + total_step;
+                        if let Some(direction) = monotone.get(current_feature) {
+                            w = direction.clamp_weight(w);
+                        }
                         model.weights[current_feature.to_index()] = w;
                         let score = evaluator.evaluate_mean(&model);
 
-                        if current_best.replace_if_better(score, model.clone()) {
-                            if !quiet {
-                                println!(
-                                    "{:4}|{:<16}|{:>9.3}|{:>9.3}",
-                                    restart_id, current_feature_name, w, score
-                                );
-                            }
+                        if current_best.replace_if_better(score, model.clone()) && !quiet {
+                            println!(
+                                "{:4}|{:<16}|{:>9.3}|{:>9.3}",
+                                restart_id, current_feature_name, w, score
+                            );
                         }
 
                         step *= params.step_scale;
@@ -193,16 +212,27 @@ fn optimize_inner(
         }
     } // optimize-loop
 
-    current_best
+    Ok(current_best)
 }
 
 impl CoordinateAscentParams {
-    pub fn learn(&self, data: &dyn RankingDataset, evaluator: &SetEvaluator) -> ModelEnum {
+    pub fn learn(
+        &self,
+        data: &dyn RankingDataset,
+        evaluator: &SetEvaluator,
+        validation: Option<&ValidationSet>,
+    ) -> Result<ModelEnum, Box<dyn Error>> {
         let mut rand = Rand64::new(self.seed.into());
 
-        assert!(data.n_dim() > 0);
-        assert!(data.instances().len() > 0);
-        assert!(data.queries().len() > 0);
+        let tolerance = NotNan::new(self.tolerance).map_err(|_| {
+            FastRankError::InvalidParameter("tolerance must be a number, not NaN".to_owned())
+        })?;
+        if data.n_dim() == 0 || data.instances().is_empty() || data.queries().is_empty() {
+            Err(FastRankError::EmptyDataset(
+                "Coordinate ascent needs features, instances and queries.".to_owned(),
+            ))?;
+        }
+        let monotone = monotone::resolve(&self.monotone_constraints, data)?;
 
         if !self.quiet {
             println!("---------------------------");
@@ -214,43 +244,73 @@ impl CoordinateAscentParams {
             .map(|restart_id| (restart_id, Rand64::new(rand.rand_u64().into())))
             .collect();
 
-        let mut history: Vec<Scored<DenseLinearRankingModel>> = Vec::new();
-        history.par_extend(states.into_par_iter().map(|(restart_id, rand)| {
-            if !self.quiet {
-                println!(
-                    "[+] Random restart #{}/{}...",
-                    restart_id + 1,
-                    self.num_restarts
-                );
-            }
-            optimize_inner(restart_id, data, evaluator, rand, self)
-        }));
+        let history: Vec<Scored<DenseLinearRankingModel>> = states
+            .into_par_iter()
+            .map(|(restart_id, rand)| {
+                if !self.quiet {
+                    println!(
+                        "[+] Random restart #{}/{}...",
+                        restart_id + 1,
+                        self.num_restarts
+                    );
+                }
+                optimize_inner(
+                    restart_id, data, evaluator, rand, self, tolerance, &monotone,
+                )
+            })
+            .collect::<Result<_, _>>()?;
 
         if !self.quiet {
             println!("---------------------------");
             println!("Finished successfully.");
         }
 
+        // Restarts are independent, so held-out queries only need to rank them, best first.
+        let history: Vec<Scored<DenseLinearRankingModel>> = match validation {
+            Some(validation) => {
+                let mut ranked = history
+                    .into_iter()
+                    .map(|sm| Scored::try_new(validation.evaluator.evaluate_mean(&sm.item), sm))
+                    .collect::<Result<Vec<_>, _>>()?;
+                ranked.sort_by(|a, b| b.cmp(a));
+                ranked.into_iter().map(|ranked| ranked.item).collect()
+            }
+            None => history,
+        };
+
         if self.output_ensemble && history.len() > 1 {
-            let members: Vec<Scored<ModelEnum>> = history
+            let mut members: Vec<Scored<ModelEnum>> = history
                 .iter()
                 .map(|sm| {
                     let mut model = sm.item.clone();
                     model.l1_normalize();
-                    let m = ModelEnum::Linear(model);
-                    Scored::new(sm.score.into_inner(), m)
+                    Scored {
+                        score: sm.score,
+                        item: ModelEnum::Linear(model),
+                    }
                 })
                 .collect();
-            ModelEnum::Ensemble(WeightedEnsemble::new(members))
+            // Keep however many of the best restarts do best together on the held-out queries.
+            let mut stopping = EarlyStopping::new(validation);
+            for size in 1..=members.len() {
+                if stopping.observe(size, || WeightedEnsemble::new(members[..size].to_vec())) {
+                    break;
+                }
+            }
+            if let Some(size) = stopping.best_size() {
+                members.truncate(size);
+            }
+            Ok(ModelEnum::Ensemble(WeightedEnsemble::new(members)))
         } else {
-            ModelEnum::Linear(
-                history
-                    .iter()
-                    .max()
-                    .expect("Should be at least 1 restart!")
-                    .item
-                    .clone(),
-            )
+            // Ranked best first on held-out queries, if any; otherwise by the training measure.
+            let best = match validation {
+                Some(_) => history.into_iter().next(),
+                None => history.into_iter().max(),
+            };
+            let best = best.ok_or_else(|| {
+                FastRankError::InvalidParameter("num_restarts must be at least 1".to_owned())
+            })?;
+            Ok(ModelEnum::Linear(best.item))
         }
     } // learn
 } // impl
@@ -1,3 +1,4 @@
+use crate::error::FastRankError;
 use ordered_float::NotNan;
 use std::cmp::Ordering;
 
@@ -9,7 +10,7 @@ impl FeatureId {
     pub fn from_index(idx: usize) -> Self {
         Self(idx as u32)
     }
-    pub fn to_index(&self) -> usize {
+    pub fn to_index(self) -> usize {
         self.0 as usize
     }
 }
@@ -21,7 +22,7 @@ impl InstanceId {
     pub fn from_index(idx: usize) -> Self {
         Self(idx as u32)
     }
-    pub fn to_index(&self) -> usize {
+    pub fn to_index(self) -> usize {
         self.0 as usize
     }
 }
@@ -39,7 +40,7 @@ impl<T: Clone> PartialEq for Scored<T> {
 }
 impl<T: Clone> PartialOrd for Scored<T> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.score.cmp(&other.score))
+        Some(self.cmp(other))
     }
 }
 impl<T: Clone> Ord for Scored<T> {
@@ -48,11 +49,16 @@ impl<T: Clone> Ord for Scored<T> {
     }
 }
 impl<T: Clone> Scored<T> {
+    /// Only for scores that cannot be NaN, e.g., constants or measures over a validated dataset; see `try_new`.
     pub fn new(score: f64, item: T) -> Self {
-        Self {
-            score: NotNan::new(score).expect("NaN found!"),
+        Self::try_new(score, item).expect("NaN found!")
+    }
+    pub fn try_new(score: f64, item: T) -> Result<Self, FastRankError> {
+        Ok(Self {
+            score: NotNan::new(score)
+                .map_err(|_| FastRankError::NaN("score of a candidate".to_owned()))?,
             item,
-        }
+        })
     }
     pub fn replace_if_better(&mut self, score: f64, item: T) -> bool {
         if let Ok(score) = NotNan::new(score) {
@@ -0,0 +1,174 @@
+use crate::dataset::{DatasetRef, RankingDataset};
+use crate::error::FastRankError;
+use crate::evaluators::SetEvaluator;
+use crate::json_api::{self, TrainRequest};
+use crate::model::ModelEnum;
+use crate::randutil::shuffle;
+use crate::sampling::DatasetSampling;
+use oorandom::Rand64;
+use std::collections::HashMap;
+use std::error::Error;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CrossValidationRequest {
+    pub train_request: TrainRequest,
+    pub num_folds: u32,
+    pub seed: u64,
+}
+
+impl Default for CrossValidationRequest {
+    fn default() -> Self {
+        let mut rand = Rand64::new(0xdeadbeef);
+        Self {
+            train_request: TrainRequest::default(),
+            num_folds: 5,
+            seed: rand.rand_u64(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FoldResult {
+    pub test_queries: Vec<String>,
+    pub model: ModelEnum,
+    /// The training measure on each of this fold's test queries.
+    pub scores: HashMap<String, f64>,
+    pub mean: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CrossValidationResult {
+    pub measure: String,
+    pub folds: Vec<FoldResult>,
+    /// Every query's score, from the one fold that held it out.
+    pub scores: HashMap<String, f64>,
+    /// Mean over all queries.
+    pub mean: f64,
+    /// Mean of the per-fold means.
+    pub fold_mean: f64,
+}
+
+fn mean(xs: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = xs.fold((0.0, 0), |(sum, count), x| (sum + x, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum / f64::from(count)
+    }
+}
+
+/// Deal the shuffled queries into `num_folds` groups of (nearly) equal size.
+pub fn query_folds(dataset: &dyn RankingDataset, num_folds: u32, seed: u64) -> Vec<Vec<String>> {
+    let mut queries = dataset.queries();
+    // Sort first so the folds only depend on the seed.
+    queries.sort_unstable();
+    shuffle(&mut queries, &mut Rand64::new(seed.into()));
+    let mut folds = vec![Vec::new(); num_folds as usize];
+    for (i, qid) in queries.into_iter().enumerate() {
+        folds[i % (num_folds as usize)].push(qid);
+    }
+    folds
+}
+
+/// Train the request once per fold, testing each model on the queries it did not see.
+pub fn cross_validate(
+    request: CrossValidationRequest,
+    dataset: &DatasetRef,
+) -> Result<CrossValidationResult, Box<dyn Error>> {
+    let num_queries = dataset.queries().len();
+    if request.num_folds < 2 || request.num_folds as usize > num_queries {
+        Err(FastRankError::InvalidParameter(format!(
+            "Cannot make {} folds from {} queries.",
+            request.num_folds, num_queries
+        )))?;
+    }
+    let train_request = request.train_request;
+
+    let folds = query_folds(dataset, request.num_folds, request.seed);
+    let mut results = Vec::new();
+    for (i, test_queries) in folds.iter().enumerate() {
+        let train_queries: Vec<String> = folds
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .flat_map(|(_, qs)| qs.iter().cloned())
+            .collect();
+        let train = dataset.with_queries(&train_queries).into_ref();
+        let test = dataset.with_queries(test_queries).into_ref();
+
+        let model = json_api::do_training(train_request.clone(), &train, None)?;
+        let evaluator = SetEvaluator::create(
+            &test,
+            train_request.measure.as_str(),
+            train_request.judgments.clone(),
+        )?;
+        let scores = evaluator.evaluate_to_map(&model);
+        results.push(FoldResult {
+            test_queries: test_queries.clone(),
+            model,
+            mean: mean(scores.values().cloned()),
+            scores,
+        });
+    }
+
+    let scores: HashMap<String, f64> = results
+        .iter()
+        .flat_map(|fold| fold.scores.iter().map(|(q, s)| (q.clone(), *s)))
+        .collect();
+    Ok(CrossValidationResult {
+        measure: train_request.measure,
+        mean: mean(scores.values().cloned()),
+        fold_mean: mean(results.iter().map(|fold| fold.mean)),
+        folds: results,
+        scores,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+    use crate::json_api::FastRankModelParams;
+    use crate::random_forest::RandomForestParams;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_folds_cover_every_query_once() {
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let request = CrossValidationRequest {
+            train_request: TrainRequest {
+                measure: "ndcg@5".to_owned(),
+                params: FastRankModelParams::RandomForest(RandomForestParams {
+                    num_trees: 5,
+                    seed: 42,
+                    quiet: true,
+                    ..RandomForestParams::default()
+                }),
+                ..TrainRequest::default()
+            },
+            num_folds: 5,
+            seed: 42,
+        };
+        let result = cross_validate(request, &dataset).unwrap();
+
+        assert_eq!(5, result.folds.len());
+        let mut seen = HashSet::new();
+        for fold in result.folds.iter() {
+            for qid in fold.test_queries.iter() {
+                assert!(seen.insert(qid.clone()), "{} tested twice", qid);
+            }
+        }
+        let all: HashSet<String> = dataset.queries().into_iter().collect();
+        assert_eq!(all, seen);
+        assert_eq!(all.len(), result.scores.len());
+
+        let no_folds = CrossValidationRequest {
+            num_folds: 1,
+            ..CrossValidationRequest::default()
+        };
+        assert!(cross_validate(no_folds, &dataset).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::FastRankError;
 use crate::instance::{FeatureRead, Instance};
 use crate::io_helper;
 use crate::libsvm;
@@ -9,6 +10,7 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::error::Error;
 use std::f64;
+use std::io::{self, BufRead};
 use std::sync::Arc;
 
 pub fn load_feature_names_json(path: &str) -> Result<HashMap<FeatureId, String>, Box<dyn Error>> {
@@ -45,6 +47,15 @@ pub trait RankingDataset: Send + Sync {
     fn get_feature_value(&self, instance: InstanceId, fid: FeatureId) -> Option<f64>;
     // Given a name or number as a string, lookup the feature id:
     fn try_lookup_feature(&self, name_or_num: &str) -> Result<FeatureId, Box<dyn Error>>;
+
+    /// How much one instance counts when fitting trees; 1.0 unless the data says otherwise.
+    fn instance_weight(&self, _id: InstanceId) -> f64 {
+        1.0
+    }
+    /// How much one query counts in evaluation, and how much its instances count in training.
+    fn query_weight(&self, _qid: &str) -> f64 {
+        1.0
+    }
 }
 
 /// This is an Arc wrapper around a LoadedRankingDataset, for cheaper copies.
@@ -52,13 +63,38 @@ pub trait RankingDataset: Send + Sync {
 pub struct DatasetRef {
     pub data: Arc<dyn RankingDataset>,
 }
+/// One instance's features, but with `fid` read as `value` instead; for what-if scoring.
+pub struct FeatureOverride<'d> {
+    pub dataset: &'d dyn RankingDataset,
+    pub id: InstanceId,
+    pub fid: FeatureId,
+    pub value: Option<f64>,
+}
+
+impl FeatureRead for FeatureOverride<'_> {
+    fn get(&self, idx: FeatureId) -> Option<f64> {
+        if idx == self.fid {
+            self.value
+        } else {
+            self.dataset.get_feature_value(self.id, idx)
+        }
+    }
+    fn dotp(&self, weights: &[f64]) -> f64 {
+        weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| w * self.get(FeatureId::from_index(i)).unwrap_or(0.0))
+            .sum()
+    }
+}
+
 /// Just proxy these requests to the inner (expensive-copy) implementation.
 impl RankingDataset for DatasetRef {
     fn get_ref(&self) -> Option<DatasetRef> {
         Some(self.data.get_ref().unwrap_or(self.clone()))
     }
     fn is_sampled(&self) -> bool {
-        return self.data.is_sampled()
+        self.data.is_sampled()
     }
     fn features(&self) -> Vec<FeatureId> {
         self.data.features()
@@ -96,6 +132,12 @@ impl RankingDataset for DatasetRef {
     fn try_lookup_feature(&self, name_or_num: &str) -> Result<FeatureId, Box<dyn Error>> {
         self.data.try_lookup_feature(name_or_num)
     }
+    fn instance_weight(&self, id: InstanceId) -> f64 {
+        self.data.instance_weight(id)
+    }
+    fn query_weight(&self, qid: &str) -> f64 {
+        self.data.query_weight(qid)
+    }
 }
 
 #[derive(Clone)]
@@ -118,7 +160,7 @@ impl RankingDataset for SampledDatasetRef {
         self.parent.get_ref()
     }
     fn is_sampled(&self) -> bool {
-        return true
+        true
     }
     fn features(&self) -> Vec<FeatureId> {
         self.features.clone()
@@ -166,15 +208,312 @@ impl RankingDataset for SampledDatasetRef {
     fn try_lookup_feature(&self, name_or_num: &str) -> Result<FeatureId, Box<dyn Error>> {
         let fid = self.parent.try_lookup_feature(name_or_num)?;
         if self.features.contains(&fid) {
-            return Ok(fid);
+            Ok(fid)
         } else {
-            Err(format!(
-                "Feature not in subsample: {}: {}",
+            Err(FastRankError::MissingFeature(format!(
+                "{} ({}) is not in this subsample",
                 name_or_num,
                 fid.to_index()
-            ))?
+            )))?
+        }
+    }
+    fn instance_weight(&self, id: InstanceId) -> f64 {
+        self.parent.instance_weight(id)
+    }
+    fn query_weight(&self, qid: &str) -> f64 {
+        self.parent.query_weight(qid)
+    }
+}
+
+/// A view of a dataset where gain() is replaced by a per-instance target, e.g., the residuals or lambdas of a boosted model.
+/// Everything else is proxied to the parent, so trees grown on this data split on the original features.
+#[derive(Clone)]
+pub struct RelabeledDatasetRef {
+    pub parent: DatasetRef,
+    /// Indexed by InstanceId; must cover every instance of the parent.
+    pub targets: Arc<Vec<NotNan<f32>>>,
+}
+
+impl RelabeledDatasetRef {
+    pub fn new(parent: &DatasetRef, targets: &[f64]) -> Result<Self, FastRankError> {
+        let targets = targets
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                NotNan::new(*t as f32)
+                    .map_err(|_| FastRankError::NaN(format!("target of instance {}", i)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            parent: parent.clone(),
+            targets: Arc::new(targets),
+        })
+    }
+    pub fn into_ref(self) -> DatasetRef {
+        DatasetRef {
+            data: Arc::new(self),
+        }
+    }
+}
+
+impl RankingDataset for RelabeledDatasetRef {
+    fn get_ref(&self) -> Option<DatasetRef> {
+        // Samples of this dataset must keep the new labels, so never hand out the parent.
+        None
+    }
+    fn is_sampled(&self) -> bool {
+        self.parent.is_sampled()
+    }
+    fn features(&self) -> Vec<FeatureId> {
+        self.parent.features()
+    }
+    fn n_dim(&self) -> u32 {
+        self.parent.n_dim()
+    }
+    fn instances(&self) -> Vec<InstanceId> {
+        self.parent.instances()
+    }
+    fn instances_by_query(&self) -> HashMap<String, Vec<InstanceId>> {
+        self.parent.instances_by_query()
+    }
+    fn score(&self, id: InstanceId, model: &dyn Model) -> NotNan<f64> {
+        self.parent.score(id, model)
+    }
+    fn gain(&self, id: InstanceId) -> NotNan<f32> {
+        self.targets[id.to_index()]
+    }
+    fn query_id(&self, id: InstanceId) -> &str {
+        self.parent.query_id(id)
+    }
+    fn document_name(&self, id: InstanceId) -> Option<&str> {
+        self.parent.document_name(id)
+    }
+    fn queries(&self) -> Vec<String> {
+        self.parent.queries()
+    }
+    fn feature_name(&self, fid: FeatureId) -> String {
+        self.parent.feature_name(fid)
+    }
+    fn get_feature_value(&self, instance: InstanceId, fid: FeatureId) -> Option<f64> {
+        self.parent.get_feature_value(instance, fid)
+    }
+    fn try_lookup_feature(&self, name_or_num: &str) -> Result<FeatureId, Box<dyn Error>> {
+        self.parent.try_lookup_feature(name_or_num)
+    }
+    fn instance_weight(&self, id: InstanceId) -> f64 {
+        self.parent.instance_weight(id)
+    }
+    fn query_weight(&self, qid: &str) -> f64 {
+        self.parent.query_weight(qid)
+    }
+}
+
+/// A view of a dataset with instance or query weights from outside of it, e.g., side files.
+/// Weights given here replace the parent's; anything not given is proxied.
+#[derive(Clone)]
+pub struct WeightedDatasetRef {
+    pub parent: DatasetRef,
+    /// Indexed by InstanceId; must cover every instance of the parent.
+    pub instance_weights: Option<Arc<Vec<f64>>>,
+    /// Queries not listed keep a weight of 1.0.
+    pub query_weights: Option<Arc<HashMap<String, f64>>>,
+}
+
+impl WeightedDatasetRef {
+    pub fn new(
+        parent: &DatasetRef,
+        instance_weights: Option<Vec<f64>>,
+        query_weights: Option<HashMap<String, f64>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if let Some(weights) = instance_weights.as_ref() {
+            let needed = parent
+                .instances()
+                .iter()
+                .map(|id| id.to_index() + 1)
+                .max()
+                .unwrap_or(0);
+            if weights.len() < needed {
+                Err(FastRankError::InvalidParameter(format!(
+                    "Found {} instance weights for {} instances.",
+                    weights.len(),
+                    needed
+                )))?;
+            }
+        }
+        let all_weights = instance_weights
+            .iter()
+            .flatten()
+            .chain(query_weights.iter().flat_map(|qw| qw.values()));
+        for w in all_weights {
+            if !w.is_finite() || *w < 0.0 {
+                Err(FastRankError::InvalidParameter(format!(
+                    "Weights must be finite and non-negative: {}",
+                    w
+                )))?;
+            }
+        }
+        Ok(Self {
+            parent: parent.clone(),
+            instance_weights: instance_weights.map(Arc::new),
+            query_weights: query_weights.map(Arc::new),
+        })
+    }
+    pub fn into_ref(self) -> DatasetRef {
+        DatasetRef {
+            data: Arc::new(self),
+        }
+    }
+}
+
+impl RankingDataset for WeightedDatasetRef {
+    fn get_ref(&self) -> Option<DatasetRef> {
+        // Samples of this dataset must keep the weights, so never hand out the parent.
+        None
+    }
+    fn is_sampled(&self) -> bool {
+        self.parent.is_sampled()
+    }
+    fn features(&self) -> Vec<FeatureId> {
+        self.parent.features()
+    }
+    fn n_dim(&self) -> u32 {
+        self.parent.n_dim()
+    }
+    fn instances(&self) -> Vec<InstanceId> {
+        self.parent.instances()
+    }
+    fn instances_by_query(&self) -> HashMap<String, Vec<InstanceId>> {
+        self.parent.instances_by_query()
+    }
+    fn score(&self, id: InstanceId, model: &dyn Model) -> NotNan<f64> {
+        self.parent.score(id, model)
+    }
+    fn gain(&self, id: InstanceId) -> NotNan<f32> {
+        self.parent.gain(id)
+    }
+    fn query_id(&self, id: InstanceId) -> &str {
+        self.parent.query_id(id)
+    }
+    fn document_name(&self, id: InstanceId) -> Option<&str> {
+        self.parent.document_name(id)
+    }
+    fn queries(&self) -> Vec<String> {
+        self.parent.queries()
+    }
+    fn feature_name(&self, fid: FeatureId) -> String {
+        self.parent.feature_name(fid)
+    }
+    fn get_feature_value(&self, instance: InstanceId, fid: FeatureId) -> Option<f64> {
+        self.parent.get_feature_value(instance, fid)
+    }
+    fn try_lookup_feature(&self, name_or_num: &str) -> Result<FeatureId, Box<dyn Error>> {
+        self.parent.try_lookup_feature(name_or_num)
+    }
+    fn instance_weight(&self, id: InstanceId) -> f64 {
+        match self.instance_weights.as_ref() {
+            Some(weights) => weights[id.to_index()],
+            None => self.parent.instance_weight(id),
+        }
+    }
+    fn query_weight(&self, qid: &str) -> f64 {
+        match self.query_weights.as_ref() {
+            Some(weights) => weights.get(qid).cloned().unwrap_or(1.0),
+            None => self.parent.query_weight(qid),
+        }
+    }
+}
+
+/// Read one instance weight per line, in the order of the data file (as LightGBM's .weight files).
+pub fn load_instance_weights(path: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+    let reader = io_helper::open_reader(path)?;
+    let mut weights = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let weight = line.parse::<f64>().map_err(|e| {
+            FastRankError::Parse(format!("{}:{}: bad weight {:?}: {}", path, i + 1, line, e))
+        })?;
+        weights.push(weight);
+    }
+    Ok(weights)
+}
+
+/// Read a "qid weight" pair from each line.
+pub fn load_query_weights(path: &str) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+    let reader = io_helper::open_reader(path)?;
+    let mut weights = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] => continue,
+            [qid, weight] => {
+                let weight = weight.parse::<f64>().map_err(|e| {
+                    FastRankError::Parse(format!(
+                        "{}:{}: bad weight {:?}: {}",
+                        path,
+                        i + 1,
+                        weight,
+                        e
+                    ))
+                })?;
+                weights.insert(qid.to_string(), weight);
+            }
+            _ => Err(FastRankError::Parse(format!(
+                "{}:{}: expected \"qid weight\": {:?}",
+                path,
+                i + 1,
+                line
+            )))?,
+        }
+    }
+    Ok(weights)
+}
+
+/// Take a "weight=..." token out of a libsvm comment, leaving the rest (e.g., the docid) behind.
+/// `path` and `line` only locate errors.
+fn take_comment_weight(
+    comment: &mut Option<String>,
+    path: &str,
+    line: usize,
+) -> Result<Option<f64>, Box<dyn Error>> {
+    let text = match comment.as_ref() {
+        Some(text) => text,
+        None => return Ok(None),
+    };
+    let mut weight = None;
+    let mut rest = Vec::new();
+    for token in text.split_whitespace() {
+        match token.strip_prefix("weight=") {
+            Some(w) => {
+                let w = w.parse::<f64>().map_err(|e| {
+                    FastRankError::Parse(format!(
+                        "{}:{}: bad weight in comment {:?}: {}",
+                        path, line, text, e
+                    ))
+                })?;
+                if !w.is_finite() || w < 0.0 {
+                    Err(FastRankError::InvalidParameter(format!(
+                        "{}:{}: weights must be finite and non-negative: {}",
+                        path, line, w
+                    )))?;
+                }
+                weight = Some(w);
+            }
+            None => rest.push(token),
         }
     }
+    if weight.is_some() {
+        *comment = if rest.is_empty() {
+            None
+        } else {
+            Some(rest.join(" "))
+        };
+    }
+    Ok(weight)
 }
 
 impl DatasetRef {
@@ -186,10 +525,13 @@ impl DatasetRef {
             data: Arc::new(LoadedRankingDataset::load_libsvm(path, feature_names)?),
         })
     }
-    pub fn new(data: Vec<Instance>, feature_names: Option<&HashMap<FeatureId, String>>) -> Self {
-        DatasetRef {
-            data: Arc::new(LoadedRankingDataset::new(data, feature_names)),
-        }
+    pub fn new(
+        data: Vec<Instance>,
+        feature_names: Option<&HashMap<FeatureId, String>>,
+    ) -> Result<Self, FastRankError> {
+        Ok(DatasetRef {
+            data: Arc::new(LoadedRankingDataset::new(data, feature_names)?),
+        })
     }
 }
 
@@ -200,6 +542,8 @@ pub struct LoadedRankingDataset {
     pub normalization: Option<Normalizer>,
     pub data_by_query: HashMap<String, Vec<InstanceId>>,
     pub feature_names: HashMap<FeatureId, String>,
+    /// From "weight=..." in libsvm comments, if any instance had one.
+    pub instance_weights: Option<Vec<f64>>,
 }
 
 impl LoadedRankingDataset {
@@ -212,15 +556,27 @@ impl LoadedRankingDataset {
         path: &str,
         feature_names: Option<&HashMap<FeatureId, String>>,
     ) -> Result<LoadedRankingDataset, Box<dyn std::error::Error>> {
-        let reader = io_helper::open_reader(path)?;
+        // Name the file in errors, but keep their type so the error code survives.
+        let reader = io_helper::open_reader(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
         let mut instances = Vec::new();
-        for inst in libsvm::instances(reader) {
-            let inst = Instance::try_new(inst?)?;
-            instances.push(inst);
+        let mut weights = Vec::new();
+        for (i, inst) in libsvm::instances(reader).enumerate() {
+            let mut inst = inst.map_err(|e| FastRankError::Parse(format!("{}: {}", path, e)))?;
+            weights.push(take_comment_weight(&mut inst.comment, path, i + 1)?);
+            instances.push(Instance::try_new(inst)?);
+        }
+        let mut dataset = Self::new(instances, feature_names)?;
+        if weights.iter().any(|w| w.is_some()) {
+            dataset.instance_weights =
+                Some(weights.into_iter().map(|w| w.unwrap_or(1.0)).collect());
         }
-        Ok(Self::new(instances, feature_names))
+        Ok(dataset)
     }
-    pub fn new(data: Vec<Instance>, feature_names: Option<&HashMap<FeatureId, String>>) -> Self {
+    pub fn new(
+        data: Vec<Instance>,
+        feature_names: Option<&HashMap<FeatureId, String>>,
+    ) -> Result<Self, FastRankError> {
         // Collect features that are actually present.
         let mut features: HashSet<FeatureId> = HashSet::new();
         // Collect training instances by the query.
@@ -228,7 +584,7 @@ impl LoadedRankingDataset {
         for (i, inst) in data.iter().enumerate() {
             data_by_query
                 .entry(inst.qid.clone())
-                .or_insert(Vec::new())
+                .or_default()
                 .push(InstanceId::from_index(i));
             features.extend(inst.features.ids());
         }
@@ -241,33 +597,43 @@ impl LoadedRankingDataset {
             .iter()
             .cloned()
             .max()
-            .expect("No features defined!")
+            .ok_or_else(|| FastRankError::EmptyDataset("No features defined!".to_owned()))?
             .to_index()
             + 1;
 
-        LoadedRankingDataset {
+        Ok(LoadedRankingDataset {
             instances: data,
             features,
             n_dim: n_dim as u32,
             normalization: None,
             data_by_query,
             feature_names: feature_names.cloned().unwrap_or(HashMap::new()),
-        }
+            instance_weights: None,
+        })
     }
-    pub fn apply_normalization(&mut self, normalizer: &Normalizer) {
+    pub fn apply_normalization(&mut self, normalizer: &Normalizer) -> Result<(), FastRankError> {
         if self.normalization.is_some() {
-            panic!("Cannot apply normalization twice!");
+            Err(FastRankError::InvalidParameter(
+                "Cannot apply normalization twice!".to_owned(),
+            ))?;
         }
-        for inst in self.instances.iter_mut() {
-            inst.features.apply_normalization(&normalizer);
+        // Normalize every instance before replacing any, so an error leaves the data as it was.
+        let normalized = self
+            .instances
+            .iter()
+            .map(|inst| inst.features.normalized(normalizer))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (inst, features) in self.instances.iter_mut().zip(normalized) {
+            inst.features = features;
         }
         self.normalization = Some(normalizer.clone());
+        Ok(())
     }
 
     /// Remove a feature or return "not-found".
     pub fn try_remove_feature(&mut self, name_or_num: &str) -> Result<(), Box<dyn Error>> {
         let fid = self.try_lookup_feature(name_or_num)?;
-        self.features.swap_remove(fid.to_index());
+        self.features.retain(|f| *f != fid);
         Ok(())
     }
 }
@@ -288,18 +654,14 @@ impl RankingDataset for LoadedRankingDataset {
     }
     fn instances(&self) -> Vec<InstanceId> {
         (0..self.instances.len())
-            .map(|i| InstanceId::from_index(i))
+            .map(InstanceId::from_index)
             .collect()
     }
     fn instances_by_query(&self) -> HashMap<String, Vec<InstanceId>> {
         self.data_by_query.clone()
     }
     fn queries(&self) -> Vec<String> {
-        self.data_by_query
-            .iter()
-            .map(|(k, _v)| k)
-            .cloned()
-            .collect()
+        self.data_by_query.keys().cloned().collect()
     }
     fn feature_name(&self, fid: FeatureId) -> String {
         self.feature_names
@@ -314,20 +676,23 @@ impl RankingDataset for LoadedRankingDataset {
         model.score(&self.instances[id.to_index()].features)
     }
     fn gain(&self, id: InstanceId) -> NotNan<f32> {
-        self.instances[id.to_index()].gain.clone()
+        self.instances[id.to_index()].gain
     }
     fn query_id(&self, id: InstanceId) -> &str {
         self.instances[id.to_index()].qid.as_str()
     }
     fn document_name(&self, id: InstanceId) -> Option<&str> {
-        self.instances[id.to_index()]
-            .docid
-            .as_ref()
-            .map(|s| s.as_str())
+        self.instances[id.to_index()].docid.as_deref()
     }
     fn try_lookup_feature(&self, name_or_num: &str) -> Result<FeatureId, Box<dyn Error>> {
         try_lookup_feature(self, &self.feature_names, name_or_num)
     }
+    fn instance_weight(&self, id: InstanceId) -> f64 {
+        match self.instance_weights.as_ref() {
+            Some(weights) => weights[id.to_index()],
+            None => 1.0,
+        }
+    }
 }
 
 pub fn try_lookup_feature(
@@ -336,35 +701,66 @@ pub fn try_lookup_feature(
     name_or_num: &str,
 ) -> Result<FeatureId, Box<dyn Error>> {
     let features = dataset.features();
-    if let Some((num, _)) = feature_names
+    let fid = match feature_names
         .iter()
         .find(|(_, v)| v.as_str() == name_or_num)
     {
-        if let Some(idx) = features.iter().position(|n| n == num) {
-            return Ok(FeatureId::from_index(idx));
-        } else {
-            return Err(format!(
-                "Named feature not present in actual dataset! {}",
-                name_or_num
-            ))?;
-        }
-    }
-
-    let num = name_or_num
-        .parse::<usize>()
-        .map(|id| FeatureId::from_index(id))
-        .map_err(|_| {
-            format!(
-                "Could not turn {} into a name or number in this dataset.",
-                name_or_num
-            )
-        })?;
-    if let Some(idx) = features.iter().position(|n| *n == num) {
-        return Ok(FeatureId::from_index(idx));
+        Some((fid, _)) => *fid,
+        None => name_or_num
+            .parse::<usize>()
+            .map(FeatureId::from_index)
+            .map_err(|_| FastRankError::MissingFeature(name_or_num.to_owned()))?,
+    };
+    if features.contains(&fid) {
+        Ok(fid)
     } else {
-        return Err(format!(
-            "Feature #{} not present in actual dataset!",
+        Err(FastRankError::MissingFeature(format!(
+            "{} is not present in this dataset",
             name_or_num
-        ))?;
+        ))
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instance::Features;
+    use crate::sampling::DatasetSampling;
+
+    #[test]
+    fn test_lookup_returns_feature_ids() {
+        // Features 2 and 5 sit at positions 0 and 1 of features(); lookups must not mix them up.
+        let instances = || {
+            vec![
+                Instance::new(
+                    NotNan::new(1.0).unwrap(),
+                    "q".to_owned(),
+                    None,
+                    Features::Sparse32(vec![(FeatureId::from_index(2), 0.5)]),
+                ),
+                Instance::new(
+                    NotNan::new(0.0).unwrap(),
+                    "q".to_owned(),
+                    None,
+                    Features::Sparse32(vec![(FeatureId::from_index(5), 0.5)]),
+                ),
+            ]
+        };
+        let mut names = HashMap::new();
+        names.insert(FeatureId::from_index(5), "pagerank".to_owned());
+        let mut loaded = LoadedRankingDataset::new(instances(), Some(&names)).unwrap();
+        let five = FeatureId::from_index(5);
+        assert_eq!(five, loaded.try_lookup_feature("5").unwrap());
+        assert_eq!(five, loaded.try_lookup_feature("pagerank").unwrap());
+        assert!(loaded.try_lookup_feature("1").is_err());
+
+        loaded.try_remove_feature("2").unwrap();
+        assert_eq!(vec![five], loaded.features());
+
+        let dataset = DatasetRef::new(instances(), Some(&names)).unwrap();
+        let sampled = dataset.with_features(&[five]).unwrap();
+        assert_eq!(five, sampled.try_lookup_feature("pagerank").unwrap());
+        assert!(sampled.try_lookup_feature("2").is_err());
     }
 }
@@ -1,22 +1,127 @@
 use crate::dataset::{DatasetRef, RankingDataset};
+use crate::error::FastRankError;
 use crate::instance::FeatureRead;
 use crate::model::Model;
 use crate::{FeatureId, InstanceId};
+use libc::c_void;
 use ordered_float::NotNan;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::error::Error;
+use std::fmt;
+use std::slice;
 use std::sync::Arc;
 
+/// Layout of a flat feature matrix: row-major (C) or column-major (Fortran).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatrixOrder {
+    C,
+    F,
+}
+
+/// Memory that belongs to someone else, e.g., a numpy array.
+/// Once nothing points at it any more, `release(owner)` lets the owner free it.
+pub struct BorrowedSlice<T> {
+    ptr: *const T,
+    len: usize,
+    owner: *const c_void,
+    release: Option<extern "C" fn(*const c_void)>,
+}
+
+// The owner promises not to move, change, or free the memory until it is released.
+unsafe impl<T: Sync> Send for BorrowedSlice<T> {}
+unsafe impl<T: Sync> Sync for BorrowedSlice<T> {}
+
+impl<T> BorrowedSlice<T> {
+    /// # Safety
+    /// `ptr` must point at `len` values that stay valid until `release(owner)` is called; without `release`, forever.
+    pub unsafe fn new(
+        ptr: *const T,
+        len: usize,
+        owner: *const c_void,
+        release: Option<extern "C" fn(*const c_void)>,
+    ) -> Self {
+        Self {
+            ptr,
+            len,
+            owner,
+            release,
+        }
+    }
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> Drop for BorrowedSlice<T> {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            release(self.owner);
+        }
+    }
+}
+
+impl<T> fmt::Debug for BorrowedSlice<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BorrowedSlice(len={})", self.len)
+    }
+}
+
+/// Either our own copy of some values, or a reference-counted borrow of someone else's.
+#[derive(Debug, Clone)]
+pub enum Values<T> {
+    Owned(Arc<[T]>),
+    Borrowed(Arc<BorrowedSlice<T>>),
+}
+
+impl<T> Values<T> {
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            Values::Owned(values) => values,
+            Values::Borrowed(values) => values.as_slice(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FeatureValues {
+    F32(Values<f32>),
+    F64(Values<f64>),
+}
+
+impl FeatureValues {
+    fn len(&self) -> usize {
+        match self {
+            FeatureValues::F32(xs) => xs.as_slice().len(),
+            FeatureValues::F64(xs) => xs.as_slice().len(),
+        }
+    }
+    fn get(&self, index: usize) -> f64 {
+        match self {
+            FeatureValues::F32(xs) => f64::from(xs.as_slice()[index]),
+            FeatureValues::F64(xs) => xs.as_slice()[index],
+        }
+    }
+    fn position_nan(&self) -> Option<usize> {
+        match self {
+            FeatureValues::F32(xs) => xs.as_slice().iter().position(|x| x.is_nan()),
+            FeatureValues::F64(xs) => xs.as_slice().iter().position(|x| x.is_nan()),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DenseDataset {
     n_features: usize,
     n_instances: usize,
-    xs: &'static [f32],
-    ys: &'static [f64],
+    xs: FeatureValues,
+    order: MatrixOrder,
+    ys: Vec<f64>,
     qid_strings: HashMap<u32, String>,
     qids: Vec<u32>,
     feature_names: HashMap<FeatureId, String>,
+    document_names: Option<Vec<String>>,
+    weights: Option<Vec<f64>>,
 }
 
 impl DenseDataset {
@@ -25,18 +130,60 @@ impl DenseDataset {
             data: Arc::new(self),
         }
     }
+    /// An n_instances x n_features matrix of `xs` with a label and query for each row.
     pub fn try_new(
         n_instances: usize,
         n_features: usize,
-        xs: &'static [f32],
-        ys: &'static [f64],
-        qids: &'static [i64],
+        xs: FeatureValues,
+        order: MatrixOrder,
+        ys: Vec<f64>,
+        qids: &[i64],
     ) -> Result<DenseDataset, Box<dyn Error>> {
+        if n_instances == 0 || n_features == 0 {
+            Err(FastRankError::EmptyDataset(format!(
+                "{} instances with {} features",
+                n_instances, n_features
+            )))?;
+        }
+        if xs.len() != n_instances * n_features || ys.len() != n_instances {
+            Err(FastRankError::InvalidParameter(format!(
+                "Expected {}x{} features and {} labels, found {} and {}.",
+                n_instances,
+                n_features,
+                n_instances,
+                xs.len(),
+                ys.len()
+            )))?;
+        }
+        if qids.len() != n_instances {
+            Err(FastRankError::InvalidParameter(format!(
+                "Found {} qids for {} instances.",
+                qids.len(),
+                n_instances
+            )))?;
+        }
+        // Scoring and evaluation assume every number is real, so check once here.
+        if let Some(i) = ys.iter().position(|y| y.is_nan()) {
+            Err(FastRankError::NaN(format!("y[{}]", i)))?;
+        }
+        if let Some(i) = xs.position_nan() {
+            let (row, col) = match order {
+                MatrixOrder::C => (i / n_features, i % n_features),
+                MatrixOrder::F => (i % n_instances, i / n_instances),
+            };
+            Err(FastRankError::NaN(format!("X[{}, {}]", row, col)))?;
+        }
+
         let mut qid_nos = Vec::new();
         let mut qid_strings = HashMap::new();
 
         for qid in qids.iter().cloned() {
-            let qid_no = u32::try_from(qid)?;
+            let qid_no = u32::try_from(qid).map_err(|_| {
+                FastRankError::InvalidParameter(format!(
+                    "qids must be non-negative 32-bit integers: {}",
+                    qid
+                ))
+            })?;
             qid_strings
                 .entry(qid_no)
                 .or_insert_with(|| format!("{}", qid_no));
@@ -47,12 +194,81 @@ impl DenseDataset {
             n_instances,
             n_features,
             xs,
+            order,
             ys,
             qids: qid_nos,
             qid_strings,
             feature_names: HashMap::new(),
+            document_names: None,
+            weights: None,
         })
     }
+    /// Name the queries when the qids given to `try_new` were positions in `names`, e.g., from `numpy.unique`.
+    pub fn with_query_names(mut self, names: Vec<String>) -> Result<Self, Box<dyn Error>> {
+        for (qid_no, name) in self.qid_strings.iter_mut() {
+            *name = names.get(*qid_no as usize).cloned().ok_or_else(|| {
+                FastRankError::InvalidParameter(format!(
+                    "qid {} has no name among {} names.",
+                    qid_no,
+                    names.len()
+                ))
+            })?;
+        }
+        Ok(self)
+    }
+    /// One name per column, so features can be looked up by name.
+    pub fn with_feature_names(mut self, names: Vec<String>) -> Result<Self, Box<dyn Error>> {
+        if names.len() != self.n_features {
+            Err(FastRankError::InvalidParameter(format!(
+                "Found {} feature names for {} features.",
+                names.len(),
+                self.n_features
+            )))?;
+        }
+        self.feature_names = names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (FeatureId::from_index(i), name))
+            .collect();
+        Ok(self)
+    }
+    /// One document id per instance, as needed for trecrun output.
+    pub fn with_document_names(mut self, names: Vec<String>) -> Result<Self, Box<dyn Error>> {
+        if names.len() != self.n_instances {
+            Err(FastRankError::InvalidParameter(format!(
+                "Found {} document names for {} instances.",
+                names.len(),
+                self.n_instances
+            )))?;
+        }
+        self.document_names = Some(names);
+        Ok(self)
+    }
+    /// One weight per instance, e.g., from a numpy array.
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Result<Self, Box<dyn Error>> {
+        if weights.len() != self.n_instances {
+            Err(FastRankError::InvalidParameter(format!(
+                "Found {} weights for {} instances.",
+                weights.len(),
+                self.n_instances
+            )))?;
+        }
+        if let Some(w) = weights.iter().find(|w| !w.is_finite() || **w < 0.0) {
+            Err(FastRankError::InvalidParameter(format!(
+                "Weights must be finite and non-negative: {}",
+                w
+            )))?;
+        }
+        self.weights = Some(weights);
+        Ok(self)
+    }
+
+    fn value(&self, row: usize, col: usize) -> f64 {
+        match self.order {
+            MatrixOrder::C => self.xs.get(row * self.n_features + col),
+            MatrixOrder::F => self.xs.get(col * self.n_instances + row),
+        }
+    }
 }
 
 struct DenseDatasetInstance<'dataset> {
@@ -65,12 +281,10 @@ impl FeatureRead for DenseDatasetInstance<'_> {
         self.dataset.get_feature_value(self.id, idx)
     }
     fn dotp(&self, weights: &[f64]) -> f64 {
-        let start = self.id.to_index() * self.dataset.n_features;
-        let end = start + self.dataset.n_features;
-        let row = &self.dataset.xs[start..end];
+        let row = self.id.to_index();
         let mut out = 0.0;
-        for (feature, weight) in row.iter().cloned().zip(weights.iter().cloned()) {
-            out += f64::from(feature) * weight;
+        for (col, weight) in weights.iter().enumerate().take(self.dataset.n_features) {
+            out += self.dataset.value(row, col) * weight;
         }
         out
     }
@@ -85,17 +299,13 @@ impl RankingDataset for DenseDataset {
         false
     }
     fn features(&self) -> Vec<FeatureId> {
-        (0..self.n_features)
-            .map(|i| FeatureId::from_index(i))
-            .collect()
+        (0..self.n_features).map(FeatureId::from_index).collect()
     }
     fn n_dim(&self) -> u32 {
         self.n_features as u32
     }
     fn instances(&self) -> Vec<InstanceId> {
-        (0..self.n_instances)
-            .map(|i| InstanceId::from_index(i))
-            .collect()
+        (0..self.n_instances).map(InstanceId::from_index).collect()
     }
     fn instances_by_query(&self) -> HashMap<String, Vec<InstanceId>> {
         let mut ref_map = HashMap::<&str, Vec<InstanceId>>::new();
@@ -116,22 +326,17 @@ impl RankingDataset for DenseDataset {
         model.score(&instance)
     }
     fn gain(&self, id: InstanceId) -> NotNan<f32> {
-        let index = id.to_index();
-        let y = self
-            .ys
-            .get(index)
-            .expect("only valid TrainingInstances should exist");
-        NotNan::new(*y as f32)
-            .map_err(|_| format!("NaN in ys[{}]", index))
-            .unwrap()
+        let y = self.ys[id.to_index()];
+        NotNan::new(y as f32).expect("try_new rejects NaN in ys")
     }
     fn query_id(&self, id: InstanceId) -> &str {
         let qid_no = self.qids[id.to_index()];
         self.qid_strings[&qid_no].as_str()
     }
-    fn document_name(&self, _id: InstanceId) -> Option<&str> {
-        // TODO: someday support names array!
-        None
+    fn document_name(&self, id: InstanceId) -> Option<&str> {
+        self.document_names
+            .as_ref()
+            .map(|names| names[id.to_index()].as_str())
     }
     fn queries(&self) -> Vec<String> {
         self.qid_strings.values().cloned().collect()
@@ -145,12 +350,111 @@ impl RankingDataset for DenseDataset {
     }
     /// Lookup a feature value for a particular instance.
     fn get_feature_value(&self, instance: InstanceId, fid: FeatureId) -> Option<f64> {
-        let index = self.n_features * instance.to_index() + fid.to_index();
-        let val = self.xs.get(index).expect("Indexes should be valid!");
-        Some(f64::from(*val))
+        if fid.to_index() >= self.n_features {
+            return None;
+        }
+        Some(self.value(instance.to_index(), fid.to_index()))
     }
     // Given a name or number as a string, lookup the feature id:
     fn try_lookup_feature(&self, name_or_num: &str) -> Result<FeatureId, Box<dyn Error>> {
         crate::dataset::try_lookup_feature(self, &self.feature_names, name_or_num)
     }
+    fn instance_weight(&self, id: InstanceId) -> f64 {
+        match self.weights.as_ref() {
+            Some(weights) => weights[id.to_index()],
+            None => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::json_api;
+    use crate::model::{ModelEnum, SingleFeatureModel};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+    extern "C" fn count_release(_owner: *const c_void) {
+        RELEASED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_orders_and_borrowing() {
+        // Rows are instances: [[1, 2, 3], [4, 5, 6]].
+        let c: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let f: Vec<f64> = vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0];
+        let ys = vec![1.0, 0.0];
+        let c_order = DenseDataset::try_new(
+            2,
+            3,
+            FeatureValues::F64(Values::Owned(c.into())),
+            MatrixOrder::C,
+            ys.clone(),
+            &[0, 0],
+        )
+        .unwrap();
+        let borrowed = unsafe {
+            BorrowedSlice::new(f.as_ptr(), f.len(), std::ptr::null(), Some(count_release))
+        };
+        let f_order = DenseDataset::try_new(
+            2,
+            3,
+            FeatureValues::F64(Values::Borrowed(Arc::new(borrowed))),
+            MatrixOrder::F,
+            ys,
+            &[1, 1],
+        )
+        .unwrap()
+        .with_query_names(vec!["a".to_owned(), "b".to_owned()])
+        .unwrap();
+        assert_eq!(vec!["b".to_owned()], f_order.queries());
+        for id in c_order.instances() {
+            for fid in c_order.features() {
+                assert_eq!(
+                    c_order.get_feature_value(id, fid),
+                    f_order.get_feature_value(id, fid)
+                );
+            }
+        }
+        let copy = f_order.clone().into_ref();
+        drop(f_order);
+        assert_eq!(0, RELEASED.load(Ordering::SeqCst));
+        drop(copy);
+        assert_eq!(1, RELEASED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_names_and_trecrun() {
+        let xs: Vec<f32> = vec![0.0, 1.0, 1.0, 0.0];
+        let dataset = DenseDataset::try_new(
+            2,
+            2,
+            FeatureValues::F32(Values::Owned(xs.into())),
+            MatrixOrder::C,
+            vec![0.0, 1.0],
+            &[7, 7],
+        )
+        .unwrap()
+        .with_feature_names(vec!["bm25".to_owned(), "pagerank".to_owned()])
+        .unwrap()
+        .with_document_names(vec!["d1".to_owned(), "d2".to_owned()])
+        .unwrap()
+        .into_ref();
+        let fid = dataset.try_lookup_feature("pagerank").unwrap();
+        assert_eq!(FeatureId::from_index(1), fid);
+        assert!(dataset.try_lookup_feature("title").is_err());
+
+        let model = ModelEnum::SingleFeature(SingleFeatureModel { fid, dir: 1.0 });
+        let path = std::env::temp_dir().join("fastrank_dense_names.trecrun");
+        let path = path.to_str().unwrap();
+        let written = json_api::predict_to_trecrun(&model, &dataset, path, "test", 0).unwrap();
+        assert_eq!(2, written);
+        let run = std::fs::read_to_string(path).unwrap();
+        let docids: Vec<&str> = run
+            .lines()
+            .map(|line| line.split_whitespace().nth(2).unwrap())
+            .collect();
+        assert_eq!(vec!["d1", "d2"], docids);
+    }
 }
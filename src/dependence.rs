@@ -0,0 +1,122 @@
+use crate::dataset::{DatasetRef, FeatureOverride, RankingDataset};
+use crate::error::FastRankError;
+use crate::model::{Model, ModelEnum};
+use crate::normalizers::FeatureStats;
+use crate::InstanceId;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PartialDependenceRequest {
+    /// Name or number of the feature to sweep.
+    pub feature: String,
+    /// How many quantiles of the feature to score at; repeated quantiles are dropped.
+    pub num_points: u32,
+    /// Also return every instance's own curve.
+    pub ice: bool,
+}
+
+impl Default for PartialDependenceRequest {
+    fn default() -> Self {
+        Self {
+            feature: String::new(),
+            num_points: 20,
+            ice: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PartialDependence {
+    pub feature: String,
+    /// The feature values scored at, ascending.
+    pub grid: Vec<f64>,
+    /// Mean score over the dataset at each grid value.
+    pub average: Vec<f64>,
+    /// Each instance's score at each grid value (individual conditional expectation).
+    pub ice: Option<BTreeMap<InstanceId, Vec<f64>>>,
+}
+
+/// Score every instance with one feature set to each of its quantiles in turn.
+pub fn partial_dependence(
+    request: &PartialDependenceRequest,
+    model: &ModelEnum,
+    dataset: &DatasetRef,
+) -> Result<PartialDependence, Box<dyn Error>> {
+    let fid = dataset.try_lookup_feature(&request.feature)?;
+    let grid = FeatureStats::quantiles(dataset, fid, request.num_points).ok_or_else(|| {
+        FastRankError::MissingFeature(format!(
+            "{} (no instance has it; nothing to sweep)",
+            request.feature
+        ))
+    })?;
+    let instances = dataset.instances();
+
+    let mut average = vec![0.0; grid.len()];
+    let mut ice = BTreeMap::new();
+    for id in instances.iter().cloned() {
+        let curve: Vec<f64> = grid
+            .iter()
+            .map(|x| {
+                let features = FeatureOverride {
+                    dataset,
+                    id,
+                    fid,
+                    value: Some(*x),
+                };
+                model.score(&features).into_inner()
+            })
+            .collect();
+        for (avg, y) in average.iter_mut().zip(curve.iter()) {
+            *avg += y;
+        }
+        if request.ice {
+            ice.insert(id, curve);
+        }
+    }
+    let n = instances.len() as f64;
+    for avg in average.iter_mut() {
+        *avg /= n;
+    }
+
+    Ok(PartialDependence {
+        feature: dataset.feature_name(fid),
+        grid,
+        average,
+        ice: if request.ice { Some(ice) } else { None },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+    use crate::model::DenseLinearRankingModel;
+
+    #[test]
+    fn test_partial_dependence() {
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let model = ModelEnum::Linear(DenseLinearRankingModel {
+            weights: vec![0.0, 0.0, 0.0, 0.0, 0.0, 2.0],
+        });
+        let request = PartialDependenceRequest {
+            feature: "5".to_owned(),
+            num_points: 5,
+            ice: true,
+        };
+        let pd = partial_dependence(&request, &model, &dataset).unwrap();
+        assert!(pd.grid.len() <= 5);
+        assert!(pd.grid.windows(2).all(|w| w[0] < w[1]));
+        // Only the swept feature matters, so every curve is exactly 2x the grid.
+        let expected: Vec<f64> = pd.grid.iter().map(|x| 2.0 * x).collect();
+        for (avg, y) in pd.average.iter().zip(expected.iter()) {
+            assert!((avg - y).abs() < 1e-9);
+        }
+        let ice = pd.ice.unwrap();
+        assert_eq!(dataset.instances().len(), ice.len());
+        assert!(ice.values().all(|curve| *curve == expected));
+    }
+}
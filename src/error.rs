@@ -0,0 +1,258 @@
+use crate::libsvm;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Errors that come from bad input rather than bugs, so callers (and Python) can react to them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FastRankError {
+    /// Input that could not be read, e.g., a malformed row, number, or JSON document.
+    Parse(String),
+    /// A NaN where a number was required; the message says where it was found.
+    NaN(String),
+    /// A feature name or number that the dataset does not have.
+    MissingFeature(String),
+    /// A dataset, or a split of one, with no instances, queries or features to work with.
+    EmptyDataset(String),
+    /// A setting outside its allowed range, or one the learner does not know.
+    InvalidParameter(String),
+}
+
+impl FastRankError {
+    /// Stable, machine-readable name for the kind of error; sent over the FFI as "code".
+    pub fn code(&self) -> &'static str {
+        match self {
+            FastRankError::Parse(_) => "parse",
+            FastRankError::NaN(_) => "nan",
+            FastRankError::MissingFeature(_) => "missing_feature",
+            FastRankError::EmptyDataset(_) => "empty_dataset",
+            FastRankError::InvalidParameter(_) => "invalid_parameter",
+        }
+    }
+}
+
+impl fmt::Display for FastRankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FastRankError::Parse(msg) => write!(f, "Parse error: {}", msg),
+            FastRankError::NaN(msg) => write!(f, "NaN found: {}", msg),
+            FastRankError::MissingFeature(name) => write!(f, "Missing feature: {}", name),
+            FastRankError::EmptyDataset(msg) => write!(f, "Empty dataset: {}", msg),
+            FastRankError::InvalidParameter(msg) => write!(f, "Invalid parameter: {}", msg),
+        }
+    }
+}
+
+impl Error for FastRankError {}
+
+/// A panic caught before it could unwind into C; always a bug, but callers get an error, not a crash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Panic(pub String);
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Internal error (panic): {}", self.0)
+    }
+}
+
+impl Error for Panic {}
+
+/// The code for any error we might return; errors from libraries are classified where we can.
+pub fn error_code(error: &(dyn Error + 'static)) -> &'static str {
+    if let Some(e) = error.downcast_ref::<FastRankError>() {
+        e.code()
+    } else if error.is::<libsvm::FileParseError>() || error.is::<serde_json::Error>() {
+        "parse"
+    } else if error.is::<io::Error>() {
+        "io"
+    } else if error.is::<Panic>() {
+        "panic"
+    } else {
+        "error"
+    }
+}
+
+/// Make an error Send and Sync, e.g., to return it out of a rayon closure, keeping its code.
+pub fn into_send(error: Box<dyn Error>) -> Box<dyn Error + Send + Sync> {
+    let error = match error.downcast::<FastRankError>() {
+        Ok(e) => return e,
+        Err(error) => error,
+    };
+    let error = match error.downcast::<libsvm::FileParseError>() {
+        Ok(e) => return e,
+        Err(error) => error,
+    };
+    let error = match error.downcast::<serde_json::Error>() {
+        Ok(e) => return e,
+        Err(error) => error,
+    };
+    let error = match error.downcast::<io::Error>() {
+        Ok(e) => return e,
+        Err(error) => error,
+    };
+    match error.downcast::<Panic>() {
+        Ok(e) => e,
+        Err(error) => error.to_string().into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coordinate_ascent::CoordinateAscentParams;
+    use crate::dataset::{LoadedRankingDataset, RankingDataset, RelabeledDatasetRef};
+    use crate::dense_dataset::MatrixOrder;
+    use crate::evaluators::SetEvaluator;
+    use crate::ffi::{catch_panic, result_make_dense_dataset, DenseDatasetSpec};
+    use crate::instance::{Features, Instance};
+    use crate::monotone::{MonotoneConstraints, Monotonicity};
+    use crate::normalizers::Normalizer;
+    use crate::random_forest::{self, RandomForestParams};
+    use crate::sampling::DatasetSampling;
+    use crate::{FeatureId, InstanceId};
+    use libc::c_void;
+    use oorandom::Rand64;
+    use ordered_float::NotNan;
+
+    #[test]
+    fn test_error_code() {
+        let e: Box<dyn Error> = Box::new(FastRankError::MissingFeature("pagerank".to_owned()));
+        assert_eq!("missing_feature", error_code(e.as_ref()));
+        assert_eq!("Missing feature: pagerank", e.to_string());
+        let e: Box<dyn Error> = serde_json::from_str::<u32>("x").unwrap_err().into();
+        assert_eq!("parse", error_code(e.as_ref()));
+        let e: Box<dyn Error> = "something else".into();
+        assert_eq!("error", error_code(e.as_ref()));
+        let e: Box<dyn Error> = into_send(Box::new(FastRankError::NaN("score".to_owned())));
+        assert_eq!("nan", error_code(e.as_ref()));
+        let e = catch_panic::<()>(|| panic!("boom")).unwrap_err();
+        assert_eq!("panic", error_code(e.as_ref()));
+        assert_eq!("Internal error (panic): boom", e.to_string());
+    }
+
+    #[test]
+    fn test_bad_input_is_an_error() {
+        let rows = "1 qid:1 1:0.5 2:nan\n";
+        let inst = libsvm::instances(Box::new(io::Cursor::new(rows)))
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!("nan", Instance::try_new(inst).err().unwrap().code());
+
+        let empty = LoadedRankingDataset::new(Vec::new(), None);
+        assert_eq!("empty_dataset", empty.err().unwrap().code());
+
+        let dataset = LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+            .unwrap()
+            .into_ref();
+        let mut rand = Rand64::new(42);
+        for fraction in &[0.0, 1.0] {
+            let split = dataset.train_test(*fraction, &mut rand);
+            assert_eq!("invalid_parameter", split.err().unwrap().code());
+        }
+        let mut targets = vec![0.0; dataset.instances().len()];
+        targets[0] = f64::NAN;
+        let relabeled = RelabeledDatasetRef::new(&dataset, &targets);
+        assert_eq!("nan", relabeled.err().unwrap().code());
+        let missing = dataset.try_lookup_feature("no_such_feature").unwrap_err();
+        assert_eq!("missing_feature", error_code(missing.as_ref()));
+
+        // Learners report bad constraints and parameters instead of panicking.
+        let eval = SetEvaluator::create(&dataset, "ndcg@5", None).unwrap();
+        let mut unknown = MonotoneConstraints::new();
+        unknown.insert("no_such_feature".to_owned(), Monotonicity::Increasing());
+        let ca = CoordinateAscentParams {
+            monotone_constraints: unknown.clone(),
+            quiet: true,
+            ..CoordinateAscentParams::default()
+        };
+        let err = ca.learn(&dataset, &eval, None).unwrap_err();
+        assert_eq!("missing_feature", error_code(err.as_ref()));
+        let rf = RandomForestParams {
+            monotone_constraints: unknown,
+            quiet: true,
+            ..RandomForestParams::default()
+        };
+        let err = random_forest::learn_ensemble(&rf, &dataset, &eval, None).unwrap_err();
+        assert_eq!("missing_feature", error_code(err.as_ref()));
+        let no_restarts = CoordinateAscentParams {
+            num_restarts: 0,
+            quiet: true,
+            ..CoordinateAscentParams::default()
+        };
+        let err = no_restarts.learn(&dataset, &eval, None).unwrap_err();
+        assert_eq!("invalid_parameter", error_code(err.as_ref()));
+
+        // Feature 2 normalizes to inf/inf on the second row only, after the first row succeeded.
+        let rows = vec![vec![(1, 0.5), (2, 0.0)], vec![(1, 1.0), (2, f32::INFINITY)]];
+        let instances = rows
+            .into_iter()
+            .map(|row| {
+                let features = row
+                    .into_iter()
+                    .map(|(fid, x)| (FeatureId::from_index(fid), x))
+                    .collect();
+                Instance::new(
+                    NotNan::new(1.0).unwrap(),
+                    "q".to_owned(),
+                    None,
+                    Features::Sparse32(features),
+                )
+            })
+            .collect();
+        let mut loaded = LoadedRankingDataset::new(instances, None).unwrap();
+        let normalizer = Normalizer::new("maxmin", &loaded).unwrap();
+        assert_eq!(
+            "nan",
+            loaded.apply_normalization(&normalizer).unwrap_err().code()
+        );
+        let first = InstanceId::from_index(0);
+        assert_eq!(
+            Some(0.5),
+            loaded.get_feature_value(first, FeatureId::from_index(1))
+        );
+        assert!(loaded.normalization.is_none());
+
+        // The size check fails before any array is read.
+        let spec = DenseDatasetSpec {
+            n: usize::MAX,
+            d: 2,
+            x_dtype: "float32".to_owned(),
+            order: MatrixOrder::C,
+            qid_dtype: "int64".to_owned(),
+            qid_names: None,
+            copy: true,
+            feature_names: None,
+            docids: None,
+        };
+        let one = [0.0f64];
+        let ptr = one.as_ptr();
+        let err = result_make_dense_dataset(
+            Ok(spec),
+            ptr as *const c_void,
+            ptr,
+            ptr as *const c_void,
+            std::ptr::null(),
+            std::ptr::null(),
+            None,
+        )
+        .err()
+        .unwrap();
+        assert_eq!("invalid_parameter", error_code(err.as_ref()));
+
+        let path = std::env::temp_dir().join("fastrank_bad_weights.libsvm");
+        for weight in &["nan", "inf", "-1"] {
+            let rows = format!(
+                "1 qid:1 1:0.5 # weight=1\n0 qid:1 1:0.2 # d2 weight={}\n",
+                weight
+            );
+            std::fs::write(&path, rows).unwrap();
+            let err = LoadedRankingDataset::load_libsvm(path.to_str().unwrap(), None)
+                .err()
+                .unwrap();
+            assert_eq!("invalid_parameter", error_code(err.as_ref()));
+            assert!(err.to_string().contains(":2:"));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
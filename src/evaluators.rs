@@ -1,4 +1,5 @@
 use crate::dataset::{DatasetRef, RankingDataset};
+use crate::error::FastRankError;
 use crate::model::Model;
 use crate::qrel::QuerySetJudgments;
 use crate::stats::PercentileStats;
@@ -11,7 +12,7 @@ use std::sync::Arc;
 
 const NUM_BOOTSTRAP_SAMPLES: u32 = 200;
 
-#[derive(Debug, Eq)]
+#[derive(Debug, Clone, Copy, Eq)]
 pub struct RankedInstance {
     pub score: NotNan<f64>,
     pub gain: NotNan<f32>,
@@ -20,13 +21,13 @@ pub struct RankedInstance {
 
 impl PartialEq for RankedInstance {
     fn eq(&self, other: &RankedInstance) -> bool {
-        self.cmp(&other) == Ordering::Equal
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl PartialOrd for RankedInstance {
     fn partial_cmp(&self, other: &RankedInstance) -> Option<Ordering> {
-        Some(self.cmp(&other))
+        Some(self.cmp(other))
     }
 }
 
@@ -136,9 +137,12 @@ impl SetEvaluator {
     ) -> Result<SetEvaluator, Box<dyn std::error::Error>> {
         let (name, depth) = if let Some(at_point) = orig_name.find('@') {
             let (lhs, rhs) = orig_name.split_at(at_point);
-            let depth = rhs[1..]
-                .parse::<usize>()
-                .map_err(|_| format!("Couldn't parse after the @ in \"{}\": {}", orig_name, rhs))?;
+            let depth = rhs[1..].parse::<usize>().map_err(|_| {
+                FastRankError::InvalidParameter(format!(
+                    "Couldn't parse after the @ in \"{}\": {}",
+                    orig_name, rhs
+                ))
+            })?;
             (lhs.to_lowercase(), Some(depth))
         } else {
             (orig_name.to_lowercase(), None)
@@ -148,8 +152,11 @@ impl SetEvaluator {
             evaluator: match name.as_str() {
                 "ap" | "map" => Arc::new(AveragePrecision::new(dataset, judgments.clone())),
                 "rr" | "mrr" => Arc::new(ReciprocalRank),
-                "ndcg" => Arc::new(NDCG::new(depth, dataset, judgments.clone())),
-                _ => Err(format!("Invalid training measure: \"{}\"", orig_name))?,
+                "ndcg" => Arc::new(NDCG::new(depth, dataset, judgments.clone())?),
+                _ => Err(FastRankError::InvalidParameter(format!(
+                    "Invalid training measure: \"{}\"",
+                    orig_name
+                )))?,
             },
         })
     }
@@ -170,55 +177,93 @@ impl SetEvaluator {
         PercentileStats::new(&means)
     }
 
+    /// The mean over queries, each weighted by the dataset's `query_weight`.
     pub fn evaluate_mean(&self, model: &dyn Model) -> f64 {
-        let scores = self.evaluate_to_vec(model);
-        if scores.len() == 0 {
+        let mut sum = 0.0;
+        let mut total_weight = 0.0;
+        for (qid, docs) in self.dataset.instances_by_query().iter() {
+            let weight = self.dataset.query_weight(qid);
+            let ranked_list = self.rank(docs, model);
+            sum += weight * self.evaluator.score(qid, &ranked_list);
+            total_weight += weight;
+        }
+        if total_weight == 0.0 {
             return 0.0;
         }
-        let n = scores.len() as f64;
+        sum / total_weight
+    }
+
+    /// The mean of per-query scores, weighted by `query_weight` as in `evaluate_mean`.
+    pub fn weighted_mean(&self, scores: &HashMap<String, f64>) -> f64 {
         let mut sum = 0.0;
-        for s in scores {
-            sum += s;
+        let mut total_weight = 0.0;
+        for (qid, score) in scores.iter() {
+            let weight = self.dataset.query_weight(qid);
+            sum += weight * score;
+            total_weight += weight;
         }
-        return sum / n;
+        if total_weight == 0.0 {
+            return 0.0;
+        }
+        sum / total_weight
     }
 
     pub fn evaluate_to_map(&self, model: &dyn Model) -> HashMap<String, f64> {
         let mut scores = HashMap::new();
         for (qid, docs) in self.dataset.instances_by_query().iter() {
-            // Predict for every document:
-            let mut ranked_list: Vec<_> = docs
-                .iter()
-                .cloned()
-                .map(|index| {
-                    let score = self.dataset.score(index, model);
-                    let gain = self.dataset.gain(index);
-                    RankedInstance::new(score, gain, index)
-                })
-                .collect();
-            // Sort largest to smallest:
-            ranked_list.sort_unstable();
-            scores.insert(qid.to_owned(), self.evaluator.score(&qid, &ranked_list));
+            let ranked_list = self.rank(docs, model);
+            scores.insert(qid.to_owned(), self.evaluator.score(qid, &ranked_list));
         }
         scores
     }
 
+    /// Score each query with its own model; queries without one are left out.
+    pub fn evaluate_to_map_with<'m>(
+        &self,
+        model_for: impl Fn(&str) -> Option<&'m dyn Model>,
+    ) -> HashMap<String, f64> {
+        let mut scores = HashMap::new();
+        for (qid, docs) in self.dataset.instances_by_query().iter() {
+            if let Some(model) = model_for(qid) {
+                let ranked_list = self.rank(docs, model);
+                scores.insert(qid.to_owned(), self.evaluator.score(qid, &ranked_list));
+            }
+        }
+        scores
+    }
+
+    fn rank(&self, docs: &[InstanceId], model: &dyn Model) -> Vec<RankedInstance> {
+        // Predict for every document:
+        let mut ranked_list: Vec<_> = docs
+            .iter()
+            .cloned()
+            .map(|index| {
+                let score = self.dataset.score(index, model);
+                let gain = self.dataset.gain(index);
+                RankedInstance::new(score, gain, index)
+            })
+            .collect();
+        // Sort largest to smallest:
+        ranked_list.sort_unstable();
+        ranked_list
+    }
+
+    /// Delta-measure for swapping two positions in a query's ranked list, used by LambdaMART.
+    pub fn swap_change(
+        &self,
+        qid: &str,
+        ranked_list: &[RankedInstance],
+        i: usize,
+        j: usize,
+    ) -> f64 {
+        self.evaluator.swap_change(qid, ranked_list, i, j)
+    }
+
     pub fn evaluate_to_vec(&self, model: &dyn Model) -> Vec<f64> {
         let mut scores = Vec::new();
         for (qid, docs) in self.dataset.instances_by_query().iter() {
-            // Predict for every document:
-            let mut ranked_list: Vec<_> = docs
-                .iter()
-                .cloned()
-                .map(|index| {
-                    let score = self.dataset.score(index, model);
-                    let gain = self.dataset.gain(index);
-                    RankedInstance::new(score, gain, index)
-                })
-                .collect();
-            // Sort largest to smallest:
-            ranked_list.sort_unstable();
-            scores.push(self.evaluator.score(&qid, &ranked_list));
+            let ranked_list = self.rank(docs, model);
+            scores.push(self.evaluator.score(qid, &ranked_list));
         }
         scores
     }
@@ -227,6 +272,13 @@ impl SetEvaluator {
 pub trait Evaluator: Send + Sync {
     fn name(&self) -> String;
     fn score(&self, qid: &str, ranked_list: &[RankedInstance]) -> f64;
+    /// The change in score from swapping positions i and j of a sorted ranked list.
+    /// By default this just re-scores the swapped list; measures with a closed form should override it.
+    fn swap_change(&self, qid: &str, ranked_list: &[RankedInstance], i: usize, j: usize) -> f64 {
+        let mut swapped = ranked_list.to_vec();
+        swapped.swap(i, j);
+        self.score(qid, &swapped) - self.score(qid, ranked_list)
+    }
 }
 
 #[derive(Clone)]
@@ -249,7 +301,7 @@ impl Evaluator for ReciprocalRank {
         {
             recip_rank = 1.0 / (rel_rank as f64)
         }
-        return recip_rank;
+        recip_rank
     }
 }
 
@@ -267,7 +319,7 @@ fn compute_dcg(gains: &[NotNan<f32>], depth: Option<usize>, ideal: bool) -> f64
     for (i, gain) in gain_vector.into_iter().enumerate() {
         let i = i as f64;
         let gain = gain.into_inner() as f64;
-        dcg += ((2.0 as f64).powf(gain) - 1.0) / (i + 2.0).log2();
+        dcg += (2.0_f64.powf(gain) - 1.0) / (i + 2.0).log2();
     }
     dcg
 }
@@ -294,6 +346,40 @@ mod tests {
 
         assert_trec_eq(0.7328, actual / ideal);
     }
+
+    #[test]
+    fn test_ndcg_swap_change() {
+        let ranked_list: Vec<RankedInstance> = [0.0, 2.0, 1.0, 0.0, 1.0]
+            .iter()
+            .enumerate()
+            .map(|(i, g)| {
+                RankedInstance::new(
+                    NotNan::new(-(i as f64)).unwrap(),
+                    NotNan::new(*g).unwrap(),
+                    InstanceId::from_index(i),
+                )
+            })
+            .collect();
+        let ideal: Vec<NotNan<f32>> = ranked_list.iter().map(|ri| ri.gain).collect();
+        for depth in &[None, Some(3)] {
+            let ndcg = NDCG {
+                depth: *depth,
+                ideal_gains: Arc::new(
+                    vec![("q".to_string(), Some(compute_dcg(&ideal, *depth, true)))]
+                        .into_iter()
+                        .collect(),
+                ),
+            };
+            for i in 0..ranked_list.len() {
+                for j in (i + 1)..ranked_list.len() {
+                    let mut swapped = ranked_list.clone();
+                    swapped.swap(i, j);
+                    let expected = ndcg.score("q", &swapped) - ndcg.score("q", &ranked_list);
+                    assert_trec_eq(expected, ndcg.swap_change("q", &ranked_list, i, j));
+                }
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -306,22 +392,32 @@ impl NDCG {
         depth: Option<usize>,
         dataset: &DatasetRef,
         judgments: Option<QuerySetJudgments>,
-    ) -> Self {
+    ) -> Result<Self, FastRankError> {
         let mut query_norms: HashMap<String, Option<f64>> = HashMap::new();
 
         for (qid, instance_ids) in dataset.instances_by_query().iter() {
+            let dataset_gains: Vec<NotNan<f32>> = instance_ids
+                .iter()
+                .map(|index| dataset.gain(*index))
+                .collect();
             // Determine the total number of relevant documents:
             let all_gains: Option<Vec<NotNan<f32>>> = judgments
                 .as_ref()
                 .and_then(|j| j.get(qid))
                 .map(|data| data.gain_vector());
+            if let Some(judged) = all_gains.as_ref() {
+                // Otherwise a ranking could score above 1.0.
+                let judged_dcg = compute_dcg(judged, depth, true);
+                let labeled_dcg = compute_dcg(&dataset_gains, depth, true);
+                if labeled_dcg > judged_dcg * (1.0 + 1e-9) {
+                    Err(FastRankError::InvalidParameter(format!(
+                        "Judgments for qid {} are below the dataset's own labels: ideal DCG {} < {}",
+                        qid, judged_dcg, labeled_dcg
+                    )))?;
+                }
+            }
             // Calculate if unavailable in config:
-            let ideal_gains: Vec<NotNan<f32>> = all_gains.unwrap_or_else(|| {
-                instance_ids
-                    .iter()
-                    .map(|index| dataset.gain(*index))
-                    .collect()
-            });
+            let ideal_gains: Vec<NotNan<f32>> = all_gains.unwrap_or(dataset_gains);
             // Insert ideal if available:
             query_norms.insert(
                 qid.clone(),
@@ -333,10 +429,26 @@ impl NDCG {
             );
         }
 
-        Self {
+        Ok(Self {
             depth,
             ideal_gains: Arc::new(query_norms),
-        }
+        })
+    }
+
+    /// The ideal DCG for a query, if it has any relevant documents.
+    fn normalizer(&self, qid: &str, actual_gain_vector: &[NotNan<f32>]) -> Option<f64> {
+        self.ideal_gains.get(qid).cloned().unwrap_or_else(|| {
+            if actual_gain_vector
+                .iter()
+                .filter(|g| g.into_inner() > 0.0)
+                .count()
+                == 0
+            {
+                None
+            } else {
+                Some(compute_dcg(actual_gain_vector, self.depth, true))
+            }
+        })
     }
 }
 
@@ -351,34 +463,35 @@ impl Evaluator for NDCG {
     fn score(&self, qid: &str, ranked_list: &[RankedInstance]) -> f64 {
         let actual_gain_vector: Vec<_> = ranked_list.iter().map(|ri| ri.gain).collect();
 
-        let normalizer = self.ideal_gains.get(qid).cloned().unwrap_or_else(|| {
-            if actual_gain_vector
-                .iter()
-                .filter(|g| g.into_inner() > 0.0)
-                .count()
-                == 0
-            {
-                None
-            } else {
-                Some(compute_dcg(&actual_gain_vector, self.depth, true))
-            }
-        });
-
-        if let Some(ideal_dcg) = normalizer {
+        if let Some(ideal_dcg) = self.normalizer(qid, &actual_gain_vector) {
             // Compute NDCG:
             let actual_dcg = compute_dcg(&actual_gain_vector, self.depth, false);
-            if actual_dcg > ideal_dcg {
-                panic!(
-                    "qid: {}, actual_gain_vector: {:?} ideal_dcg: {}",
-                    qid, actual_gain_vector, ideal_dcg
-                )
-            }
-            actual_dcg / ideal_dcg
+            // NDCG::new rejects judgments below the labels, so only rounding can take this past 1.0.
+            (actual_dcg / ideal_dcg).min(1.0)
         } else {
             // If not gains, there's nothing to calculate.
             0.0
         }
     }
+    fn swap_change(&self, qid: &str, ranked_list: &[RankedInstance], i: usize, j: usize) -> f64 {
+        if let Some(depth) = self.depth {
+            if i >= depth && j >= depth {
+                return 0.0;
+            }
+        }
+        let actual_gain_vector: Vec<_> = ranked_list.iter().map(|ri| ri.gain).collect();
+        let ideal_dcg = match self.normalizer(qid, &actual_gain_vector) {
+            Some(ideal_dcg) => ideal_dcg,
+            None => return 0.0,
+        };
+        let discount = |rank: usize| match self.depth {
+            Some(depth) if rank >= depth => 0.0,
+            _ => 1.0 / (rank as f64 + 2.0).log2(),
+        };
+        let gain = |g: NotNan<f32>| 2.0_f64.powf(g.into_inner() as f64) - 1.0;
+        (gain(ranked_list[j].gain) - gain(ranked_list[i].gain)) * (discount(i) - discount(j))
+            / ideal_dcg
+    }
 }
 
 #[derive(Clone)]
@@ -0,0 +1,444 @@
+use crate::dataset::{DatasetRef, RankingDataset};
+use crate::error::FastRankError;
+use crate::model::{ModelEnum, TreeNode};
+use crate::{FeatureId, InstanceId};
+use ordered_float::NotNan;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Why one document got its score: `expected_value` plus every contribution equals `score`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Explanation {
+    pub instance: InstanceId,
+    pub score: f64,
+    /// The model's average output over the dataset it is explained against.
+    pub expected_value: f64,
+    /// SHAP value by feature name; features that never matter to this model are left out.
+    pub contributions: BTreeMap<String, f64>,
+}
+
+/// Explain the scores of `instances`, using all of `dataset` as the background.
+/// Exact TreeSHAP for trees and ensembles of them, and linear SHAP for linear models.
+pub fn explain(
+    model: &ModelEnum,
+    dataset: &DatasetRef,
+    instances: &[InstanceId],
+) -> Result<Vec<Explanation>, Box<dyn Error>> {
+    let members = shap_members(model, dataset, 1.0)?;
+    let expected_value = members.iter().map(|m| m.expected_value()).sum();
+    let mut known = dataset.instances();
+    known.sort_unstable();
+    instances
+        .iter()
+        .map(|id| {
+            if known.binary_search(id).is_err() {
+                Err(FastRankError::InvalidParameter(format!(
+                    "No instance {} in this dataset.",
+                    id.to_index()
+                )))?;
+            }
+            let mut phi = BTreeMap::new();
+            for m in members.iter() {
+                m.explain(dataset, *id, &mut phi);
+            }
+            Ok(Explanation {
+                instance: *id,
+                score: dataset.score(*id, model).into_inner(),
+                expected_value,
+                contributions: phi
+                    .into_iter()
+                    .map(|(fid, v)| (dataset.feature_name(fid), v))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// One additive piece of a model, already scaled by its ensemble weight.
+enum ShapMember {
+    Tree {
+        tree: ShapTree,
+        weight: f64,
+    },
+    /// Per-feature weight and that feature's mean over the background (missing counts as 0.0).
+    Linear(Vec<(FeatureId, f64, f64)>),
+}
+
+fn shap_members(
+    model: &ModelEnum,
+    dataset: &DatasetRef,
+    weight: f64,
+) -> Result<Vec<ShapMember>, Box<dyn Error>> {
+    let linear = |weights: Vec<(FeatureId, f64)>| {
+        let instances = dataset.instances();
+        let n = instances.len().max(1) as f64;
+        let terms = weights
+            .into_iter()
+            .filter(|(_, w)| *w != 0.0)
+            .map(|(fid, w)| {
+                let sum: f64 = instances
+                    .iter()
+                    .map(|id| dataset.get_feature_value(*id, fid).unwrap_or(0.0))
+                    .sum();
+                (fid, weight * w, sum / n)
+            })
+            .collect();
+        vec![ShapMember::Linear(terms)]
+    };
+    Ok(match model {
+        ModelEnum::SingleFeature(m) => linear(vec![(m.fid, m.dir)]),
+        ModelEnum::Linear(m) => linear(
+            m.weights
+                .iter()
+                .enumerate()
+                .map(|(i, w)| (FeatureId::from_index(i), *w))
+                .collect(),
+        ),
+        ModelEnum::DecisionTree(tree) => vec![ShapMember::Tree {
+            tree: ShapTree::new(tree, dataset),
+            weight,
+        }],
+        ModelEnum::Ensemble(e) => {
+            let mut members = Vec::new();
+            for (w, m) in e.iter() {
+                members.extend(shap_members(m, dataset, weight * w)?);
+            }
+            members
+        }
+        ModelEnum::MLP(_) => Err(FastRankError::InvalidParameter(
+            "SHAP explanations are not available for MLP models.".to_owned(),
+        ))?,
+    })
+}
+
+impl ShapMember {
+    fn expected_value(&self) -> f64 {
+        match self {
+            ShapMember::Tree { tree, weight } => weight * tree.expected_value(0),
+            ShapMember::Linear(terms) => terms.iter().map(|(_, w, mean)| w * mean).sum(),
+        }
+    }
+    fn explain(&self, dataset: &DatasetRef, id: InstanceId, phi: &mut BTreeMap<FeatureId, f64>) {
+        match self {
+            ShapMember::Tree { tree, weight } => {
+                let mut tree_phi = BTreeMap::new();
+                tree.recurse(dataset, id, 0, Vec::new(), 1.0, 1.0, None, &mut tree_phi);
+                for (fid, v) in tree_phi {
+                    *phi.entry(fid).or_default() += weight * v;
+                }
+            }
+            ShapMember::Linear(terms) => {
+                for (fid, w, mean) in terms.iter() {
+                    let x = dataset.get_feature_value(id, *fid).unwrap_or(0.0);
+                    *phi.entry(*fid).or_default() += w * (x - mean);
+                }
+            }
+        }
+    }
+}
+
+struct ShapSplit {
+    fid: FeatureId,
+    split: NotNan<f64>,
+    missing_left: Option<bool>,
+    lhs: usize,
+    rhs: usize,
+}
+
+struct ShapNode {
+    /// None for leaves.
+    split: Option<ShapSplit>,
+    value: f64,
+    /// How many background instances reach this node.
+    cover: f64,
+}
+
+/// A tree flattened into an array, with the cover of every node.
+struct ShapTree {
+    nodes: Vec<ShapNode>,
+}
+
+#[derive(Clone)]
+struct PathElement {
+    /// None only for the root's placeholder.
+    fid: Option<FeatureId>,
+    zero_fraction: f64,
+    one_fraction: f64,
+    weight: f64,
+}
+
+impl ShapTree {
+    fn new(tree: &TreeNode, dataset: &DatasetRef) -> Self {
+        let mut out = ShapTree { nodes: Vec::new() };
+        out.push(tree);
+        for id in dataset.instances() {
+            let mut node = 0;
+            out.nodes[node].cover += 1.0;
+            while let Some(s) = out.nodes[node].split.as_ref() {
+                let value = dataset.get_feature_value(id, s.fid);
+                node = if TreeNode::goes_left(value, s.split, s.missing_left) {
+                    s.lhs
+                } else {
+                    s.rhs
+                };
+                out.nodes[node].cover += 1.0;
+            }
+        }
+        out
+    }
+
+    fn push(&mut self, tree: &TreeNode) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(ShapNode {
+            split: None,
+            value: 0.0,
+            cover: 0.0,
+        });
+        match tree {
+            TreeNode::LeafNode(output) => self.nodes[index].value = output.into_inner(),
+            TreeNode::FeatureSplit {
+                fid,
+                split,
+                lhs,
+                rhs,
+                missing_left,
+                ..
+            } => {
+                let lhs = self.push(lhs);
+                let rhs = self.push(rhs);
+                self.nodes[index].split = Some(ShapSplit {
+                    fid: *fid,
+                    split: *split,
+                    missing_left: *missing_left,
+                    lhs,
+                    rhs,
+                });
+            }
+        }
+        index
+    }
+
+    /// The share of `parent`'s background that goes to `child`; parts of the tree the background
+    /// never reaches split evenly, so fractions always sum to one.
+    fn fraction(&self, child: usize, parent: usize) -> f64 {
+        let parent_cover = self.nodes[parent].cover;
+        if parent_cover > 0.0 {
+            self.nodes[child].cover / parent_cover
+        } else {
+            0.5
+        }
+    }
+
+    fn expected_value(&self, node: usize) -> f64 {
+        match self.nodes[node].split.as_ref() {
+            None => self.nodes[node].value,
+            Some(s) => {
+                self.fraction(s.lhs, node) * self.expected_value(s.lhs)
+                    + self.fraction(s.rhs, node) * self.expected_value(s.rhs)
+            }
+        }
+    }
+
+    /// Algorithm 2 of Lundberg et al., "Consistent Individualized Feature Attribution for Tree
+    /// Ensembles" (2018).
+    #[allow(clippy::too_many_arguments)]
+    fn recurse(
+        &self,
+        dataset: &DatasetRef,
+        id: InstanceId,
+        node: usize,
+        mut path: Vec<PathElement>,
+        zero_fraction: f64,
+        one_fraction: f64,
+        fid: Option<FeatureId>,
+        phi: &mut BTreeMap<FeatureId, f64>,
+    ) {
+        // Neither x nor the background reaches here, so nothing below can contribute.
+        if zero_fraction == 0.0 && one_fraction == 0.0 {
+            return;
+        }
+        extend_path(&mut path, zero_fraction, one_fraction, fid);
+        let s = match self.nodes[node].split.as_ref() {
+            Some(s) => s,
+            None => {
+                for i in 1..path.len() {
+                    let w = unwound_path_sum(&path, i);
+                    let el = &path[i];
+                    let fid = el.fid.expect("Only the root has no feature.");
+                    *phi.entry(fid).or_default() +=
+                        w * (el.one_fraction - el.zero_fraction) * self.nodes[node].value;
+                }
+                return;
+            }
+        };
+
+        let value = dataset.get_feature_value(id, s.fid);
+        let (hot, cold) = if TreeNode::goes_left(value, s.split, s.missing_left) {
+            (s.lhs, s.rhs)
+        } else {
+            (s.rhs, s.lhs)
+        };
+        // A feature already on the path is undone first, so it appears once.
+        let mut incoming_zero = 1.0;
+        let mut incoming_one = 1.0;
+        if let Some(k) = path.iter().position(|el| el.fid == Some(s.fid)) {
+            incoming_zero = path[k].zero_fraction;
+            incoming_one = path[k].one_fraction;
+            unwind_path(&mut path, k);
+        }
+        self.recurse(
+            dataset,
+            id,
+            hot,
+            path.clone(),
+            self.fraction(hot, node) * incoming_zero,
+            incoming_one,
+            Some(s.fid),
+            phi,
+        );
+        self.recurse(
+            dataset,
+            id,
+            cold,
+            path,
+            self.fraction(cold, node) * incoming_zero,
+            0.0,
+            Some(s.fid),
+            phi,
+        );
+    }
+}
+
+fn extend_path(
+    path: &mut Vec<PathElement>,
+    zero_fraction: f64,
+    one_fraction: f64,
+    fid: Option<FeatureId>,
+) {
+    let depth = path.len();
+    path.push(PathElement {
+        fid,
+        zero_fraction,
+        one_fraction,
+        weight: if depth == 0 { 1.0 } else { 0.0 },
+    });
+    let scale = (depth + 1) as f64;
+    for i in (0..depth).rev() {
+        path[i + 1].weight += one_fraction * path[i].weight * ((i + 1) as f64) / scale;
+        path[i].weight = zero_fraction * path[i].weight * ((depth - i) as f64) / scale;
+    }
+}
+
+fn unwind_path(path: &mut Vec<PathElement>, index: usize) {
+    let depth = path.len() - 1;
+    let one_fraction = path[index].one_fraction;
+    let zero_fraction = path[index].zero_fraction;
+    let scale = (depth + 1) as f64;
+    let mut next_one_portion = path[depth].weight;
+    for i in (0..depth).rev() {
+        if one_fraction != 0.0 {
+            let tmp = path[i].weight;
+            path[i].weight = next_one_portion * scale / (((i + 1) as f64) * one_fraction);
+            next_one_portion = tmp - path[i].weight * zero_fraction * ((depth - i) as f64) / scale;
+        } else {
+            path[i].weight = path[i].weight * scale / (zero_fraction * ((depth - i) as f64));
+        }
+    }
+    // Weights stay where they are; only the features shift down.
+    for i in index..depth {
+        path[i].fid = path[i + 1].fid;
+        path[i].zero_fraction = path[i + 1].zero_fraction;
+        path[i].one_fraction = path[i + 1].one_fraction;
+    }
+    path.pop();
+}
+
+fn unwound_path_sum(path: &[PathElement], index: usize) -> f64 {
+    let depth = path.len() - 1;
+    let one_fraction = path[index].one_fraction;
+    let zero_fraction = path[index].zero_fraction;
+    let scale = (depth + 1) as f64;
+    let mut next_one_portion = path[depth].weight;
+    let mut total = 0.0;
+    for i in (0..depth).rev() {
+        if one_fraction != 0.0 {
+            let tmp = next_one_portion * scale / (((i + 1) as f64) * one_fraction);
+            total += tmp;
+            next_one_portion = path[i].weight - tmp * zero_fraction * ((depth - i) as f64) / scale;
+        } else if zero_fraction != 0.0 {
+            total += path[i].weight / zero_fraction / (((depth - i) as f64) / scale);
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+    use crate::instance::{Features, Instance};
+    use crate::model::DenseLinearRankingModel;
+    use crate::random_forest::{self, RandomForestParams};
+
+    fn assert_sums_to_score(explanations: &[Explanation]) {
+        for e in explanations {
+            let total: f64 = e.expected_value + e.contributions.values().sum::<f64>();
+            assert!((total - e.score).abs() < 1e-9, "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn test_tree_shap() {
+        // y = 2 when both features are on; the two features share the credit equally.
+        let training_instances: Vec<Instance> = [(0.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0)]
+            .iter()
+            .map(|(a, b)| {
+                let gain = NotNan::new(if *a + *b > 1.5 { 2.0 } else { 0.0 }).unwrap();
+                Instance::new(gain, "q".to_string(), None, Features::Dense32(vec![*a, *b]))
+            })
+            .collect();
+        let dataset = DatasetRef::new(training_instances, None).unwrap();
+        let leaf = |y: f64| Box::new(TreeNode::LeafNode(NotNan::new(y).unwrap()));
+        let split = |fid: usize, lhs: Box<TreeNode>, rhs: Box<TreeNode>| TreeNode::FeatureSplit {
+            fid: FeatureId::from_index(fid),
+            split: NotNan::new(0.5).unwrap(),
+            lhs,
+            rhs,
+            missing_left: None,
+            gain: 0.0,
+        };
+        let tree = ModelEnum::DecisionTree(split(
+            0,
+            leaf(0.0),
+            Box::new(split(1, leaf(0.0), leaf(2.0))),
+        ));
+        let both = *dataset.instances().last().unwrap();
+        let e = &explain(&tree, &dataset, &[both]).unwrap()[0];
+        assert!((e.expected_value - 0.5).abs() < 1e-9);
+        assert!((e.contributions["0"] - 0.75).abs() < 1e-9);
+        assert!((e.contributions["1"] - 0.75).abs() < 1e-9);
+
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let ids: Vec<InstanceId> = dataset.instances().into_iter().take(20).collect();
+        let forest = random_forest::learn_ensemble(
+            &RandomForestParams {
+                num_trees: 5,
+                seed: 42,
+                quiet: true,
+                ..RandomForestParams::default()
+            },
+            &dataset,
+            &crate::evaluators::SetEvaluator::create(&dataset, "ndcg", None).unwrap(),
+            None,
+        )
+        .unwrap();
+        assert_sums_to_score(&explain(&ModelEnum::Ensemble(forest), &dataset, &ids).unwrap());
+        let linear = ModelEnum::Linear(DenseLinearRankingModel {
+            weights: vec![0.0, 0.5, -1.0, 0.25, 2.0, 1.0, 0.1],
+        });
+        assert_sums_to_score(&explain(&linear, &dataset, &ids).unwrap());
+    }
+}
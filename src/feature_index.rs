@@ -0,0 +1,169 @@
+use crate::dataset::RankingDataset;
+use crate::monotone::Monotonicity;
+use crate::{FeatureId, InstanceId};
+use ordered_float::NotNan;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// One feature's value for every training instance, and those instances in ascending order.
+#[derive(Debug, Clone)]
+pub struct FeatureColumn {
+    /// Indexed by InstanceId; missing values read as 0.0 unless checked with `is_present`.
+    values: Vec<f64>,
+    present: Vec<bool>,
+    /// Ties are broken by InstanceId so the order never depends on thread scheduling.
+    sorted: Vec<InstanceId>,
+    /// Quantile bin edges; empty unless requested with `FeatureIndex::with_bins`.
+    bins: Vec<NotNan<f64>>,
+}
+
+impl FeatureColumn {
+    fn new(
+        dataset: &dyn RankingDataset,
+        fid: FeatureId,
+        instances: &[InstanceId],
+        n: usize,
+    ) -> Self {
+        let mut values = vec![0.0; n];
+        let mut present = vec![false; n];
+        for id in instances.iter() {
+            if let Some(val) = dataset.get_feature_value(*id, fid) {
+                values[id.to_index()] = val;
+                present[id.to_index()] = true;
+            }
+        }
+        let mut sorted = instances.to_vec();
+        sorted.sort_unstable_by_key(|id| {
+            (
+                NotNan::new(values[id.to_index()]).expect("NaN feature value"),
+                *id,
+            )
+        });
+        Self {
+            values,
+            present,
+            sorted,
+            bins: Vec::new(),
+        }
+    }
+
+    pub fn value(&self, id: InstanceId) -> f64 {
+        self.values[id.to_index()]
+    }
+    pub fn is_present(&self, id: InstanceId) -> bool {
+        self.present[id.to_index()]
+    }
+    pub fn sorted(&self) -> &[InstanceId] {
+        &self.sorted
+    }
+    pub fn bins(&self) -> &[NotNan<f64>] {
+        &self.bins
+    }
+
+    fn compute_bins(&mut self, num_bins: u32) {
+        let n = self.sorted.len();
+        let mut edges: Vec<NotNan<f64>> = Vec::new();
+        for bin in 1..(num_bins as usize) {
+            let below = self.value(self.sorted[(bin * n) / (num_bins as usize)]);
+            // Put the edge between this value and the next larger one, so ties share a bin.
+            let next = self.sorted.partition_point(|id| self.value(*id) <= below);
+            if next < n {
+                let edge = (below + self.value(self.sorted[next])) / 2.0;
+                edges.push(NotNan::new(edge).expect("NaN bin edge"));
+            }
+        }
+        edges.dedup();
+        self.bins = edges;
+    }
+}
+
+/// A column-oriented, presorted copy of a dataset's features.
+/// Tree learners build it once per training run, so no node has to re-read or re-sort values.
+#[derive(Debug, Clone)]
+pub struct FeatureIndex {
+    num_rows: usize,
+    columns: HashMap<FeatureId, FeatureColumn>,
+    monotone: HashMap<FeatureId, Monotonicity>,
+}
+
+impl FeatureIndex {
+    pub fn new(dataset: &dyn RankingDataset) -> Self {
+        let instances = dataset.instances();
+        let num_rows = instances
+            .iter()
+            .max()
+            .map(|id| id.to_index() + 1)
+            .unwrap_or(0);
+        let columns = dataset
+            .features()
+            .into_par_iter()
+            .map(|fid| (fid, FeatureColumn::new(dataset, fid, &instances, num_rows)))
+            .collect();
+        Self {
+            num_rows,
+            columns,
+            monotone: HashMap::new(),
+        }
+    }
+
+    /// Quantize every feature into `num_bins` quantile buckets.
+    pub fn with_bins(mut self, num_bins: u32) -> Self {
+        self.columns
+            .par_iter_mut()
+            .for_each(|(_, column)| column.compute_bins(num_bins));
+        self
+    }
+
+    /// Constrain the direction in which trees grown from this index may respond to features.
+    pub fn with_monotone(mut self, monotone: HashMap<FeatureId, Monotonicity>) -> Self {
+        self.monotone = monotone;
+        self
+    }
+
+    pub fn monotone(&self) -> &HashMap<FeatureId, Monotonicity> {
+        &self.monotone
+    }
+
+    /// One more than the largest InstanceId covered.
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    pub fn column(&self, fid: FeatureId) -> Option<&FeatureColumn> {
+        self.columns.get(&fid)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset::DatasetRef;
+    use crate::instance::{Features, Instance};
+
+    #[test]
+    fn test_columns_are_sorted_and_binned() {
+        let xs = [3.0, 1.0, 2.0, 1.0, 0.0, 7.0];
+        let instances: Vec<Instance> = xs
+            .iter()
+            .map(|x| {
+                Instance::new(
+                    NotNan::new(0.0).unwrap(),
+                    "query".to_string(),
+                    None,
+                    Features::Dense32(vec![*x]),
+                )
+            })
+            .collect();
+        let dataset = DatasetRef::new(instances, None).unwrap();
+        let index = FeatureIndex::new(&dataset).with_bins(3);
+        let column = index.column(FeatureId::from_index(0)).unwrap();
+
+        let sorted: Vec<f64> = column.sorted().iter().map(|id| column.value(*id)).collect();
+        assert_eq!(vec![0.0, 1.0, 1.0, 2.0, 3.0, 7.0], sorted);
+        // Ties keep InstanceId order.
+        assert!(column.sorted()[1] < column.sorted()[2]);
+        let bins: Vec<f64> = column.bins().iter().map(|b| b.into_inner()).collect();
+        assert_eq!(vec![1.5, 5.0], bins);
+        assert_eq!(6, index.num_rows());
+    }
+}
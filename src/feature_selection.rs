@@ -0,0 +1,243 @@
+use crate::dataset::{DatasetRef, RankingDataset};
+use crate::error::{self, FastRankError};
+use crate::evaluators::SetEvaluator;
+use crate::json_api::{self, TrainRequest};
+use crate::sampling::DatasetSampling;
+use crate::validation::holdout_split;
+use crate::FeatureId;
+use oorandom::Rand64;
+use rayon::prelude::*;
+use std::error::Error;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SelectionDirection {
+    /// Start from nothing and add the most helpful feature each step.
+    Forward,
+    /// Start from every feature and remove the least useful one each step.
+    Backward,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FeatureSelectionRequest {
+    pub train_request: TrainRequest,
+    pub direction: SelectionDirection,
+    /// Names or numbers of the features to choose among; every feature when None.
+    pub features: Option<Vec<String>>,
+    /// Fraction of the training queries to score on when no validation dataset is given.
+    pub fraction: f64,
+    pub seed: u64,
+    /// Stop after this many steps in a row fail to improve on the best score.
+    pub patience: Option<u32>,
+}
+
+impl Default for FeatureSelectionRequest {
+    fn default() -> Self {
+        let mut rand = Rand64::new(0xdeadbeef);
+        Self {
+            train_request: TrainRequest::default(),
+            direction: SelectionDirection::Forward,
+            features: None,
+            fraction: 0.2,
+            seed: rand.rand_u64(),
+            patience: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SelectionStep {
+    /// The feature added or removed this step; None for the starting set of a backward search.
+    pub changed: Option<String>,
+    /// The features used after this step.
+    pub features: Vec<String>,
+    /// The measure on the validation queries.
+    pub score: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeatureSelectionResult {
+    pub measure: String,
+    /// One entry per step, in order.
+    pub trajectory: Vec<SelectionStep>,
+    /// The best-scoring feature set seen, by name.
+    pub selected: Vec<String>,
+    pub score: f64,
+}
+
+fn score_features(
+    request: &FeatureSelectionRequest,
+    train: &DatasetRef,
+    evaluator: &SetEvaluator,
+    features: &[FeatureId],
+) -> Result<f64, Box<dyn Error>> {
+    let subset = train.with_features(features)?.into_ref();
+    let model = json_api::do_training(request.train_request.clone(), &subset, None)?;
+    Ok(evaluator.evaluate_mean(&model))
+}
+
+/// Greedily add or remove one feature at a time, retraining for every candidate.
+/// Without a validation dataset, a fraction of the training queries is held out to score on.
+pub fn select_features(
+    request: &FeatureSelectionRequest,
+    dataset: &DatasetRef,
+    validation: Option<&DatasetRef>,
+) -> Result<FeatureSelectionResult, Box<dyn Error>> {
+    let pool: Vec<FeatureId> = match request.features.as_ref() {
+        Some(names) => names
+            .iter()
+            .map(|name| dataset.try_lookup_feature(name))
+            .collect::<Result<_, _>>()?,
+        None => dataset.features(),
+    };
+    if pool.is_empty() {
+        Err(FastRankError::EmptyDataset(
+            "No features to select among.".to_owned(),
+        ))?;
+    }
+    let (train, test) = match validation {
+        Some(v) => (dataset.clone(), v.clone()),
+        None => holdout_split(dataset, request.fraction, request.seed)?,
+    };
+    let measure = request.train_request.measure.as_str();
+    let evaluator = SetEvaluator::create(&test, measure, request.train_request.judgments.clone())?;
+    let names = |fids: &[FeatureId]| -> Vec<String> {
+        fids.iter().map(|fid| dataset.feature_name(*fid)).collect()
+    };
+
+    let mut trajectory = Vec::new();
+    let (mut current, mut remaining) = match request.direction {
+        SelectionDirection::Forward => (Vec::new(), pool),
+        SelectionDirection::Backward => {
+            let score = score_features(request, &train, &evaluator, &pool)?;
+            trajectory.push(SelectionStep {
+                changed: None,
+                features: names(&pool),
+                score,
+            });
+            (pool.clone(), pool)
+        }
+    };
+    let mut best: Option<(usize, f64)> = trajectory.first().map(|step| (0, step.score));
+    let mut since_best = 0;
+
+    loop {
+        let candidates: Vec<(FeatureId, Vec<FeatureId>)> = match request.direction {
+            SelectionDirection::Forward => remaining
+                .iter()
+                .map(|fid| {
+                    let mut next = current.clone();
+                    next.push(*fid);
+                    (*fid, next)
+                })
+                .collect(),
+            // Never remove the last feature; there would be nothing to train on.
+            SelectionDirection::Backward if current.len() > 1 => current
+                .iter()
+                .map(|fid| {
+                    (
+                        *fid,
+                        current.iter().filter(|f| *f != fid).cloned().collect(),
+                    )
+                })
+                .collect(),
+            SelectionDirection::Backward => Vec::new(),
+        };
+        if candidates.is_empty() {
+            break;
+        }
+
+        let scores: Vec<f64> = candidates
+            .par_iter()
+            .map(|(_, features)| {
+                score_features(request, &train, &evaluator, features).map_err(error::into_send)
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|e| e as Box<dyn Error>)?;
+        // Ties go to the candidate listed first.
+        let mut choice = 0;
+        for (i, score) in scores.iter().enumerate() {
+            if *score > scores[choice] {
+                choice = i;
+            }
+        }
+        let (changed, features) = candidates[choice].clone();
+        remaining.retain(|fid| *fid != changed);
+        current = features;
+        trajectory.push(SelectionStep {
+            changed: Some(dataset.feature_name(changed)),
+            features: names(&current),
+            score: scores[choice],
+        });
+
+        match best {
+            Some((_, score)) if score >= scores[choice] => since_best += 1,
+            _ => {
+                best = Some((trajectory.len() - 1, scores[choice]));
+                since_best = 0;
+            }
+        }
+        if let Some(patience) = request.patience {
+            if since_best >= patience {
+                break;
+            }
+        }
+    }
+
+    let (best_step, score) = best.expect("At least one feature set was scored.");
+    Ok(FeatureSelectionResult {
+        measure: evaluator.name(),
+        selected: trajectory[best_step].features.clone(),
+        trajectory,
+        score,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coordinate_ascent::CoordinateAscentParams;
+    use crate::dataset;
+    use crate::json_api::FastRankModelParams;
+
+    #[test]
+    fn test_select_features() {
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let mut request = FeatureSelectionRequest {
+            train_request: TrainRequest {
+                measure: "ndcg@5".to_owned(),
+                params: FastRankModelParams::CoordinateAscent(CoordinateAscentParams {
+                    num_restarts: 1,
+                    num_max_iterations: 5,
+                    seed: 42,
+                    quiet: true,
+                    ..CoordinateAscentParams::default()
+                }),
+                ..TrainRequest::default()
+            },
+            features: Some(vec!["1".to_owned(), "4".to_owned(), "5".to_owned()]),
+            seed: 42,
+            ..FeatureSelectionRequest::default()
+        };
+
+        let forward = select_features(&request, &dataset, None).unwrap();
+        assert_eq!(3, forward.trajectory.len());
+        for (i, step) in forward.trajectory.iter().enumerate() {
+            assert_eq!(i + 1, step.features.len());
+        }
+        let best = forward
+            .trajectory
+            .iter()
+            .map(|step| step.score)
+            .fold(f64::MIN, f64::max);
+        assert_eq!(best, forward.score);
+
+        request.direction = SelectionDirection::Backward;
+        let backward = select_features(&request, &dataset, None).unwrap();
+        assert_eq!(3, backward.trajectory.len());
+        assert_eq!(None, backward.trajectory[0].changed);
+        assert_eq!(1, backward.trajectory[2].features.len());
+    }
+}
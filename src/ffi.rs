@@ -1,21 +1,31 @@
 use libc::{c_char, c_void};
-use serde_json;
 use std::error::Error;
 use std::ffi::CStr;
 use std::ffi::CString;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+use std::sync::Arc;
 
-use crate::coordinate_ascent::CoordinateAscentParams;
+use crate::cross_validation::{self, CrossValidationRequest};
 use crate::dataset;
 use crate::dataset::DatasetRef;
 use crate::dataset::RankingDataset;
+use crate::dataset::WeightedDatasetRef;
+use crate::dense_dataset::{BorrowedSlice, DenseDataset, FeatureValues, MatrixOrder, Values};
+use crate::dependence::{self, PartialDependenceRequest};
+use crate::error::{self, FastRankError, Panic};
 use crate::evaluators::SetEvaluator;
+use crate::explain;
+use crate::feature_selection::{self, FeatureSelectionRequest};
+use crate::importance;
 use crate::json_api;
 use crate::json_api::{FastRankModelParams, TrainRequest};
 use crate::model::ModelEnum;
+use crate::permutation::{self, PermutationRequest};
 use crate::qrel::QuerySetJudgments;
-use crate::random_forest::RandomForestParams;
 use crate::sampling::DatasetSampling;
-use crate::FeatureId;
+use crate::tuning::{self, TuningRequest};
+use crate::{FeatureId, InstanceId};
 
 use crate::{CDataset, CModel, CQRel, CResult};
 
@@ -23,9 +33,47 @@ use crate::{CDataset, CModel, CQRel, CResult};
 #[derive(Serialize, Deserialize)]
 struct ErrorMessage {
     error: String,
+    /// Machine-readable kind of error, e.g., "parse" or "missing_feature"; see `error::error_code`.
+    code: String,
     context: String,
 }
 
+impl ErrorMessage {
+    fn from_error(e: &(dyn Error + 'static)) -> Self {
+        Self {
+            error: "error".to_owned(),
+            code: error::error_code(e).to_owned(),
+            context: format!("{}", e),
+        }
+    }
+    fn unknown_query(error: &str, query: &str) -> Self {
+        Self {
+            error: error.to_owned(),
+            code: "invalid_parameter".to_owned(),
+            context: query.to_owned(),
+        }
+    }
+}
+
+/// Run the body of an FFI entry point; a panic becomes an error instead of unwinding into C.
+pub(crate) fn catch_panic<T>(
+    body: impl FnOnce() -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned());
+        Err(Box::new(Panic(message)))
+    })
+}
+
+/// Like `catch_panic`, for entry points with no way to report an error, e.g., the `free_*` functions.
+pub(crate) fn ignore_panic(body: impl FnOnce()) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(body));
+}
+
 /// Accept a string parameter!
 pub(crate) fn accept_str(name: &str, input: *const c_void) -> Result<&str, Box<dyn Error>> {
     if input.is_null() {
@@ -46,11 +94,8 @@ pub(crate) fn return_string(output: &str) -> *const c_void {
 pub(crate) fn result_to_json(rust_result: Result<String, Box<dyn Error>>) -> *const c_void {
     let output = match rust_result {
         Ok(response) => response,
-        Err(e) => serde_json::to_string(&ErrorMessage {
-            error: "error".to_string(),
-            context: format!("{:?}", e),
-        })
-        .expect("Error serialization should succeed."),
+        Err(e) => serde_json::to_string(&ErrorMessage::from_error(e.as_ref()))
+            .expect("Error serialization should succeed."),
     };
     return_string(&output)
 }
@@ -63,11 +108,8 @@ pub(crate) fn result_to_c<T>(rust_result: Result<T, Box<dyn Error>>) -> *const C
             c_result.success = Box::into_raw(output) as *const c_void;
         }
         Err(e) => {
-            let error_message = serde_json::to_string(&ErrorMessage {
-                error: "error".to_string(),
-                context: format!("{:?}", e),
-            })
-            .unwrap();
+            let error_message =
+                serde_json::to_string(&ErrorMessage::from_error(e.as_ref())).unwrap();
             c_result.error_message = return_string(&error_message);
         }
     };
@@ -110,11 +152,107 @@ pub(crate) fn result_load_ranksvm_format(
 ) -> Result<DatasetRef, Box<dyn Error>> {
     let feature_names = feature_names_path
         .transpose()?
-        .map(|path| dataset::load_feature_names_json(path))
+        .map(dataset::load_feature_names_json)
         .transpose()?;
     let data_path: &str = data_path?;
-    Ok(DatasetRef::load_libsvm(data_path, feature_names.as_ref())
-        .map_err(|e| format!("{}: {:?}", data_path, e))?)
+    DatasetRef::load_libsvm(data_path, feature_names.as_ref())
+}
+
+/// How to read the raw arrays passed to `make_dense_dataset`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct DenseDatasetSpec {
+    pub(crate) n: usize,
+    pub(crate) d: usize,
+    /// "float32" or "float64".
+    pub(crate) x_dtype: String,
+    pub(crate) order: MatrixOrder,
+    /// "int32" or "int64".
+    pub(crate) qid_dtype: String,
+    /// When qids are positions in this list, e.g., string qids run through `numpy.unique`.
+    pub(crate) qid_names: Option<Vec<String>>,
+    /// Copy X now rather than borrowing it until `release(owner)`.
+    pub(crate) copy: bool,
+    /// One per column, for looking up features by name.
+    pub(crate) feature_names: Option<Vec<String>>,
+    /// One per row, for trecrun output.
+    pub(crate) docids: Option<Vec<String>>,
+}
+
+/// Copy or borrow `x` by the spec; y, qids and weights are short, so they are always copied.
+pub(crate) fn result_make_dense_dataset(
+    spec: Result<DenseDatasetSpec, Box<dyn Error>>,
+    x: *const c_void,
+    y: *const f64,
+    qids: *const c_void,
+    weights: *const f64,
+    owner: *const c_void,
+    release: Option<extern "C" fn(*const c_void)>,
+) -> Result<DatasetRef, Box<dyn Error>> {
+    let spec = spec?;
+    if x.is_null() || y.is_null() || qids.is_null() {
+        Err(FastRankError::InvalidParameter(
+            "X, y and qid pointers are required.".to_owned(),
+        ))?;
+    }
+    let len = spec.n.checked_mul(spec.d).ok_or_else(|| {
+        FastRankError::InvalidParameter(format!(
+            "A {}x{} feature matrix is too large.",
+            spec.n, spec.d
+        ))
+    })?;
+    let xs = match (spec.x_dtype.as_str(), spec.copy) {
+        ("float32", true) => {
+            let xs = unsafe { slice::from_raw_parts(x as *const f32, len) };
+            FeatureValues::F32(Values::Owned(xs.into()))
+        }
+        ("float64", true) => {
+            let xs = unsafe { slice::from_raw_parts(x as *const f64, len) };
+            FeatureValues::F64(Values::Owned(xs.into()))
+        }
+        ("float32", false) => FeatureValues::F32(Values::Borrowed(Arc::new(unsafe {
+            BorrowedSlice::new(x as *const f32, len, owner, release)
+        }))),
+        ("float64", false) => FeatureValues::F64(Values::Borrowed(Arc::new(unsafe {
+            BorrowedSlice::new(x as *const f64, len, owner, release)
+        }))),
+        (other, _) => Err(FastRankError::InvalidParameter(format!(
+            "Features must be float32 or float64, not {}",
+            other
+        )))?,
+    };
+    // Release now if X was copied; after this point the dataset owns the borrow.
+    if spec.copy {
+        if let Some(release) = release {
+            release(owner);
+        }
+    }
+    let ys = unsafe { slice::from_raw_parts(y, spec.n) }.to_vec();
+    let qids: Vec<i64> = match spec.qid_dtype.as_str() {
+        "int32" => unsafe { slice::from_raw_parts(qids as *const i32, spec.n) }
+            .iter()
+            .map(|q| i64::from(*q))
+            .collect(),
+        "int64" => unsafe { slice::from_raw_parts(qids as *const i64, spec.n) }.to_vec(),
+        other => Err(FastRankError::InvalidParameter(format!(
+            "qids must be int32 or int64, not {}",
+            other
+        )))?,
+    };
+    let mut dataset = DenseDataset::try_new(spec.n, spec.d, xs, spec.order, ys, &qids)?;
+    if let Some(names) = spec.qid_names {
+        dataset = dataset.with_query_names(names)?;
+    }
+    if let Some(names) = spec.feature_names {
+        dataset = dataset.with_feature_names(names)?;
+    }
+    if let Some(docids) = spec.docids {
+        dataset = dataset.with_document_names(docids)?;
+    }
+    if !weights.is_null() {
+        dataset =
+            dataset.with_weights(unsafe { slice::from_raw_parts(weights, spec.n) }.to_vec())?;
+    }
+    Ok(dataset.into_ref())
 }
 
 pub(crate) fn result_dataset_query_sampling(
@@ -130,6 +268,23 @@ pub(crate) fn result_dataset_query_sampling(
     Ok(dataset.reference.with_queries(&queries).into_ref())
 }
 
+pub(crate) fn result_dataset_with_weights(
+    dataset: Option<&CDataset>,
+    instance_weights_path: Option<Result<&str, Box<dyn Error>>>,
+    query_weights_path: Option<Result<&str, Box<dyn Error>>>,
+) -> Result<DatasetRef, Box<dyn Error>> {
+    let dataset = require_pointer("Dataset", dataset)?;
+    let instance_weights = match instance_weights_path {
+        Some(path) => Some(dataset::load_instance_weights(path?)?),
+        None => None,
+    };
+    let query_weights = match query_weights_path {
+        Some(path) => Some(dataset::load_query_weights(path?)?),
+        None => None,
+    };
+    Ok(WeightedDatasetRef::new(&dataset.reference, instance_weights, query_weights)?.into_ref())
+}
+
 pub(crate) fn result_dataset_feature_sampling(
     dataset: Option<&CDataset>,
     feature_json_list: Result<&str, Box<dyn Error>>,
@@ -153,7 +308,13 @@ pub(crate) fn result_dataset_query_json(
     };
 
     let response = match query_str? {
-        "is_sampled" => if dataset.reference.is_sampled() { "true".to_string() } else { "false".to_string() },
+        "is_sampled" => {
+            if dataset.reference.is_sampled() {
+                "true".to_string()
+            } else {
+                "false".to_string()
+            }
+        }
         "num_features" => serde_json::to_string(&dataset.reference.n_dim())?,
         "feature_ids" => serde_json::to_string(&dataset.reference.features())?,
         "num_instances" => serde_json::to_string(&dataset.reference.instances().len())?,
@@ -168,10 +329,10 @@ pub(crate) fn result_dataset_query_json(
                 .collect::<Vec<_>>();
             serde_json::to_string(&names)?
         }
-        other => serde_json::to_string(&ErrorMessage {
-            error: "unknown_dataset_query_str".to_owned(),
-            context: other.to_owned(),
-        })?,
+        other => serde_json::to_string(&ErrorMessage::unknown_query(
+            "unknown_dataset_query_str",
+            other,
+        ))?,
     };
 
     Ok(response)
@@ -180,12 +341,17 @@ pub(crate) fn result_dataset_query_json(
 pub(crate) fn result_train_model(
     train_request: Result<TrainRequest, Box<dyn Error>>,
     dataset: Option<&CDataset>,
+    validation_dataset: Option<&CDataset>,
 ) -> Result<ModelEnum, Box<dyn Error>> {
     let dataset = match dataset {
         Some(d) => d,
         None => Err("Dataset pointer is null!")?,
     };
-    Ok(json_api::do_training(train_request?, &dataset.reference)?)
+    json_api::do_training(
+        train_request?,
+        &dataset.reference,
+        validation_dataset.map(|v| &v.reference),
+    )
 }
 
 pub(crate) fn result_model_query_json(
@@ -198,10 +364,14 @@ pub(crate) fn result_model_query_json(
     };
     let response = match query_str? {
         "to_json" => serde_json::to_string(&model.actual)?,
-        other => serde_json::to_string(&ErrorMessage {
-            error: "unknown_dataset_query_str".to_owned(),
-            context: other.to_owned(),
-        })?,
+        "feature_importance" => serde_json::to_string(&importance::by_name(
+            importance::feature_importance(&model.actual)?,
+            None,
+        ))?,
+        other => serde_json::to_string(&ErrorMessage::unknown_query(
+            "unknown_dataset_query_str",
+            other,
+        ))?,
     };
 
     Ok(response)
@@ -210,21 +380,18 @@ pub(crate) fn result_model_query_json(
 pub(crate) fn result_exec_json(
     query_str: Result<&str, Box<dyn Error>>,
 ) -> Result<String, Box<dyn Error>> {
-    let response = match query_str? {
-        "coordinate_ascent_defaults" => serde_json::to_string(&TrainRequest {
+    let name = query_str?;
+    let response = match name
+        .strip_suffix("_defaults")
+        .and_then(FastRankModelParams::defaults)
+    {
+        Some(params) => serde_json::to_string(&TrainRequest {
             measure: "ndcg".to_string(),
-            params: FastRankModelParams::CoordinateAscent(CoordinateAscentParams::default()),
+            params,
             judgments: None,
+            validation: None,
         })?,
-        "random_forest_defaults" => serde_json::to_string(&TrainRequest {
-            measure: "ndcg".to_string(),
-            params: FastRankModelParams::RandomForest(RandomForestParams::default()),
-            judgments: None,
-        })?,
-        other => serde_json::to_string(&ErrorMessage {
-            error: "unknown_query_str".to_owned(),
-            context: other.to_owned(),
-        })?,
+        None => serde_json::to_string(&ErrorMessage::unknown_query("unknown_query_str", name))?,
     };
 
     Ok(response)
@@ -252,6 +419,71 @@ pub(crate) fn result_evaluate_by_query(
     Ok(serde_json::to_string(&output)?)
 }
 
+pub(crate) fn result_cross_validate(
+    request: Result<CrossValidationRequest, Box<dyn Error>>,
+    dataset: Option<&CDataset>,
+) -> Result<String, Box<dyn Error>> {
+    let dataset = &require_pointer("Dataset", dataset)?.reference;
+    let result = cross_validation::cross_validate(request?, dataset)?;
+    Ok(serde_json::to_string(&result)?)
+}
+
+pub(crate) fn result_explain(
+    model: Option<&CModel>,
+    dataset: Option<&CDataset>,
+    instances: Result<Vec<InstanceId>, Box<dyn Error>>,
+) -> Result<String, Box<dyn Error>> {
+    let model = &require_pointer("Model", model)?.actual;
+    let dataset = &require_pointer("Dataset", dataset)?.reference;
+    let explanations = explain::explain(model, dataset, &instances?)?;
+    Ok(serde_json::to_string(&explanations)?)
+}
+
+pub(crate) fn result_partial_dependence(
+    request: Result<PartialDependenceRequest, Box<dyn Error>>,
+    model: Option<&CModel>,
+    dataset: Option<&CDataset>,
+) -> Result<String, Box<dyn Error>> {
+    let model = &require_pointer("Model", model)?.actual;
+    let dataset = &require_pointer("Dataset", dataset)?.reference;
+    let result = dependence::partial_dependence(&request?, model, dataset)?;
+    Ok(serde_json::to_string(&result)?)
+}
+
+pub(crate) fn result_permutation_importance(
+    request: Result<PermutationRequest, Box<dyn Error>>,
+    model: Option<&CModel>,
+    dataset: Option<&CDataset>,
+) -> Result<String, Box<dyn Error>> {
+    let model = &require_pointer("Model", model)?.actual;
+    let dataset = &require_pointer("Dataset", dataset)?.reference;
+    let result = permutation::permutation_importance(&request?, model, dataset)?;
+    Ok(serde_json::to_string(&result)?)
+}
+
+pub(crate) fn result_select_features(
+    request: Result<FeatureSelectionRequest, Box<dyn Error>>,
+    dataset: Option<&CDataset>,
+    validation_dataset: Option<&CDataset>,
+) -> Result<String, Box<dyn Error>> {
+    let dataset = &require_pointer("Dataset", dataset)?.reference;
+    let result = feature_selection::select_features(
+        &request?,
+        dataset,
+        validation_dataset.map(|v| &v.reference),
+    )?;
+    Ok(serde_json::to_string(&result)?)
+}
+
+pub(crate) fn result_tune(
+    request: Result<TuningRequest, Box<dyn Error>>,
+    dataset: Option<&CDataset>,
+) -> Result<String, Box<dyn Error>> {
+    let dataset = &require_pointer("Dataset", dataset)?.reference;
+    let result = tuning::tune(request?, dataset)?;
+    Ok(serde_json::to_string(&result)?)
+}
+
 pub(crate) fn result_predict_scores(
     model: Option<&CModel>,
     dataset: Option<&CDataset>,
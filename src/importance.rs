@@ -0,0 +1,169 @@
+use crate::dataset::RankingDataset;
+use crate::error::FastRankError;
+use crate::model::{ModelEnum, TreeNode};
+use crate::FeatureId;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// How much a model relies on one feature.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeatureImportance {
+    /// How many tree splits test this feature.
+    pub splits: u32,
+    /// Total gain of those splits, summed over every tree.
+    pub gain: f64,
+    /// Share of the model's total gain (trees) or absolute weight (linear models); sums to 1.
+    pub importance: f64,
+}
+
+/// Feature importance for trees, linear models, or ensembles of one kind; features the model ignores are left out.
+pub fn feature_importance(
+    model: &ModelEnum,
+) -> Result<BTreeMap<FeatureId, FeatureImportance>, Box<dyn Error>> {
+    let mut out = BTreeMap::new();
+    let mut weights: BTreeMap<FeatureId, f64> = BTreeMap::new();
+    collect(model, &mut out, &mut weights)?;
+    // Split gain and linear weight have no common scale, so neither can be a share of the other.
+    if !out.is_empty() && weights.values().any(|w| *w != 0.0) {
+        Err(FastRankError::InvalidParameter(
+            "Feature importance is not defined for ensembles that mix trees and linear models."
+                .to_owned(),
+        ))?;
+    }
+
+    if !out.is_empty() {
+        // Models saved before splits recorded their gain fall back on split counts.
+        let use_gain = out.values().map(|fi| fi.gain).sum::<f64>() > 0.0;
+        let share = |fi: &FeatureImportance| {
+            if use_gain {
+                fi.gain
+            } else {
+                f64::from(fi.splits)
+            }
+        };
+        let total: f64 = out.values().map(share).sum();
+        for fi in out.values_mut() {
+            fi.importance = share(fi) / total;
+        }
+    } else {
+        let total: f64 = weights.values().map(|w| w.abs()).sum();
+        for (fid, w) in weights {
+            if w == 0.0 {
+                continue;
+            }
+            out.entry(fid).or_default().importance = w.abs() / total;
+        }
+    }
+    Ok(out)
+}
+
+/// Key an importance report by feature name; without a dataset, features are named by number.
+pub fn by_name(
+    importance: BTreeMap<FeatureId, FeatureImportance>,
+    dataset: Option<&dyn RankingDataset>,
+) -> BTreeMap<String, FeatureImportance> {
+    importance
+        .into_iter()
+        .map(|(fid, fi)| {
+            let name = match dataset {
+                Some(d) => d.feature_name(fid),
+                None => format!("{}", fid.to_index()),
+            };
+            (name, fi)
+        })
+        .collect()
+}
+
+fn collect(
+    model: &ModelEnum,
+    out: &mut BTreeMap<FeatureId, FeatureImportance>,
+    weights: &mut BTreeMap<FeatureId, f64>,
+) -> Result<(), Box<dyn Error>> {
+    match model {
+        ModelEnum::SingleFeature(m) => *weights.entry(m.fid).or_default() += m.dir,
+        ModelEnum::Linear(m) => {
+            for (i, w) in m.weights.iter().enumerate() {
+                *weights.entry(FeatureId::from_index(i)).or_default() += w;
+            }
+        }
+        ModelEnum::DecisionTree(tree) => collect_tree(tree, out),
+        ModelEnum::Ensemble(e) => {
+            for (_, m) in e.iter() {
+                collect(m, out, weights)?;
+            }
+        }
+        ModelEnum::MLP(_) => Err(FastRankError::InvalidParameter(
+            "Feature importance is not defined for MLP models.".to_owned(),
+        ))?,
+    }
+    Ok(())
+}
+
+fn collect_tree(tree: &TreeNode, out: &mut BTreeMap<FeatureId, FeatureImportance>) {
+    if let TreeNode::FeatureSplit {
+        fid,
+        lhs,
+        rhs,
+        gain,
+        ..
+    } = tree
+    {
+        let fi = out.entry(*fid).or_default();
+        fi.splits += 1;
+        fi.gain += gain;
+        collect_tree(lhs, out);
+        collect_tree(rhs, out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+    use crate::error;
+    use crate::model::{DenseLinearRankingModel, WeightedEnsemble};
+    use crate::random_forest::{self, RandomForestParams};
+    use crate::Scored;
+
+    #[test]
+    fn test_feature_importance() {
+        let linear = ModelEnum::Linear(DenseLinearRankingModel {
+            weights: vec![0.0, 3.0, -1.0],
+        });
+        let report = by_name(feature_importance(&linear).unwrap(), None);
+        assert_eq!(vec!["1", "2"], report.keys().collect::<Vec<_>>());
+        assert_eq!(0.75, report["1"].importance);
+        assert_eq!(0.25, report["2"].importance);
+
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let params = RandomForestParams {
+            num_trees: 1,
+            seed: 42,
+            ..RandomForestParams::default()
+        };
+        let tree = random_forest::learn_decision_tree(&params, &dataset).unwrap();
+        let report = feature_importance(&ModelEnum::DecisionTree(tree.clone())).unwrap();
+        let splits: u32 = report.values().map(|fi| fi.splits).sum();
+        let total: f64 = report.values().map(|fi| fi.importance).sum();
+        assert_eq!(splits as usize, count_splits(&tree));
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!(report.values().all(|fi| fi.gain > -1e-9));
+
+        let mixed = ModelEnum::Ensemble(WeightedEnsemble::new(vec![
+            Scored::new(1.0, ModelEnum::DecisionTree(tree)),
+            Scored::new(1.0, linear),
+        ]));
+        let err = feature_importance(&mixed).unwrap_err();
+        assert_eq!("invalid_parameter", error::error_code(err.as_ref()));
+    }
+
+    fn count_splits(tree: &TreeNode) -> usize {
+        match tree {
+            TreeNode::LeafNode(_) => 0,
+            TreeNode::FeatureSplit { lhs, rhs, .. } => 1 + count_splits(lhs) + count_splits(rhs),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::FastRankError;
 use crate::libsvm;
 use crate::normalizers::Normalizer;
 use crate::FeatureId;
@@ -13,26 +14,30 @@ impl Features {
     pub fn ids(&self) -> Vec<FeatureId> {
         let mut features: Vec<FeatureId> = Vec::new();
         match self {
-            Features::Dense32(arr) => {
-                features.extend((0..arr.len()).map(|idx| FeatureId::from_index(idx)))
-            }
+            Features::Dense32(arr) => features.extend((0..arr.len()).map(FeatureId::from_index)),
             Features::Sparse32(arr) => features.extend(arr.iter().map(|(idx, _)| *idx)),
         }
         features
     }
-    pub fn apply_normalization(&mut self, normalizer: &Normalizer) {
-        match self {
-            Features::Dense32(arr) => {
-                for (fid, val) in arr.iter_mut().enumerate() {
-                    *val = normalizer.normalize(FeatureId::from_index(fid), *val);
-                }
-            }
-            Features::Sparse32(arr) => {
-                for (fid, val) in arr.iter_mut() {
-                    *val = normalizer.normalize(*fid, *val);
-                }
-            }
-        }
+    pub fn apply_normalization(&mut self, normalizer: &Normalizer) -> Result<(), FastRankError> {
+        *self = self.normalized(normalizer)?;
+        Ok(())
+    }
+    /// A normalized copy, so a failure part-way leaves these features untouched.
+    pub fn normalized(&self, normalizer: &Normalizer) -> Result<Features, FastRankError> {
+        Ok(match self {
+            Features::Dense32(arr) => Features::Dense32(
+                arr.iter()
+                    .enumerate()
+                    .map(|(fid, val)| normalizer.normalize(FeatureId::from_index(fid), *val))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Features::Sparse32(arr) => Features::Sparse32(
+                arr.iter()
+                    .map(|(fid, val)| Ok((*fid, normalizer.normalize(*fid, *val)?)))
+                    .collect::<Result<_, FastRankError>>()?,
+            ),
+        })
     }
 }
 
@@ -101,7 +106,13 @@ impl Instance {
             features,
         }
     }
-    pub fn try_new(libsvm: libsvm::Instance) -> Result<Instance, &'static str> {
+    pub fn try_new(libsvm: libsvm::Instance) -> Result<Instance, FastRankError> {
+        if let Some(f) = libsvm.features.iter().find(|f| f.value.is_nan()) {
+            Err(FastRankError::NaN(format!(
+                "feature {} of a row in qid {:?}",
+                f.idx, libsvm.query
+            )))?;
+        }
         // Convert features to dense representation if it's worthwhile.
         let max_feature = libsvm.features.iter().map(|f| f.idx).max().unwrap_or(1);
         let density = (libsvm.features.len() as f64) / (max_feature as f64);
@@ -123,7 +134,9 @@ impl Instance {
 
         Ok(Instance {
             gain: libsvm.label,
-            qid: libsvm.query.ok_or("Missing qid")?,
+            qid: libsvm
+                .query
+                .ok_or_else(|| FastRankError::Parse("Missing qid".to_owned()))?,
             docid: libsvm.comment,
             features,
         })
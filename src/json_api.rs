@@ -1,20 +1,36 @@
 use crate::io_helper;
 use std::error::Error;
 
+use crate::adarank;
+use crate::adarank::AdaRankParams;
 use crate::coordinate_ascent::CoordinateAscentParams;
 use crate::dataset::{DatasetRef, RankingDataset};
 use crate::evaluators::{RankedInstance, SetEvaluator};
+use crate::lambdamart;
+use crate::lambdamart::LambdaMARTParams;
+use crate::mart;
+use crate::mart::MARTParams;
 use crate::model::ModelEnum;
+use crate::neural;
+use crate::neural::NeuralParams;
 use crate::qrel::QuerySetJudgments;
 use crate::random_forest;
 use crate::random_forest::RandomForestParams;
+use crate::rankboost;
+use crate::rankboost::RankBoostParams;
+use crate::ranksvm;
+use crate::ranksvm::RankSVMParams;
+use crate::validation::{holdout_split, ValidationParams, ValidationSet};
 use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TrainRequest {
     pub measure: String,
     pub params: FastRankModelParams,
     pub judgments: Option<QuerySetJudgments>,
+    /// Select models on held-out queries; without this, learners only see the training measure.
+    #[serde(default)]
+    pub validation: Option<ValidationParams>,
 }
 
 impl Default for TrainRequest {
@@ -23,29 +39,112 @@ impl Default for TrainRequest {
             measure: "ndcg".to_owned(),
             params: FastRankModelParams::CoordinateAscent(CoordinateAscentParams::default()),
             judgments: None,
+            validation: None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum FastRankModelParams {
     CoordinateAscent(CoordinateAscentParams),
     RandomForest(RandomForestParams),
+    LambdaMART(LambdaMARTParams),
+    MART(MARTParams),
+    Neural(NeuralParams),
+    RankSVM(RankSVMParams),
+    AdaRank(AdaRankParams),
+    RankBoost(RankBoostParams),
+}
+
+/// Split the validation queries off the training data, unless a validation dataset was given.
+fn split_validation(
+    dataset: &DatasetRef,
+    validation_dataset: Option<&DatasetRef>,
+    params: Option<&ValidationParams>,
+) -> Result<(DatasetRef, Option<DatasetRef>), Box<dyn Error>> {
+    if let Some(validation) = validation_dataset {
+        return Ok((dataset.clone(), Some(validation.clone())));
+    }
+    let params = match params {
+        Some(p) => p,
+        None => return Ok((dataset.clone(), None)),
+    };
+    let (train, validation) = holdout_split(dataset, params.fraction, params.seed)?;
+    Ok((train, Some(validation)))
+}
+
+impl FastRankModelParams {
+    /// Default params for a learner by its short name, e.g., "random_forest" or "listnet".
+    pub fn defaults(name: &str) -> Option<Self> {
+        Some(match name {
+            "coordinate_ascent" => {
+                FastRankModelParams::CoordinateAscent(CoordinateAscentParams::default())
+            }
+            "random_forest" => FastRankModelParams::RandomForest(RandomForestParams::default()),
+            "lambdamart" => FastRankModelParams::LambdaMART(LambdaMARTParams::default()),
+            "mart" => FastRankModelParams::MART(MARTParams::default()),
+            "ranknet" => FastRankModelParams::Neural(NeuralParams::default()),
+            "listnet" => FastRankModelParams::Neural(NeuralParams::listnet()),
+            "ranksvm" => FastRankModelParams::RankSVM(RankSVMParams::default()),
+            "adarank" => FastRankModelParams::AdaRank(AdaRankParams::default()),
+            "rankboost" => FastRankModelParams::RankBoost(RankBoostParams::default()),
+            _ => return None,
+        })
+    }
 }
 
 pub fn do_training(
     train_request: TrainRequest,
     dataset: &DatasetRef,
+    validation_dataset: Option<&DatasetRef>,
 ) -> Result<ModelEnum, Box<dyn Error>> {
+    let (dataset, validation_dataset) = split_validation(
+        dataset,
+        validation_dataset,
+        train_request.validation.as_ref(),
+    )?;
+    let dataset = &dataset;
+    let validation = match validation_dataset {
+        Some(validation_dataset) => Some(ValidationSet {
+            evaluator: SetEvaluator::create(
+                &validation_dataset,
+                train_request.measure.as_str(),
+                train_request.judgments.clone(),
+            )?,
+            patience: train_request.validation.as_ref().and_then(|v| v.patience),
+        }),
+        None => None,
+    };
+    let validation = validation.as_ref();
     let evaluator = SetEvaluator::create(
         dataset,
         train_request.measure.as_str(),
         train_request.judgments,
     )?;
     Ok(match train_request.params {
-        FastRankModelParams::CoordinateAscent(params) => params.learn(dataset, &evaluator),
-        FastRankModelParams::RandomForest(params) => {
-            ModelEnum::Ensemble(random_forest::learn_ensemble(&params, dataset, &evaluator))
+        FastRankModelParams::CoordinateAscent(params) => {
+            params.learn(dataset, &evaluator, validation)?
+        }
+        FastRankModelParams::RandomForest(params) => ModelEnum::Ensemble(
+            random_forest::learn_ensemble(&params, dataset, &evaluator, validation)?,
+        ),
+        FastRankModelParams::LambdaMART(params) => ModelEnum::Ensemble(lambdamart::learn_ensemble(
+            &params, dataset, &evaluator, validation,
+        )?),
+        FastRankModelParams::MART(params) => ModelEnum::Ensemble(mart::learn_ensemble(
+            &params, dataset, &evaluator, validation,
+        )?),
+        FastRankModelParams::Neural(params) => {
+            neural::learn(&params, dataset, &evaluator, validation)?
+        }
+        FastRankModelParams::RankSVM(params) => {
+            ranksvm::learn(&params, dataset, &evaluator, validation)?
+        }
+        FastRankModelParams::AdaRank(params) => {
+            adarank::learn(&params, dataset, &evaluator, validation)?
+        }
+        FastRankModelParams::RankBoost(params) => {
+            rankboost::learn(&params, dataset, &evaluator, validation)?
         }
     })
 }
@@ -61,10 +160,7 @@ pub fn predict_scores(
         for index in docs.iter().cloned() {
             let score = dataset.score(index, model);
             // TODO make this an error?
-            scores.insert(
-                index.to_index(),
-                score.into_inner(),
-            );
+            scores.insert(index.to_index(), score.into_inner());
         }
     }
 
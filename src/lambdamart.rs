@@ -0,0 +1,273 @@
+use crate::boosting::{self, BoostingObjective, BoostingParams};
+use crate::dataset::{DatasetRef, RankingDataset};
+use crate::error::FastRankError;
+use crate::evaluators::{RankedInstance, SetEvaluator};
+use crate::model::{TreeNode, WeightedEnsemble};
+use crate::monotone::MonotoneConstraints;
+use crate::random_forest::{MissingValues, SplitFinding};
+use crate::validation::ValidationSet;
+use crate::InstanceId;
+use oorandom::Rand64;
+use ordered_float::NotNan;
+use rayon::prelude::*;
+use std::error::Error;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LambdaMARTParams {
+    pub seed: u64,
+    pub quiet: bool,
+    pub num_trees: u32,
+    pub learning_rate: f64,
+    pub instance_sampling_rate: f64,
+    pub feature_sampling_rate: f64,
+    pub min_leaf_support: u32,
+    pub split_candidates: u32,
+    #[serde(default)]
+    pub split_finding: SplitFinding,
+    #[serde(default)]
+    pub missing_values: MissingValues,
+    #[serde(default)]
+    pub monotone_constraints: MonotoneConstraints,
+    pub max_depth: u32,
+}
+
+impl Default for LambdaMARTParams {
+    fn default() -> Self {
+        let mut rand = Rand64::new(0xdeadbeef);
+        Self {
+            seed: rand.rand_u64(),
+            quiet: false,
+            num_trees: 100,
+            learning_rate: 0.1,
+            instance_sampling_rate: 1.0,
+            feature_sampling_rate: 1.0,
+            min_leaf_support: 1,
+            split_candidates: 32,
+            split_finding: SplitFinding::EvenlySpaced(),
+            missing_values: MissingValues::AsZero(),
+            monotone_constraints: MonotoneConstraints::new(),
+            max_depth: 5,
+        }
+    }
+}
+
+impl LambdaMARTParams {
+    fn boosting(&self) -> BoostingParams {
+        BoostingParams {
+            seed: self.seed,
+            quiet: self.quiet,
+            num_trees: self.num_trees,
+            learning_rate: self.learning_rate,
+            instance_sampling_rate: self.instance_sampling_rate,
+            feature_sampling_rate: self.feature_sampling_rate,
+            min_leaf_support: self.min_leaf_support,
+            split_candidates: self.split_candidates,
+            split_finding: self.split_finding.clone(),
+            missing_values: self.missing_values.clone(),
+            monotone_constraints: self.monotone_constraints.clone(),
+            max_depth: self.max_depth,
+        }
+    }
+}
+
+/// First and second derivatives of the LambdaRank objective, indexed by InstanceId.
+struct Lambdas {
+    lambdas: Vec<f64>,
+    weights: Vec<f64>,
+}
+
+/// Compute lambdas for one query: every pair of differently-labeled documents pushes on each other
+/// in proportion to how much the measure would change if they were swapped.
+fn query_lambdas(
+    qid: &str,
+    docs: &[InstanceId],
+    dataset: &dyn RankingDataset,
+    evaluator: &SetEvaluator,
+    scores: &[f64],
+) -> Result<Vec<(InstanceId, f64, f64)>, FastRankError> {
+    let mut ranked_list: Vec<RankedInstance> = docs
+        .iter()
+        .cloned()
+        .map(|index| {
+            let score = NotNan::new(scores[index.to_index()]).map_err(|_| {
+                FastRankError::NaN(format!("score of instance {}", index.to_index()))
+            })?;
+            Ok(RankedInstance::new(score, dataset.gain(index), index))
+        })
+        .collect::<Result<_, FastRankError>>()?;
+    // Sort largest to smallest:
+    ranked_list.sort_unstable();
+
+    let mut lambdas = vec![0.0; ranked_list.len()];
+    let mut weights = vec![0.0; ranked_list.len()];
+    for i in 0..ranked_list.len() {
+        for j in (i + 1)..ranked_list.len() {
+            let (better, worse) = if ranked_list[i].gain > ranked_list[j].gain {
+                (i, j)
+            } else if ranked_list[j].gain > ranked_list[i].gain {
+                (j, i)
+            } else {
+                continue;
+            };
+            let delta = evaluator.swap_change(qid, &ranked_list, i, j).abs();
+            if delta == 0.0 {
+                continue;
+            }
+            let diff =
+                ranked_list[better].score.into_inner() - ranked_list[worse].score.into_inner();
+            let rho = 1.0 / (1.0 + diff.exp());
+            let lambda = rho * delta;
+            let weight = rho * (1.0 - rho) * delta;
+            lambdas[better] += lambda;
+            lambdas[worse] -= lambda;
+            weights[better] += weight;
+            weights[worse] += weight;
+        }
+    }
+
+    Ok(ranked_list
+        .iter()
+        .enumerate()
+        .map(|(i, ri)| (ri.identifier, lambdas[i], weights[i]))
+        .collect())
+}
+
+fn compute_lambdas(
+    dataset: &DatasetRef,
+    evaluator: &SetEvaluator,
+    scores: &[f64],
+) -> Result<Lambdas, FastRankError> {
+    let by_query: Vec<(String, Vec<InstanceId>)> =
+        dataset.instances_by_query().into_iter().collect();
+    let mut output = Lambdas {
+        lambdas: vec![0.0; scores.len()],
+        weights: vec![0.0; scores.len()],
+    };
+    let per_query: Vec<Vec<(InstanceId, f64, f64)>> = by_query
+        .par_iter()
+        .map(|(qid, docs)| query_lambdas(qid, docs, dataset, evaluator, scores))
+        .collect::<Result<_, _>>()?;
+    for (index, lambda, weight) in per_query.into_iter().flatten() {
+        output.lambdas[index.to_index()] = lambda;
+        output.weights[index.to_index()] = weight;
+    }
+    Ok(output)
+}
+
+/// Replace each leaf's output with a Newton step over the training instances that reach it.
+fn newton_leaves(
+    tree: &mut TreeNode,
+    ids: &[InstanceId],
+    dataset: &dyn RankingDataset,
+    lambdas: &Lambdas,
+) -> Result<(), FastRankError> {
+    match tree {
+        TreeNode::LeafNode(output) => {
+            let mut sum_lambda = 0.0;
+            let mut sum_weight = 0.0;
+            for index in ids.iter() {
+                let weight = dataset.instance_weight(*index)
+                    * dataset.query_weight(dataset.query_id(*index));
+                sum_lambda += weight * lambdas.lambdas[index.to_index()];
+                sum_weight += weight * lambdas.weights[index.to_index()];
+            }
+            let step = if sum_weight > 0.0 {
+                sum_lambda / sum_weight
+            } else {
+                0.0
+            };
+            *output = NotNan::new(step)
+                .map_err(|_| FastRankError::NaN("LambdaMART leaf output".to_owned()))?;
+        }
+        TreeNode::FeatureSplit {
+            fid,
+            split,
+            lhs,
+            rhs,
+            missing_left,
+            ..
+        } => {
+            let (lhs_ids, rhs_ids): (Vec<InstanceId>, Vec<InstanceId>) =
+                ids.iter().partition(|index| {
+                    let value = dataset.get_feature_value(**index, *fid);
+                    TreeNode::goes_left(value, *split, *missing_left)
+                });
+            newton_leaves(lhs, &lhs_ids, dataset, lambdas)?;
+            newton_leaves(rhs, &rhs_ids, dataset, lambdas)?;
+        }
+    }
+    Ok(())
+}
+
+/// LambdaRank gradients of the training measure, with Newton-step leaves.
+struct LambdaObjective<'d> {
+    dataset: &'d DatasetRef,
+    evaluator: &'d SetEvaluator,
+    /// From the latest call to `targets`, for `refit_leaves`.
+    lambdas: Option<Lambdas>,
+}
+
+impl BoostingObjective for LambdaObjective<'_> {
+    fn targets(&mut self, scores: &[f64]) -> Result<Vec<f64>, FastRankError> {
+        let lambdas = compute_lambdas(self.dataset, self.evaluator, scores)?;
+        let targets = lambdas.lambdas.clone();
+        self.lambdas = Some(lambdas);
+        Ok(targets)
+    }
+    fn refit_leaves(&self, tree: &mut TreeNode, ids: &[InstanceId]) -> Result<bool, FastRankError> {
+        let lambdas = self.lambdas.as_ref().expect("targets before refit_leaves");
+        newton_leaves(tree, ids, self.dataset, lambdas)?;
+        Ok(true)
+    }
+}
+
+pub fn learn_ensemble(
+    params: &LambdaMARTParams,
+    dataset: &DatasetRef,
+    evaluator: &SetEvaluator,
+    validation: Option<&ValidationSet>,
+) -> Result<WeightedEnsemble, Box<dyn Error>> {
+    let mut objective = LambdaObjective {
+        dataset,
+        evaluator,
+        lambdas: None,
+    };
+    boosting::learn_ensemble(
+        &params.boosting(),
+        &mut objective,
+        dataset,
+        evaluator,
+        validation,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+
+    #[test]
+    fn test_lambdamart_beats_single_feature() {
+        let feature_names =
+            dataset::load_feature_names_json("examples/trec_news_2018.features.json").unwrap();
+        let train_dataset = dataset::LoadedRankingDataset::load_libsvm(
+            "examples/trec_news_2018.train",
+            Some(&feature_names),
+        )
+        .unwrap()
+        .into_ref();
+        let params = LambdaMARTParams {
+            num_trees: 20,
+            seed: 42,
+            quiet: true,
+            ..LambdaMARTParams::default()
+        };
+
+        let eval = SetEvaluator::create(&train_dataset, "ndcg@5", None).unwrap();
+        let model = learn_ensemble(&params, &train_dataset, &eval, None).unwrap();
+        let again = learn_ensemble(&params, &train_dataset, &eval, None).unwrap();
+        assert_eq!(eval.evaluate_mean(&model), eval.evaluate_mean(&again));
+        // para-fraction alone gets 0.439 NDCG@5 on this data.
+        assert!(eval.evaluate_mean(&model) > 0.5);
+    }
+}
@@ -1,27 +1,59 @@
-#![crate_type = "dylib"]
-
 #[macro_use]
 extern crate serde_derive;
 
 mod core;
-pub(crate) use crate::core::FeatureId;
-pub(crate) use crate::core::InstanceId;
+pub use crate::core::FeatureId;
+pub use crate::core::InstanceId;
 pub(crate) use crate::core::Scored;
 
+/// Contains code for AdaRank, which boosts single-feature models.
+pub mod adarank;
+/// Contains the stable Rust API: load datasets and models, train, score and evaluate.
+pub mod api;
+/// Contains the boosting loop shared by LambdaMART and MART.
+pub mod boosting;
 /// Contains code for feature-at-a-time non-differentiable optimization.
 pub mod coordinate_ascent;
+/// Contains code for k-fold cross-validation over query groups.
+pub mod cross_validation;
 pub mod dataset;
 pub mod dense_dataset;
+/// Contains code for partial dependence and ICE curves.
+pub mod dependence;
+/// Contains the error type for bad input, with codes that survive the trip to Python.
+pub mod error;
 pub mod evaluators;
+/// Contains code for SHAP explanations of individual scores.
+pub mod explain;
+/// Contains code for a presorted, column-oriented copy of training features.
+pub mod feature_index;
+/// Contains code for greedy forward and backward feature selection.
+pub mod feature_selection;
+/// Contains code for reporting which features a model relies on.
+pub mod importance;
 pub mod instance;
 /// Contains code for reading compressed files based on their extension.
 pub mod io_helper;
+/// Contains code for gradient-boosted trees trained on LambdaRank gradients.
+pub mod lambdamart;
 /// Contains code for reading ranklib and libsvm input files.
 pub mod libsvm;
+/// Contains code for pointwise gradient-boosted regression trees.
+pub mod mart;
 pub mod model;
+/// Contains code for constraining models to be monotone in some features.
+pub mod monotone;
+/// Contains code for RankNet and ListNet, trained by gradient descent.
+pub mod neural;
 pub mod normalizers;
+/// Contains code for measuring feature importance by shuffling feature values.
+pub mod permutation;
 pub mod qrel;
 pub mod randutil;
+/// Contains code for RankBoost, which boosts thresholded single-feature stumps.
+pub mod rankboost;
+/// Contains code for a linear pairwise hinge-loss (RankSVM) learner.
+pub mod ranksvm;
 pub mod sampling;
 
 pub mod json_api;
@@ -29,18 +61,26 @@ pub mod json_api;
 pub mod random_forest;
 /// Streaming computation of statistics.
 pub mod stats;
+/// Contains code for grid and random hyperparameter search.
+pub mod tuning;
+/// Contains code for choosing model size and stopping early on held-out queries.
+pub mod validation;
 
+use cross_validation::CrossValidationRequest;
 use dataset::DatasetRef;
-use dense_dataset::DenseDataset;
+use dense_dataset::MatrixOrder;
+use dependence::PartialDependenceRequest;
+use feature_selection::FeatureSelectionRequest;
 use json_api::TrainRequest;
 use model::ModelEnum;
+use permutation::PermutationRequest;
 use qrel::QuerySetJudgments;
+use tuning::TuningRequest;
 
 use libc::{c_char, c_void};
 use std::error::Error;
 use std::ffi::CString;
 use std::ptr;
-use std::slice;
 
 mod ffi;
 use ffi::*;
@@ -75,52 +115,66 @@ impl Default for CResult {
 
 #[no_mangle]
 pub extern "C" fn free_str(originally_from_rust: *mut c_void) {
-    let _will_drop: CString = unsafe { CString::from_raw(originally_from_rust as *mut c_char) };
+    ignore_panic(|| {
+        let _will_drop: CString = unsafe { CString::from_raw(originally_from_rust as *mut c_char) };
+    });
 }
 
 /// Note: not-recursive. Free Error Message Manually!
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn free_c_result(originally_from_rust: *mut CResult) {
-    let _will_drop: Box<CResult> = unsafe { Box::from_raw(originally_from_rust) };
+    ignore_panic(|| {
+        let _will_drop: Box<CResult> = unsafe { Box::from_raw(originally_from_rust) };
+    });
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn free_dataset(originally_from_rust: *mut CDataset) {
-    let _will_drop: Box<CDataset> = unsafe { Box::from_raw(originally_from_rust) };
+    ignore_panic(|| {
+        let _will_drop: Box<CDataset> = unsafe { Box::from_raw(originally_from_rust) };
+    });
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn free_model(originally_from_rust: *mut CModel) {
-    let _will_drop: Box<CModel> = unsafe { Box::from_raw(originally_from_rust) };
+    ignore_panic(|| {
+        let _will_drop: Box<CModel> = unsafe { Box::from_raw(originally_from_rust) };
+    });
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn free_cqrel(originally_from_rust: *mut CQRel) {
-    let _will_drop: Box<CQRel> = unsafe { Box::from_raw(originally_from_rust) };
+    ignore_panic(|| {
+        let _will_drop: Box<CQRel> = unsafe { Box::from_raw(originally_from_rust) };
+    });
 }
 
 #[no_mangle]
 pub extern "C" fn load_cqrel(data_path: *const c_void) -> *const CResult {
-    result_to_c(
-        result_load_cqrel(accept_str("data_path", data_path)).map(|actual| CQRel { actual }),
-    )
+    result_to_c(catch_panic(|| {
+        result_load_cqrel(accept_str("data_path", data_path)).map(|actual| CQRel { actual })
+    }))
 }
 
 #[no_mangle]
 pub extern "C" fn cqrel_from_json(json_str: *const c_void) -> *const CResult {
-    result_to_c(
+    result_to_c(catch_panic(|| {
         deserialize_from_cstr_json::<QuerySetJudgments>(accept_str("json_str", json_str))
-            .map(|actual| CQRel { actual }),
-    )
+            .map(|actual| CQRel { actual })
+    }))
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn cqrel_query_json(cqrel: *const CQRel, query_str: *const c_void) -> *const c_void {
-    let cqrel: Option<&CQRel> = unsafe { (cqrel as *mut CQRel).as_ref() };
-    result_to_json(result_cqrel_query_json(
-        cqrel,
-        accept_str("query_str", query_str),
-    ))
+    result_to_json(catch_panic(|| {
+        let cqrel: Option<&CQRel> = unsafe { cqrel.as_ref() };
+        result_cqrel_query_json(cqrel, accept_str("query_str", query_str))
+    }))
 }
 
 #[no_mangle]
@@ -128,48 +182,76 @@ pub extern "C" fn load_ranksvm_format(
     data_path: *mut c_void,
     feature_names_path: *mut c_void,
 ) -> *const CResult {
-    let data_path = accept_str("data_path", data_path);
-    let feature_names_path: Option<Result<&str, Box<dyn Error>>> = if feature_names_path.is_null() {
-        None
-    } else {
-        Some(accept_str("feature_names_path", feature_names_path))
-    };
-    result_to_c(
+    result_to_c(catch_panic(|| {
+        let data_path = accept_str("data_path", data_path);
+        let feature_names_path: Option<Result<&str, Box<dyn Error>>> =
+            if feature_names_path.is_null() {
+                None
+            } else {
+                Some(accept_str("feature_names_path", feature_names_path))
+            };
         result_load_ranksvm_format(data_path, feature_names_path).map(|response| CDataset {
             reference: response,
-        }),
-    )
+        })
+    }))
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn dataset_query_sampling(
     dataset: *mut CDataset,
     queries_json_list: *const c_void,
 ) -> *const CResult {
-    let dataset: Option<&CDataset> = unsafe { (dataset as *mut CDataset).as_ref() };
-    result_to_c(
+    result_to_c(catch_panic(|| {
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
         result_dataset_query_sampling(dataset, accept_str("queries_json_list", queries_json_list))
             .map(|response| CDataset {
                 reference: response,
-            }),
-    )
+            })
+    }))
+}
+
+/// Attach weights from side files; either path may be NULL.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn dataset_with_weights(
+    dataset: *mut CDataset,
+    instance_weights_path: *const c_void,
+    query_weights_path: *const c_void,
+) -> *const CResult {
+    result_to_c(catch_panic(|| {
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        let instance_weights_path = if instance_weights_path.is_null() {
+            None
+        } else {
+            Some(accept_str("instance_weights_path", instance_weights_path))
+        };
+        let query_weights_path = if query_weights_path.is_null() {
+            None
+        } else {
+            Some(accept_str("query_weights_path", query_weights_path))
+        };
+        result_dataset_with_weights(dataset, instance_weights_path, query_weights_path).map(
+            |response| CDataset {
+                reference: response,
+            },
+        )
+    }))
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn dataset_feature_sampling(
     dataset: *mut CDataset,
     feature_json_list: *const c_void,
 ) -> *const CResult {
-    let dataset: Option<&CDataset> = unsafe { (dataset as *mut CDataset).as_ref() };
-    result_to_c(
-        result_dataset_feature_sampling(
-            dataset,
-            accept_str("feature_json_list", feature_json_list),
-        )
-        .map(|response| CDataset {
-            reference: response,
-        }),
-    )
+    result_to_c(catch_panic(|| {
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        result_dataset_feature_sampling(dataset, accept_str("feature_json_list", feature_json_list))
+            .map(|response| CDataset {
+                reference: response,
+            })
+    }))
 }
 
 #[no_mangle]
@@ -177,18 +259,20 @@ pub extern "C" fn dataset_query_json(
     dataset: *mut c_void,
     json_cmd_str: *mut c_void,
 ) -> *const c_void {
-    let dataset: Option<&CDataset> = unsafe { (dataset as *mut CDataset).as_ref() };
-    result_to_json(result_dataset_query_json(
-        dataset,
-        accept_str("dataset_query_json", json_cmd_str),
-    ))
+    result_to_json(catch_panic(|| {
+        let dataset: Option<&CDataset> = unsafe { (dataset as *mut CDataset).as_ref() };
+        result_dataset_query_json(dataset, accept_str("dataset_query_json", json_cmd_str))
+    }))
 }
 
 #[no_mangle]
 pub extern "C" fn query_json(json_cmd_str: *const c_void) -> *const c_void {
-    result_to_json(result_exec_json(accept_str("query_json_str", json_cmd_str)))
+    result_to_json(catch_panic(|| {
+        result_exec_json(accept_str("query_json_str", json_cmd_str))
+    }))
 }
 
+/// Copies its arrays; see `make_dense_dataset` for weights, other dtypes, F order, or borrowing X.
 #[no_mangle]
 pub extern "C" fn make_dense_dataset_f32_f64_i64(
     n: usize,
@@ -197,15 +281,50 @@ pub extern "C" fn make_dense_dataset_f32_f64_i64(
     y: *const f64,
     qids: *const i64,
 ) -> *const CResult {
-    let x_len = n * d;
-    let x_slice: &'static [f32] = unsafe { slice::from_raw_parts(x, x_len) };
-    let y_slice: &'static [f64] = unsafe { slice::from_raw_parts(y, n) };
-    let qid_slice: &'static [i64] = unsafe { slice::from_raw_parts(qids, n) };
-    result_to_c(
-        DenseDataset::try_new(n, d, x_slice, y_slice, qid_slice).map(|dd| CDataset {
-            reference: dd.into_ref(),
-        }),
-    )
+    result_to_c(catch_panic(|| {
+        let spec = DenseDatasetSpec {
+            n,
+            d,
+            x_dtype: "float32".to_owned(),
+            order: MatrixOrder::C,
+            qid_dtype: "int64".to_owned(),
+            qid_names: None,
+            copy: true,
+            feature_names: None,
+            docids: None,
+        };
+        result_make_dense_dataset(
+            Ok(spec),
+            x as *const c_void,
+            y,
+            qids as *const c_void,
+            ptr::null(),
+            ptr::null(),
+            None,
+        )
+        .map(|reference| CDataset { reference })
+    }))
+}
+
+/// Build a dense dataset from numpy-style arrays, as described by `spec_json`.
+/// Unless the spec asks for a copy, X is borrowed until the dataset is freed, then `release(owner)` is called.
+/// Weights are optional; pass NULL for none.
+#[no_mangle]
+pub extern "C" fn make_dense_dataset(
+    spec_json: *const c_void,
+    x: *const c_void,
+    y: *const f64,
+    qids: *const c_void,
+    weights: *const f64,
+    owner: *const c_void,
+    release: Option<extern "C" fn(*const c_void)>,
+) -> *const CResult {
+    result_to_c(catch_panic(|| {
+        let spec: Result<DenseDatasetSpec, _> =
+            deserialize_from_cstr_json(accept_str("make_dense_dataset", spec_json));
+        result_make_dense_dataset(spec, x, y, qids, weights, owner, release)
+            .map(|reference| CDataset { reference })
+    }))
 }
 
 #[no_mangle]
@@ -213,18 +332,37 @@ pub extern "C" fn train_model(
     train_request_json: *mut c_void,
     dataset: *mut c_void,
 ) -> *const CResult {
-    let dataset: Option<&CDataset> = unsafe { (dataset as *mut CDataset).as_ref() };
-    let request: Result<TrainRequest, _> =
-        deserialize_from_cstr_json(accept_str("train_request_json", train_request_json));
-    result_to_c(result_train_model(request, dataset).map(|actual| CModel { actual }))
+    result_to_c(catch_panic(|| {
+        let dataset: Option<&CDataset> = unsafe { (dataset as *mut CDataset).as_ref() };
+        let request: Result<TrainRequest, _> =
+            deserialize_from_cstr_json(accept_str("train_request_json", train_request_json));
+        result_train_model(request, dataset, None).map(|actual| CModel { actual })
+    }))
+}
+
+/// Like train_model, but selects and stops training on a separate validation dataset.
+#[no_mangle]
+pub extern "C" fn train_model_with_validation(
+    train_request_json: *mut c_void,
+    dataset: *mut c_void,
+    validation_dataset: *mut c_void,
+) -> *const CResult {
+    result_to_c(catch_panic(|| {
+        let dataset: Option<&CDataset> = unsafe { (dataset as *mut CDataset).as_ref() };
+        let validation_dataset: Option<&CDataset> =
+            unsafe { (validation_dataset as *mut CDataset).as_ref() };
+        let request: Result<TrainRequest, _> =
+            deserialize_from_cstr_json(accept_str("train_request_json", train_request_json));
+        result_train_model(request, dataset, validation_dataset).map(|actual| CModel { actual })
+    }))
 }
 
 #[no_mangle]
 pub extern "C" fn model_from_json(json_str: *const c_void) -> *const CResult {
-    result_to_c(
+    result_to_c(catch_panic(|| {
         deserialize_from_cstr_json::<ModelEnum>(accept_str("json_str", json_str))
-            .map(|actual| CModel { actual }),
-    )
+            .map(|actual| CModel { actual })
+    }))
 }
 
 #[no_mangle]
@@ -232,14 +370,14 @@ pub extern "C" fn model_query_json(
     model: *const c_void,
     json_cmd_str: *const c_void,
 ) -> *const c_void {
-    let model: Option<&CModel> = unsafe { (model as *const CModel).as_ref() };
-    result_to_json(result_model_query_json(
-        model,
-        accept_str("query_json", json_cmd_str),
-    ))
+    result_to_json(catch_panic(|| {
+        let model: Option<&CModel> = unsafe { (model as *const CModel).as_ref() };
+        result_model_query_json(model, accept_str("query_json", json_cmd_str))
+    }))
 }
 
 /// returns json of qid->score for evaluator; or error-json.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn evaluate_by_query(
     model: *const CModel,
@@ -247,20 +385,132 @@ pub extern "C" fn evaluate_by_query(
     qrel: *const CQRel,
     evaluator: *const c_void,
 ) -> *const c_void {
-    let model: Option<&CModel> = unsafe { (model as *const CModel).as_ref() };
-    let dataset: Option<&CDataset> = unsafe { (dataset as *const CDataset).as_ref() };
-    let qrel: Option<&CQRel> = unsafe { (qrel as *const CQRel).as_ref() };
-    let evaluator: Result<&str, Box<dyn Error>> = accept_str("evaluator_name", evaluator);
-    result_to_json(result_evaluate_by_query(model, dataset, qrel, evaluator))
+    result_to_json(catch_panic(|| {
+        let model: Option<&CModel> = unsafe { model.as_ref() };
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        let qrel: Option<&CQRel> = unsafe { qrel.as_ref() };
+        let evaluator: Result<&str, Box<dyn Error>> = accept_str("evaluator_name", evaluator);
+        result_evaluate_by_query(model, dataset, qrel, evaluator)
+    }))
+}
+
+/// returns json of a CrossValidationResult; or error-json.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn cross_validate(
+    cross_validation_request_json: *const c_void,
+    dataset: *const CDataset,
+) -> *const c_void {
+    result_to_json(catch_panic(|| {
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        let request: Result<CrossValidationRequest, _> = deserialize_from_cstr_json(accept_str(
+            "cross_validation_request_json",
+            cross_validation_request_json,
+        ));
+        result_cross_validate(request, dataset)
+    }))
+}
+
+/// returns json of a list of Explanations, one per instance id; or error-json.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn explain(
+    model: *const CModel,
+    dataset: *const CDataset,
+    instance_ids_json: *const c_void,
+) -> *const c_void {
+    result_to_json(catch_panic(|| {
+        let model: Option<&CModel> = unsafe { model.as_ref() };
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        let instances: Result<Vec<InstanceId>, _> =
+            deserialize_from_cstr_json(accept_str("instance_ids_json", instance_ids_json));
+        result_explain(model, dataset, instances)
+    }))
+}
+
+/// returns json of a PartialDependence; or error-json.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn partial_dependence(
+    partial_dependence_request_json: *const c_void,
+    model: *const CModel,
+    dataset: *const CDataset,
+) -> *const c_void {
+    result_to_json(catch_panic(|| {
+        let model: Option<&CModel> = unsafe { model.as_ref() };
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        let request: Result<PartialDependenceRequest, _> = deserialize_from_cstr_json(accept_str(
+            "partial_dependence_request_json",
+            partial_dependence_request_json,
+        ));
+        result_partial_dependence(request, model, dataset)
+    }))
+}
+
+/// returns json of a PermutationResult; or error-json.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn permutation_importance(
+    permutation_request_json: *const c_void,
+    model: *const CModel,
+    dataset: *const CDataset,
+) -> *const c_void {
+    result_to_json(catch_panic(|| {
+        let model: Option<&CModel> = unsafe { model.as_ref() };
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        let request: Result<PermutationRequest, _> = deserialize_from_cstr_json(accept_str(
+            "permutation_request_json",
+            permutation_request_json,
+        ));
+        result_permutation_importance(request, model, dataset)
+    }))
+}
+
+/// returns json of a FeatureSelectionResult; or error-json. The validation dataset may be null.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn select_features(
+    feature_selection_request_json: *const c_void,
+    dataset: *const CDataset,
+    validation_dataset: *const CDataset,
+) -> *const c_void {
+    result_to_json(catch_panic(|| {
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        let validation_dataset: Option<&CDataset> = unsafe { validation_dataset.as_ref() };
+        let request: Result<FeatureSelectionRequest, _> = deserialize_from_cstr_json(accept_str(
+            "feature_selection_request_json",
+            feature_selection_request_json,
+        ));
+        result_select_features(request, dataset, validation_dataset)
+    }))
+}
+
+/// returns json of a TuningResult; or error-json.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn tune(
+    tuning_request_json: *const c_void,
+    dataset: *const CDataset,
+) -> *const c_void {
+    result_to_json(catch_panic(|| {
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        let request: Result<TuningRequest, _> =
+            deserialize_from_cstr_json(accept_str("tuning_request_json", tuning_request_json));
+        result_tune(request, dataset)
+    }))
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn predict_scores(model: *const CModel, dataset: *const CDataset) -> *const c_void {
-    let model: Option<&CModel> = unsafe { (model as *const CModel).as_ref() };
-    let dataset: Option<&CDataset> = unsafe { (dataset as *const CDataset).as_ref() };
-    result_to_json(result_predict_scores(model, dataset))
+    result_to_json(catch_panic(|| {
+        let model: Option<&CModel> = unsafe { model.as_ref() };
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        result_predict_scores(model, dataset)
+    }))
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn predict_to_trecrun(
     model: *const CModel,
@@ -269,15 +519,11 @@ pub extern "C" fn predict_to_trecrun(
     system_name: *const c_void,
     depth: usize,
 ) -> *const c_void {
-    let model: Option<&CModel> = unsafe { (model as *const CModel).as_ref() };
-    let dataset: Option<&CDataset> = unsafe { (dataset as *const CDataset).as_ref() };
-    let output_path: Result<&str, Box<dyn Error>> = accept_str("output_path", output_path);
-    let system_name: Result<&str, Box<dyn Error>> = accept_str("system_name", system_name);
-    result_to_json(result_predict_to_trecrun(
-        model,
-        dataset,
-        output_path,
-        system_name,
-        depth,
-    ))
+    result_to_json(catch_panic(|| {
+        let model: Option<&CModel> = unsafe { model.as_ref() };
+        let dataset: Option<&CDataset> = unsafe { dataset.as_ref() };
+        let output_path: Result<&str, Box<dyn Error>> = accept_str("output_path", output_path);
+        let system_name: Result<&str, Box<dyn Error>> = accept_str("system_name", system_name);
+        result_predict_to_trecrun(model, dataset, output_path, system_name, depth)
+    }))
 }
@@ -116,6 +116,12 @@ pub struct Instance {
     pub comment: Option<String>,
 }
 
+impl Default for Instance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Instance {
     /// Constructor. Creates an empty instance with no comment and a negative label.
     pub fn new() -> Instance {
@@ -174,7 +180,7 @@ impl Instance {
 
         // Sort features by index so we have some guarantees about them.
         if needs_sorting {
-            inst.features.sort_unstable_by(|f1, f2| f1.idx.cmp(&f2.idx));
+            inst.features.sort_unstable_by_key(|f1| f1.idx);
             for i in 0..(inst.features.len() - 1) {
                 if inst.features[i].idx == inst.features[i + 1].idx {
                     return Err(ParseError::MultipleDefinitions(
@@ -232,7 +238,7 @@ impl Iterator for InstanceIter {
         match amt_read {
             Ok(amt) => {
                 // All done?
-                if amt <= 0 {
+                if amt == 0 {
                     return None;
                 }
             }
@@ -280,8 +286,8 @@ mod tests {
     /// This calculates the dot product between two instances efficiently, given that their
     /// features are sorted already.
     fn dot_product(lhs: &Instance, rhs: &Instance) -> f32 {
-        let ref a = lhs.features;
-        let ref b = rhs.features;
+        let a = &lhs.features;
+        let b = &rhs.features;
         let mut i = 0;
         let mut j = 0;
 
@@ -315,7 +321,7 @@ mod tests {
 
     /// Compare floats with a given epsilon.
     fn flt_eq(lhs: f32, rhs: f32) -> bool {
-        return (lhs - rhs).abs() < 1e-7;
+        (lhs - rhs).abs() < 1e-7
     }
 
     #[test]
@@ -382,9 +388,6 @@ mod tests {
             };
             false
         }
-        fn ne(&self, other: &ParseError) -> bool {
-            !self.eq(other)
-        }
     }
 
     #[test]
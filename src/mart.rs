@@ -0,0 +1,154 @@
+use crate::boosting::{self, BoostingObjective, BoostingParams};
+use crate::dataset::{DatasetRef, RankingDataset};
+use crate::error::FastRankError;
+use crate::evaluators::SetEvaluator;
+use crate::model::WeightedEnsemble;
+use crate::monotone::MonotoneConstraints;
+use crate::random_forest::{self, MissingValues, SplitFinding};
+use crate::validation::ValidationSet;
+use crate::InstanceId;
+use oorandom::Rand64;
+use std::error::Error;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MARTParams {
+    pub seed: u64,
+    pub quiet: bool,
+    pub num_trees: u32,
+    pub learning_rate: f64,
+    pub instance_sampling_rate: f64,
+    pub feature_sampling_rate: f64,
+    pub min_leaf_support: u32,
+    pub split_candidates: u32,
+    #[serde(default)]
+    pub split_finding: SplitFinding,
+    #[serde(default)]
+    pub missing_values: MissingValues,
+    #[serde(default)]
+    pub monotone_constraints: MonotoneConstraints,
+    pub max_depth: u32,
+}
+
+impl Default for MARTParams {
+    fn default() -> Self {
+        let mut rand = Rand64::new(0xdeadbeef);
+        Self {
+            seed: rand.rand_u64(),
+            quiet: false,
+            num_trees: 100,
+            learning_rate: 0.1,
+            instance_sampling_rate: 0.5,
+            feature_sampling_rate: 1.0,
+            min_leaf_support: 1,
+            split_candidates: 32,
+            split_finding: SplitFinding::EvenlySpaced(),
+            missing_values: MissingValues::AsZero(),
+            monotone_constraints: MonotoneConstraints::new(),
+            max_depth: 5,
+        }
+    }
+}
+
+impl MARTParams {
+    fn boosting(&self) -> BoostingParams {
+        BoostingParams {
+            seed: self.seed,
+            quiet: self.quiet,
+            num_trees: self.num_trees,
+            learning_rate: self.learning_rate,
+            instance_sampling_rate: self.instance_sampling_rate,
+            feature_sampling_rate: self.feature_sampling_rate,
+            min_leaf_support: self.min_leaf_support,
+            split_candidates: self.split_candidates,
+            split_finding: self.split_finding.clone(),
+            missing_values: self.missing_values.clone(),
+            monotone_constraints: self.monotone_constraints.clone(),
+            max_depth: self.max_depth,
+        }
+    }
+}
+
+/// Squared-error residuals of gain().
+struct Residuals<'d> {
+    dataset: &'d DatasetRef,
+    instances: Vec<InstanceId>,
+}
+
+impl BoostingObjective for Residuals<'_> {
+    /// Start from the mean gain so the first tree does not spend its splits on the intercept.
+    fn initial_score(&self) -> Option<f64> {
+        Some(
+            random_forest::label_stats(&self.instances, self.dataset)
+                .map(|stats| stats.mean)
+                .unwrap_or(0.0),
+        )
+    }
+    fn targets(&mut self, scores: &[f64]) -> Result<Vec<f64>, FastRankError> {
+        let mut residuals = vec![0.0; scores.len()];
+        for index in self.instances.iter().cloned() {
+            let i = index.to_index();
+            residuals[i] = f64::from(self.dataset.gain(index).into_inner()) - scores[i];
+        }
+        Ok(residuals)
+    }
+}
+
+/// Gradient-boosted regression trees: each round fits the squared-error residuals of gain().
+pub fn learn_ensemble(
+    params: &MARTParams,
+    dataset: &DatasetRef,
+    evaluator: &SetEvaluator,
+    validation: Option<&ValidationSet>,
+) -> Result<WeightedEnsemble, Box<dyn Error>> {
+    let mut residuals = Residuals {
+        dataset,
+        instances: dataset.instances(),
+    };
+    boosting::learn_ensemble(
+        &params.boosting(),
+        &mut residuals,
+        dataset,
+        evaluator,
+        validation,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+
+    #[test]
+    fn test_mart_fits_residuals() {
+        let train_dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let eval = SetEvaluator::create(&train_dataset, "ndcg@5", None).unwrap();
+        let squared_error = |model: &WeightedEnsemble| -> f64 {
+            train_dataset
+                .instances()
+                .into_iter()
+                .map(|index| {
+                    let diff = train_dataset.score(index, model).into_inner()
+                        - f64::from(train_dataset.gain(index).into_inner());
+                    diff * diff
+                })
+                .sum()
+        };
+
+        let short = MARTParams {
+            num_trees: 5,
+            seed: 42,
+            quiet: true,
+            ..MARTParams::default()
+        };
+        let long = MARTParams {
+            num_trees: 50,
+            ..short.clone()
+        };
+        let short_model = learn_ensemble(&short, &train_dataset, &eval, None).unwrap();
+        let long_model = learn_ensemble(&long, &train_dataset, &eval, None).unwrap();
+        assert!(squared_error(&long_model) < squared_error(&short_model));
+    }
+}
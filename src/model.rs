@@ -13,6 +13,7 @@ pub enum ModelEnum {
     Linear(DenseLinearRankingModel),
     DecisionTree(TreeNode),
     Ensemble(WeightedEnsemble),
+    MLP(MultiLayerPerceptron),
 }
 
 impl Model for ModelEnum {
@@ -22,6 +23,7 @@ impl Model for ModelEnum {
             ModelEnum::Linear(m) => m.score(features),
             ModelEnum::DecisionTree(m) => m.score(features),
             ModelEnum::Ensemble(m) => m.score(features),
+            ModelEnum::MLP(m) => m.score(features),
         }
     }
 }
@@ -50,6 +52,30 @@ impl Model for DenseLinearRankingModel {
     }
 }
 
+/// A single layer of tanh hidden units feeding a linear output; trained by the neural learners.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MultiLayerPerceptron {
+    /// One dense weight vector (indexed by FeatureId) per hidden unit.
+    pub hidden_weights: Vec<Vec<f64>>,
+    pub hidden_bias: Vec<f64>,
+    pub output_weights: Vec<f64>,
+}
+
+impl Model for MultiLayerPerceptron {
+    fn score(&self, features: &dyn FeatureRead) -> NotNan<f64> {
+        let mut output = 0.0;
+        for ((weights, bias), out) in self
+            .hidden_weights
+            .iter()
+            .zip(self.hidden_bias.iter())
+            .zip(self.output_weights.iter())
+        {
+            output += out * (features.dotp(weights) + bias).tanh();
+        }
+        NotNan::new(output).expect("MLP.predict -> NaN")
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum TreeNode {
     FeatureSplit {
@@ -57,23 +83,41 @@ pub enum TreeNode {
         split: NotNan<f64>,
         lhs: Box<TreeNode>,
         rhs: Box<TreeNode>,
+        /// Whether instances without this feature go left; None reads them as 0.0.
+        #[serde(default)]
+        missing_left: Option<bool>,
+        /// How much the split improved the learner's criterion; 0.0 in older models.
+        #[serde(default)]
+        gain: f64,
     },
     LeafNode(NotNan<f64>),
 }
 
+impl TreeNode {
+    /// Which side of a split a feature value (or its absence) falls on.
+    pub fn goes_left(value: Option<f64>, split: NotNan<f64>, missing_left: Option<bool>) -> bool {
+        match (value, missing_left) {
+            (None, Some(left)) => left,
+            (value, _) => {
+                NotNan::new(value.unwrap_or(0.0)).expect("NaN in feature eval...") <= split
+            }
+        }
+    }
+}
+
 impl Model for TreeNode {
     fn score(&self, features: &dyn FeatureRead) -> NotNan<f64> {
         match self {
-            TreeNode::LeafNode(score) => score.clone(),
+            TreeNode::LeafNode(score) => *score,
             TreeNode::FeatureSplit {
                 fid,
                 split,
                 lhs,
                 rhs,
+                missing_left,
+                ..
             } => {
-                let fval =
-                    NotNan::new(features.get(*fid).unwrap_or(0.0)).expect("NaN in feature eval...");
-                if fval <= *split {
+                if TreeNode::goes_left(features.get(*fid), *split, *missing_left) {
                     lhs.score(features)
                 } else {
                     rhs.score(features)
@@ -99,6 +143,19 @@ impl WeightedEnsemble {
         }
         Self { weights, models }
     }
+    pub fn len(&self) -> usize {
+        self.models.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+    /// Each member model with its weight.
+    pub fn iter(&self) -> impl Iterator<Item = (f64, &ModelEnum)> {
+        self.weights
+            .iter()
+            .map(|w| w.into_inner())
+            .zip(self.models.iter())
+    }
 }
 
 impl Model for WeightedEnsemble {
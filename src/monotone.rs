@@ -0,0 +1,212 @@
+use crate::dataset::RankingDataset;
+use crate::model::TreeNode;
+use crate::FeatureId;
+use ordered_float::NotNan;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+/// Which way a model's score must move as a feature's value goes up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Monotonicity {
+    Increasing(),
+    Decreasing(),
+}
+
+/// Constraints keyed by feature name or number, as the user wrote them.
+pub type MonotoneConstraints = BTreeMap<String, Monotonicity>;
+
+impl Monotonicity {
+    /// The closest weight to `weight` that a linear model may use for this feature.
+    pub fn clamp_weight(&self, weight: f64) -> f64 {
+        match self {
+            Monotonicity::Increasing() => weight.max(0.0),
+            Monotonicity::Decreasing() => weight.min(0.0),
+        }
+    }
+
+    /// Whether a split whose left side averages `lhs` and right side `rhs` respects this.
+    pub fn allows(&self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            Monotonicity::Increasing() => lhs <= rhs,
+            Monotonicity::Decreasing() => lhs >= rhs,
+        }
+    }
+}
+
+/// Look up every constrained feature in `dataset`; an unknown name is an error.
+pub fn resolve(
+    constraints: &MonotoneConstraints,
+    dataset: &dyn RankingDataset,
+) -> Result<HashMap<FeatureId, Monotonicity>, Box<dyn Error>> {
+    constraints
+        .iter()
+        .map(|(name, direction)| Ok((dataset.try_lookup_feature(name)?, *direction)))
+        .collect()
+}
+
+fn leaf_range(tree: &TreeNode) -> (f64, f64) {
+    match tree {
+        TreeNode::LeafNode(output) => (output.into_inner(), output.into_inner()),
+        TreeNode::FeatureSplit { lhs, rhs, .. } => {
+            let (lhs_min, lhs_max) = leaf_range(lhs);
+            let (rhs_min, rhs_max) = leaf_range(rhs);
+            (lhs_min.min(rhs_min), lhs_max.max(rhs_max))
+        }
+    }
+}
+
+/// Clamp leaf outputs so the tree is monotone in every constrained feature.
+/// Below a constrained split, the low side's leaves are capped and the high side's floored at a
+/// shared midpoint, and those bounds carry down to every split beneath it.
+pub fn clamp_leaves(tree: &mut TreeNode, constraints: &HashMap<FeatureId, Monotonicity>) {
+    if !constraints.is_empty() {
+        clamp_within(tree, constraints, f64::MIN, f64::MAX);
+    }
+}
+
+fn clamp_within(
+    tree: &mut TreeNode,
+    constraints: &HashMap<FeatureId, Monotonicity>,
+    lower: f64,
+    upper: f64,
+) {
+    match tree {
+        TreeNode::LeafNode(output) => {
+            *output =
+                NotNan::new(output.into_inner().max(lower).min(upper)).expect("Leaf output NaN.");
+        }
+        TreeNode::FeatureSplit { fid, lhs, rhs, .. } => {
+            let (low, high) = match constraints.get(fid) {
+                None => {
+                    clamp_within(lhs, constraints, lower, upper);
+                    clamp_within(rhs, constraints, lower, upper);
+                    return;
+                }
+                Some(Monotonicity::Increasing()) => (lhs, rhs),
+                Some(Monotonicity::Decreasing()) => (rhs, lhs),
+            };
+            let (_, low_max) = leaf_range(low);
+            let (high_min, _) = leaf_range(high);
+            let mid = ((low_max + high_min) / 2.0).max(lower).min(upper);
+            clamp_within(low, constraints, lower, mid);
+            clamp_within(high, constraints, mid, upper);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coordinate_ascent::CoordinateAscentParams;
+    use crate::dataset;
+    use crate::instance::Features;
+    use crate::json_api::{self, FastRankModelParams, TrainRequest};
+    use crate::model::Model;
+    use crate::random_forest::RandomForestParams;
+
+    fn leaf(x: f64) -> Box<TreeNode> {
+        Box::new(TreeNode::LeafNode(NotNan::new(x).unwrap()))
+    }
+    fn split(fid: usize, split: f64, lhs: Box<TreeNode>, rhs: Box<TreeNode>) -> Box<TreeNode> {
+        Box::new(TreeNode::FeatureSplit {
+            fid: FeatureId::from_index(fid),
+            split: NotNan::new(split).unwrap(),
+            lhs,
+            rhs,
+            missing_left: None,
+            gain: 0.0,
+        })
+    }
+    fn leaves(tree: &TreeNode) -> Vec<f64> {
+        match tree {
+            TreeNode::LeafNode(output) => vec![output.into_inner()],
+            TreeNode::FeatureSplit { lhs, rhs, .. } => {
+                let mut out = leaves(lhs);
+                out.extend(leaves(rhs));
+                out
+            }
+        }
+    }
+
+    #[test]
+    fn test_clamp_leaves() {
+        // Increasing in feature 0, but the deepest leaves on the low side overshoot.
+        let mut tree = split(
+            0,
+            5.0,
+            split(1, 0.5, leaf(1.0), split(0, 2.0, leaf(2.0), leaf(8.0))),
+            split(1, 0.5, leaf(4.0), leaf(6.0)),
+        );
+        let mut constraints = HashMap::new();
+        constraints.insert(FeatureId::from_index(0), Monotonicity::Increasing());
+        clamp_leaves(&mut tree, &constraints);
+        assert_eq!(vec![1.0, 2.0, 6.0, 6.0, 6.0], leaves(&tree));
+
+        assert_eq!(0.0, Monotonicity::Increasing().clamp_weight(-0.5));
+        assert_eq!(-0.5, Monotonicity::Decreasing().clamp_weight(-0.5));
+    }
+
+    #[test]
+    fn test_learners_respect_constraints() {
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let mut constraints = MonotoneConstraints::new();
+        // Left alone, a forest scores this feature increasingly.
+        constraints.insert("5".to_owned(), Monotonicity::Decreasing());
+        let fid = dataset.try_lookup_feature("5").unwrap();
+        let n_dim = dataset.features().iter().max().unwrap().to_index() + 1;
+
+        let learners = vec![
+            FastRankModelParams::RandomForest(RandomForestParams {
+                num_trees: 5,
+                seed: 42,
+                quiet: true,
+                monotone_constraints: constraints.clone(),
+                ..RandomForestParams::default()
+            }),
+            FastRankModelParams::CoordinateAscent(CoordinateAscentParams {
+                num_restarts: 1,
+                seed: 42,
+                quiet: true,
+                monotone_constraints: constraints.clone(),
+                ..CoordinateAscentParams::default()
+            }),
+        ];
+        for params in learners {
+            let request = TrainRequest {
+                measure: "ndcg@5".to_owned(),
+                params,
+                ..TrainRequest::default()
+            };
+            let model = json_api::do_training(request, &dataset, None).unwrap();
+            for id in dataset.instances().into_iter().take(50) {
+                let mut values: Vec<f32> = (0..n_dim)
+                    .map(|i| {
+                        let value = dataset.get_feature_value(id, FeatureId::from_index(i));
+                        value.unwrap_or(0.0) as f32
+                    })
+                    .collect();
+                let mut last = f64::MAX;
+                for x in &[-1.0, 0.0, 0.01, 0.03, 0.05, 0.1, 0.5, 1.0] {
+                    values[fid.to_index()] = *x;
+                    let score = model.score(&Features::Dense32(values.clone())).into_inner();
+                    assert!(score <= last, "{:?} rose with feature 5", model);
+                    last = score;
+                }
+            }
+        }
+
+        constraints.insert("no-such-feature".to_owned(), Monotonicity::Increasing());
+        let request = TrainRequest {
+            measure: "ndcg@5".to_owned(),
+            params: FastRankModelParams::RandomForest(RandomForestParams {
+                monotone_constraints: constraints,
+                ..RandomForestParams::default()
+            }),
+            ..TrainRequest::default()
+        };
+        assert!(json_api::do_training(request, &dataset, None).is_err());
+    }
+}
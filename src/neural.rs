@@ -0,0 +1,388 @@
+use crate::dataset::RankingDataset;
+use crate::error::FastRankError;
+use crate::evaluators::SetEvaluator;
+use crate::model::{DenseLinearRankingModel, ModelEnum, MultiLayerPerceptron};
+use crate::normalizers::FeatureStats;
+use crate::randutil::shuffle;
+use crate::validation::{EarlyStopping, ValidationSet};
+use crate::{FeatureId, InstanceId};
+use oorandom::Rand64;
+use std::error::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NeuralLoss {
+    /// Pairwise logistic loss (Burges et al., 2005).
+    RankNet,
+    /// Listwise cross-entropy between top-one probabilities (Cao et al., 2007).
+    ListNet,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NeuralParams {
+    pub seed: u64,
+    pub quiet: bool,
+    pub loss: NeuralLoss,
+    /// Zero trains a linear model; anything else a single hidden layer of this size.
+    pub hidden_units: u32,
+    pub num_epochs: u32,
+    pub learning_rate: f64,
+    pub l2: f64,
+}
+
+impl Default for NeuralParams {
+    fn default() -> Self {
+        let mut rand = Rand64::new(0xdeadbeef);
+        Self {
+            seed: rand.rand_u64(),
+            quiet: false,
+            loss: NeuralLoss::RankNet,
+            hidden_units: 10,
+            num_epochs: 50,
+            learning_rate: 0.01,
+            l2: 0.0,
+        }
+    }
+}
+
+impl NeuralParams {
+    pub fn listnet() -> Self {
+        Self {
+            loss: NeuralLoss::ListNet,
+            hidden_units: 0,
+            ..Self::default()
+        }
+    }
+}
+
+/// Features are z-scored during training so one learning rate suits them all.
+/// The scaling gets folded back into the weights of the final model.
+pub(crate) struct Standardizer {
+    pub(crate) fids: Vec<FeatureId>,
+    means: Vec<f64>,
+    scales: Vec<f64>,
+}
+
+impl Standardizer {
+    pub(crate) fn new(dataset: &dyn RankingDataset) -> Self {
+        let mut fids = dataset.features();
+        fids.sort_unstable();
+        let stats = FeatureStats::compute(dataset).feature_stats;
+        let mut means = Vec::new();
+        let mut scales = Vec::new();
+        for fid in fids.iter() {
+            let (mean, stddev) = stats
+                .get(fid)
+                .map(|s| (s.mean, s.variance.sqrt()))
+                .unwrap_or((0.0, 0.0));
+            means.push(mean);
+            // Constant features carry no information; zero them out.
+            scales.push(if stddev > 0.0 { 1.0 / stddev } else { 0.0 });
+        }
+        Self {
+            fids,
+            means,
+            scales,
+        }
+    }
+    pub(crate) fn transform(&self, dataset: &dyn RankingDataset, id: InstanceId) -> Vec<f64> {
+        self.fids
+            .iter()
+            .enumerate()
+            .map(|(k, fid)| {
+                let val = dataset.get_feature_value(id, *fid).unwrap_or(0.0);
+                (val - self.means[k]) * self.scales[k]
+            })
+            .collect()
+    }
+    /// Width of a dense weight vector that covers every feature of the dataset.
+    pub(crate) fn n_dim(&self, dataset: &dyn RankingDataset) -> usize {
+        let max_fid = self.fids.last().map(|fid| fid.to_index() + 1).unwrap_or(0);
+        std::cmp::max(dataset.n_dim() as usize, max_fid)
+    }
+    /// Turn weights over standardized inputs into dense weights over raw features, plus the bias shift.
+    pub(crate) fn unscale(&self, weights: &[f64], n_dim: usize) -> (Vec<f64>, f64) {
+        let mut raw = vec![0.0; n_dim];
+        let mut bias = 0.0;
+        for (k, fid) in self.fids.iter().enumerate() {
+            raw[fid.to_index()] = weights[k] * self.scales[k];
+            bias -= weights[k] * self.scales[k] * self.means[k];
+        }
+        (raw, bias)
+    }
+}
+
+/// The trainable parameters; with no hidden units the output weights read the inputs directly.
+#[derive(Clone)]
+struct Network {
+    n_input: usize,
+    hidden: Vec<Vec<f64>>,
+    hidden_bias: Vec<f64>,
+    output: Vec<f64>,
+}
+
+impl Network {
+    fn new(n_input: usize, n_hidden: usize, rand: &mut Rand64) -> Self {
+        let mut init = |n: usize| -> Vec<f64> {
+            (0..n)
+                .map(|_| (rand.rand_float() * 2.0 - 1.0) * 0.1)
+                .collect()
+        };
+        if n_hidden == 0 {
+            Self {
+                n_input,
+                hidden: Vec::new(),
+                hidden_bias: Vec::new(),
+                output: init(n_input),
+            }
+        } else {
+            Self {
+                n_input,
+                hidden: (0..n_hidden).map(|_| init(n_input)).collect(),
+                hidden_bias: init(n_hidden),
+                output: init(n_hidden),
+            }
+        }
+    }
+    fn zeros_like(&self) -> Self {
+        Self {
+            n_input: self.n_input,
+            hidden: vec![vec![0.0; self.n_input]; self.hidden.len()],
+            hidden_bias: vec![0.0; self.hidden_bias.len()],
+            output: vec![0.0; self.output.len()],
+        }
+    }
+    fn is_linear(&self) -> bool {
+        self.hidden.is_empty()
+    }
+    /// Returns the score and the hidden activations needed for backprop.
+    fn forward(&self, x: &[f64]) -> (f64, Vec<f64>) {
+        if self.is_linear() {
+            return (dotp(&self.output, x), Vec::new());
+        }
+        let activations: Vec<f64> = self
+            .hidden
+            .iter()
+            .zip(self.hidden_bias.iter())
+            .map(|(w, b)| (dotp(w, x) + b).tanh())
+            .collect();
+        (dotp(&self.output, &activations), activations)
+    }
+    /// Add d(score)/d(params) * dscore into grad.
+    fn backward(&self, x: &[f64], activations: &[f64], dscore: f64, grad: &mut Network) {
+        if self.is_linear() {
+            for (g, xi) in grad.output.iter_mut().zip(x.iter()) {
+                *g += dscore * xi;
+            }
+            return;
+        }
+        for (h, a) in activations.iter().enumerate() {
+            grad.output[h] += dscore * a;
+            let dpre = dscore * self.output[h] * (1.0 - a * a);
+            grad.hidden_bias[h] += dpre;
+            for (g, xi) in grad.hidden[h].iter_mut().zip(x.iter()) {
+                *g += dpre * xi;
+            }
+        }
+    }
+    fn step(&mut self, grad: &Network, learning_rate: f64, l2: f64) {
+        let update = |params: &mut [f64], grads: &[f64]| {
+            for (p, g) in params.iter_mut().zip(grads.iter()) {
+                *p -= learning_rate * (g + l2 * *p);
+            }
+        };
+        update(&mut self.output, &grad.output);
+        update(&mut self.hidden_bias, &grad.hidden_bias);
+        for (params, grads) in self.hidden.iter_mut().zip(grad.hidden.iter()) {
+            update(params, grads);
+        }
+    }
+    fn to_model(&self, standardizer: &Standardizer, n_dim: usize) -> ModelEnum {
+        if self.is_linear() {
+            let (weights, _bias) = standardizer.unscale(&self.output, n_dim);
+            return ModelEnum::Linear(DenseLinearRankingModel { weights });
+        }
+        let mut hidden_weights = Vec::new();
+        let mut hidden_bias = Vec::new();
+        for (w, b) in self.hidden.iter().zip(self.hidden_bias.iter()) {
+            let (weights, shift) = standardizer.unscale(w, n_dim);
+            hidden_weights.push(weights);
+            hidden_bias.push(b + shift);
+        }
+        ModelEnum::MLP(MultiLayerPerceptron {
+            hidden_weights,
+            hidden_bias,
+            output_weights: self.output.clone(),
+        })
+    }
+}
+
+fn dotp(lhs: &[f64], rhs: &[f64]) -> f64 {
+    lhs.iter().zip(rhs.iter()).map(|(a, b)| a * b).sum()
+}
+
+/// d(loss)/d(score) for every document of one query.
+fn score_gradients(loss: &NeuralLoss, scores: &[f64], gains: &[f64]) -> Vec<f64> {
+    let mut dscores = vec![0.0; scores.len()];
+    match loss {
+        NeuralLoss::RankNet => {
+            let mut num_pairs = 0;
+            for i in 0..scores.len() {
+                for j in 0..scores.len() {
+                    if gains[i] <= gains[j] {
+                        continue;
+                    }
+                    // i should be above j; loss = log(1 + exp(s_j - s_i)).
+                    let push = 1.0 / (1.0 + (scores[i] - scores[j]).exp());
+                    dscores[i] -= push;
+                    dscores[j] += push;
+                    num_pairs += 1;
+                }
+            }
+            if num_pairs > 0 {
+                for d in dscores.iter_mut() {
+                    *d /= f64::from(num_pairs);
+                }
+            }
+        }
+        NeuralLoss::ListNet => {
+            let target = softmax(gains);
+            let predicted = softmax(scores);
+            for (i, d) in dscores.iter_mut().enumerate() {
+                *d = predicted[i] - target[i];
+            }
+        }
+    }
+    dscores
+}
+
+fn softmax(xs: &[f64]) -> Vec<f64> {
+    let max = xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<f64> = xs.iter().map(|x| (x - max).exp()).collect();
+    let total: f64 = exps.iter().sum();
+    exps.into_iter().map(|e| e / total).collect()
+}
+
+pub fn learn(
+    params: &NeuralParams,
+    dataset: &dyn RankingDataset,
+    evaluator: &SetEvaluator,
+    validation: Option<&ValidationSet>,
+) -> Result<ModelEnum, Box<dyn Error>> {
+    if !(params.learning_rate > 0.0 && params.learning_rate.is_finite()) {
+        Err(FastRankError::InvalidParameter(format!(
+            "learning_rate must be positive and finite: {}",
+            params.learning_rate
+        )))?;
+    }
+    if !(params.l2 >= 0.0 && params.l2.is_finite()) {
+        Err(FastRankError::InvalidParameter(format!(
+            "l2 must be non-negative and finite: {}",
+            params.l2
+        )))?;
+    }
+    let mut rand = Rand64::new(params.seed.into());
+    let standardizer = Standardizer::new(dataset);
+    let n_dim = standardizer.n_dim(dataset);
+
+    let mut queries: Vec<(String, Vec<InstanceId>)> =
+        dataset.instances_by_query().into_iter().collect();
+    // Sort first so the shuffles below only depend on the seed.
+    queries.sort_unstable();
+
+    let mut network = Network::new(
+        standardizer.fids.len(),
+        params.hidden_units as usize,
+        &mut rand,
+    );
+    let mut stopping = EarlyStopping::new(validation);
+    let mut best_network = None;
+
+    if !params.quiet {
+        println!("-----------------------");
+        println!("|{:>7}|{:>15}|", "Epoch", evaluator.name());
+        println!("-----------------------");
+    }
+
+    for epoch in 0..params.num_epochs {
+        shuffle(&mut queries, &mut rand);
+        for (_qid, docs) in queries.iter() {
+            let xs: Vec<Vec<f64>> = docs
+                .iter()
+                .map(|id| standardizer.transform(dataset, *id))
+                .collect();
+            let gains: Vec<f64> = docs
+                .iter()
+                .map(|id| f64::from(dataset.gain(*id).into_inner()))
+                .collect();
+            let forward: Vec<(f64, Vec<f64>)> = xs.iter().map(|x| network.forward(x)).collect();
+            let scores: Vec<f64> = forward.iter().map(|(s, _)| *s).collect();
+            let dscores = score_gradients(&params.loss, &scores, &gains);
+
+            let mut grad = network.zeros_like();
+            for ((x, (_, activations)), dscore) in xs.iter().zip(forward.iter()).zip(dscores) {
+                if dscore != 0.0 {
+                    network.backward(x, activations, dscore, &mut grad);
+                }
+            }
+            network.step(&grad, params.learning_rate, params.l2);
+        }
+
+        if !params.quiet {
+            let model = network.to_model(&standardizer, n_dim);
+            println!(
+                "|{:>7}|{:>15.3}|",
+                epoch + 1,
+                evaluator.evaluate_mean(&model)
+            );
+        }
+        let epochs = epoch as usize + 1;
+        let stop = stopping.observe(epochs, || network.to_model(&standardizer, n_dim));
+        if stopping.best_size() == Some(epochs) {
+            best_network = Some(network.clone());
+        }
+        if stop {
+            break;
+        }
+    }
+
+    if !params.quiet {
+        println!("-----------------------");
+        stopping.print_summary();
+    }
+
+    Ok(best_network
+        .unwrap_or(network)
+        .to_model(&standardizer, n_dim))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+
+    #[test]
+    fn test_neural_learners_beat_init() {
+        let train_dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let eval = SetEvaluator::create(&train_dataset, "ndcg@5", None).unwrap();
+
+        for params in &[NeuralParams::default(), NeuralParams::listnet()] {
+            let untrained = NeuralParams {
+                seed: 42,
+                quiet: true,
+                num_epochs: 0,
+                ..params.clone()
+            };
+            let trained = NeuralParams {
+                num_epochs: 20,
+                ..untrained.clone()
+            };
+            let before =
+                eval.evaluate_mean(&learn(&untrained, &train_dataset, &eval, None).unwrap());
+            let after = eval.evaluate_mean(&learn(&trained, &train_dataset, &eval, None).unwrap());
+            assert!(after > before, "{:?}: {} <= {}", params.loss, after, before);
+        }
+    }
+}
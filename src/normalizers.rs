@@ -1,7 +1,7 @@
 use crate::dataset::RankingDataset;
-use crate::stats::{ComputedStats, StreamingStats};
+use crate::error::FastRankError;
+use crate::stats::{ComputedStats, PercentileStats, StreamingStats};
 use crate::FeatureId;
-use ordered_float::NotNan;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +34,30 @@ impl FeatureStats {
                 .collect(),
         }
     }
+
+    /// `num_points` evenly spaced quantiles (min to max) of a feature's present values, without
+    /// repeats; None if no instance has the feature.
+    pub fn quantiles(
+        dataset: &dyn RankingDataset,
+        fid: FeatureId,
+        num_points: u32,
+    ) -> Option<Vec<f64>> {
+        let values: Vec<f64> = dataset
+            .instances()
+            .into_iter()
+            .flat_map(|inst| dataset.get_feature_value(inst, fid))
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        let percentiles = PercentileStats::new(&values);
+        let steps = num_points.max(2) - 1;
+        let mut grid: Vec<f64> = (0..=steps)
+            .map(|i| percentiles.percentile(f64::from(i) / f64::from(steps)))
+            .collect();
+        grid.dedup();
+        Some(grid)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,67 +68,53 @@ pub enum Normalizer {
 }
 
 impl Normalizer {
-    pub fn new(method: &str, dataset: &dyn RankingDataset) -> Result<Normalizer, String> {
+    pub fn new(method: &str, dataset: &dyn RankingDataset) -> Result<Normalizer, FastRankError> {
         Ok(match method {
             "zscore" => Normalizer::ZScoreNormalizer(FeatureStats::compute(dataset)),
             "maxmin" | "linear" => Normalizer::MaxMinNormalizer(FeatureStats::compute(dataset)),
             "sigmoid" => Normalizer::SigmoidNormalizer(),
-            unkn => Err(format!("Unsupported Normalizer: {}", unkn))?,
+            unkn => Err(FastRankError::InvalidParameter(format!(
+                "Unsupported Normalizer: {}",
+                unkn
+            )))?,
         })
     }
-    pub fn normalize(&self, fid: FeatureId, val: f32) -> f32 {
-        match self {
-            Normalizer::MaxMinNormalizer(fs) => {
-                if let Some(stats) = fs.feature_stats.get(&fid) {
+    pub fn normalize(&self, fid: FeatureId, val: f32) -> Result<f32, FastRankError> {
+        let out = match self {
+            Normalizer::MaxMinNormalizer(fs) => match fs.feature_stats.get(&fid) {
+                Some(stats) => {
                     let max = stats.max as f32;
                     let min = stats.min as f32;
                     if max == min {
-                        return 0.0;
-                    }
-                    match NotNan::new((val - min) / (max - min)) {
-                        Ok(out) => return out.into_inner(),
-                        Err(_) => panic!(
-                            "Normalization.maxmin NaN: {} {} {:?}",
-                            val,
-                            max - min,
-                            stats
-                        ),
+                        return Ok(0.0);
                     }
+                    (val - min) / (max - min)
                 }
-            }
-            Normalizer::ZScoreNormalizer(fs) => {
-                if let Some(stats) = fs.feature_stats.get(&fid) {
+                // if no stats, original value.
+                None => val,
+            },
+            Normalizer::ZScoreNormalizer(fs) => match fs.feature_stats.get(&fid) {
+                Some(stats) => {
                     let mean = stats.mean as f32;
                     let stddev = stats.variance.sqrt() as f32;
                     if stddev == 0.0 {
-                        return 0.0;
+                        return Ok(0.0);
                     }
-                    match NotNan::new((val - mean) / stddev) {
-                        Ok(out) => return out.into_inner(),
-                        Err(_) => panic!(
-                            "Normalization.zscore NaN: {} {} {} {:?}",
-                            stats.mean,
-                            stats.variance,
-                            stats.variance.sqrt(),
-                            stats
-                        ),
-                    };
+                    (val - mean) / stddev
                 }
-            }
-            Normalizer::SigmoidNormalizer() => match NotNan::new(sigmoid(val)) {
-                Ok(out) => return out.into_inner() as f32,
-                Err(_) => panic!(
-                    "Normalization.sigmoid NaN: {} {} {} {} {}",
-                    val,
-                    val.exp(),
-                    -(val).exp(),
-                    sigmoid(val),
-                    fid.to_index()
-                ),
+                None => val,
             },
+            Normalizer::SigmoidNormalizer() => sigmoid(val),
+        };
+        if out.is_nan() {
+            Err(FastRankError::NaN(format!(
+                "normalizing feature {} value {} with {:?}",
+                fid.to_index(),
+                val,
+                self
+            )))?;
         }
-        // if no stats or match, original value.
-        val
+        Ok(out)
     }
 }
 
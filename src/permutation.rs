@@ -0,0 +1,260 @@
+use crate::dataset::{DatasetRef, FeatureOverride, RankingDataset};
+use crate::error::FastRankError;
+use crate::evaluators::SetEvaluator;
+use crate::instance::FeatureRead;
+use crate::model::{Model, ModelEnum};
+use crate::qrel::QuerySetJudgments;
+use crate::randutil::shuffle;
+use crate::stats::PercentileStats;
+use crate::{FeatureId, InstanceId};
+use oorandom::Rand64;
+use ordered_float::NotNan;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::sync::Arc;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PermutationRequest {
+    pub measure: String,
+    pub judgments: Option<QuerySetJudgments>,
+    /// Names or numbers of the features to permute; every feature when None.
+    pub features: Option<Vec<String>>,
+    /// How many different shuffles of each feature to average over.
+    pub num_repeats: u32,
+    /// How many times to resample queries for the confidence interval.
+    pub num_bootstrap: u32,
+    pub seed: u64,
+}
+
+impl Default for PermutationRequest {
+    fn default() -> Self {
+        let mut rand = Rand64::new(0xdeadbeef);
+        Self {
+            measure: "ndcg".to_owned(),
+            judgments: None,
+            features: None,
+            num_repeats: 5,
+            num_bootstrap: 200,
+            seed: rand.rand_u64(),
+        }
+    }
+}
+
+/// How much the measure falls when one feature's values are shuffled.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeatureDrop {
+    /// Mean measure minus mean measure with the feature shuffled; positive means the model needs it.
+    pub drop: f64,
+    /// 5th and 95th percentiles of the drop over bootstrap resamples of the queries.
+    pub lower: f64,
+    pub upper: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PermutationResult {
+    pub measure: String,
+    /// The measure on the dataset as it is.
+    pub baseline: f64,
+    /// Keyed by feature name.
+    pub features: BTreeMap<String, FeatureDrop>,
+}
+
+/// A view of a dataset where one feature's values are taken from other instances.
+/// Scoring reads features through the parent, so it works for any dataset and any model.
+#[derive(Clone)]
+pub struct PermutedDatasetRef {
+    pub parent: DatasetRef,
+    pub fid: FeatureId,
+    /// Where each instance gets its value of `fid`; instances not listed keep their own.
+    pub sources: Arc<HashMap<InstanceId, InstanceId>>,
+}
+
+impl PermutedDatasetRef {
+    /// Shuffle `fid` within each query.
+    pub fn shuffled(parent: &DatasetRef, fid: FeatureId, rand: &mut Rand64) -> Self {
+        let mut queries: Vec<(String, Vec<InstanceId>)> =
+            parent.instances_by_query().into_iter().collect();
+        // Sort first so the shuffle only depends on the seed.
+        queries.sort_unstable();
+        let mut sources = HashMap::new();
+        for (_, docs) in queries {
+            let mut shuffled = docs.clone();
+            shuffle(&mut shuffled, rand);
+            sources.extend(docs.into_iter().zip(shuffled));
+        }
+        Self {
+            parent: parent.clone(),
+            fid,
+            sources: Arc::new(sources),
+        }
+    }
+    pub fn into_ref(self) -> DatasetRef {
+        DatasetRef {
+            data: Arc::new(self),
+        }
+    }
+
+    fn features_of(&self, id: InstanceId) -> FeatureOverride<'_> {
+        let source = *self.sources.get(&id).unwrap_or(&id);
+        FeatureOverride {
+            dataset: &self.parent,
+            id,
+            fid: self.fid,
+            value: self.parent.get_feature_value(source, self.fid),
+        }
+    }
+}
+
+impl RankingDataset for PermutedDatasetRef {
+    fn get_ref(&self) -> Option<DatasetRef> {
+        None
+    }
+    fn is_sampled(&self) -> bool {
+        self.parent.is_sampled()
+    }
+    fn features(&self) -> Vec<FeatureId> {
+        self.parent.features()
+    }
+    fn n_dim(&self) -> u32 {
+        self.parent.n_dim()
+    }
+    fn instances(&self) -> Vec<InstanceId> {
+        self.parent.instances()
+    }
+    fn instances_by_query(&self) -> HashMap<String, Vec<InstanceId>> {
+        self.parent.instances_by_query()
+    }
+    fn score(&self, id: InstanceId, model: &dyn Model) -> NotNan<f64> {
+        model.score(&self.features_of(id))
+    }
+    fn gain(&self, id: InstanceId) -> NotNan<f32> {
+        self.parent.gain(id)
+    }
+    fn query_id(&self, id: InstanceId) -> &str {
+        self.parent.query_id(id)
+    }
+    fn document_name(&self, id: InstanceId) -> Option<&str> {
+        self.parent.document_name(id)
+    }
+    fn queries(&self) -> Vec<String> {
+        self.parent.queries()
+    }
+    fn feature_name(&self, fid: FeatureId) -> String {
+        self.parent.feature_name(fid)
+    }
+    fn get_feature_value(&self, instance: InstanceId, fid: FeatureId) -> Option<f64> {
+        self.features_of(instance).get(fid)
+    }
+    fn try_lookup_feature(&self, name_or_num: &str) -> Result<FeatureId, Box<dyn Error>> {
+        self.parent.try_lookup_feature(name_or_num)
+    }
+    fn instance_weight(&self, id: InstanceId) -> f64 {
+        self.parent.instance_weight(id)
+    }
+    fn query_weight(&self, qid: &str) -> f64 {
+        self.parent.query_weight(qid)
+    }
+}
+
+/// Shuffle each feature within queries and measure how much worse the model does.
+pub fn permutation_importance(
+    request: &PermutationRequest,
+    model: &ModelEnum,
+    dataset: &DatasetRef,
+) -> Result<PermutationResult, Box<dyn Error>> {
+    if request.num_repeats == 0 {
+        Err(FastRankError::InvalidParameter(
+            "Permutation importance needs num_repeats of at least 1.".to_owned(),
+        ))?;
+    }
+    let fids = match request.features.as_ref() {
+        Some(names) => names
+            .iter()
+            .map(|name| dataset.try_lookup_feature(name))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => dataset.features(),
+    };
+    let evaluator = SetEvaluator::create(dataset, &request.measure, request.judgments.clone())?;
+    let baseline = evaluator.evaluate_mean(model);
+    let baseline_by_query = evaluator.evaluate_to_map(model);
+    let mut queries: Vec<&String> = baseline_by_query.keys().collect();
+    queries.sort_unstable();
+
+    let mut rand = Rand64::new(request.seed.into());
+    let mut features = BTreeMap::new();
+    for fid in fids {
+        let mut drop = 0.0;
+        let mut query_drops = vec![0.0; queries.len()];
+        for _ in 0..request.num_repeats {
+            let permuted = PermutedDatasetRef::shuffled(dataset, fid, &mut rand).into_ref();
+            let permuted_eval =
+                SetEvaluator::create(&permuted, &request.measure, request.judgments.clone())?;
+            drop += baseline - permuted_eval.evaluate_mean(model);
+            let by_query = permuted_eval.evaluate_to_map(model);
+            for (qd, qid) in query_drops.iter_mut().zip(queries.iter()) {
+                *qd += baseline_by_query[*qid] - by_query[*qid];
+            }
+        }
+        let repeats = f64::from(request.num_repeats);
+        for qd in query_drops.iter_mut() {
+            *qd /= repeats;
+        }
+        let intervals = bootstrap_means(&query_drops, request.num_bootstrap, &mut rand);
+        features.insert(
+            dataset.feature_name(fid),
+            FeatureDrop {
+                drop: drop / repeats,
+                lower: intervals.percentile(0.05),
+                upper: intervals.percentile(0.95),
+            },
+        );
+    }
+
+    Ok(PermutationResult {
+        measure: evaluator.name(),
+        baseline,
+        features,
+    })
+}
+
+fn bootstrap_means(data: &[f64], num_trials: u32, rand: &mut Rand64) -> PercentileStats {
+    let n = data.len() as u64;
+    let means: Vec<f64> = (0..num_trials.max(1))
+        .map(|_| {
+            let sum: f64 = (0..n).map(|_| data[rand.rand_range(0..n) as usize]).sum();
+            sum / (n as f64)
+        })
+        .collect();
+    PercentileStats::new(&means)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+    use crate::model::SingleFeatureModel;
+
+    #[test]
+    fn test_permutation_importance() {
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let fid = dataset.try_lookup_feature("5").unwrap();
+        let model = ModelEnum::SingleFeature(SingleFeatureModel { fid, dir: 1.0 });
+        let request = PermutationRequest {
+            measure: "ndcg@5".to_owned(),
+            seed: 42,
+            ..PermutationRequest::default()
+        };
+        let result = permutation_importance(&request, &model, &dataset).unwrap();
+
+        let used = &result.features["5"];
+        assert!(used.drop > 0.0);
+        assert!(used.lower <= used.drop && used.drop <= used.upper);
+        // The model never looks at the other features, so shuffling them changes nothing.
+        for (name, fd) in result.features.iter().filter(|(name, _)| *name != "5") {
+            assert!(fd.drop.abs() < 1e-9, "{}", name);
+        }
+    }
+}
@@ -20,8 +20,7 @@ impl QueryJudgments {
     }
     pub fn num_relevant(&self) -> u32 {
         self.docid_to_rel
-            .iter()
-            .map(|(_, gain)| gain)
+            .values()
             .filter(|gain| gain.into_inner() > 0.0)
             .count() as u32
     }
@@ -73,7 +72,7 @@ pub fn read_file(path: &str) -> Result<QuerySetJudgments, Box<dyn std::error::Er
     loop {
         num += 1;
         let amt = reader.read_line(&mut line)?;
-        if amt <= 0 {
+        if amt == 0 {
             break;
         }
         let row: Vec<&str> = line.split_whitespace().collect();
@@ -86,10 +85,7 @@ pub fn read_file(path: &str) -> Result<QuerySetJudgments, Box<dyn std::error::Er
         let gain =
             NotNan::new(gain).map_err(|_| format!("{}:{}: NaN relevance judgment.", path, num))?;
 
-        output
-            .entry(qid)
-            .or_insert_with(|| HashMap::new())
-            .insert(docid, gain);
+        output.entry(qid).or_default().insert(docid, gain);
         line.clear();
     }
 
@@ -1,15 +1,23 @@
 use crate::dataset::{DatasetRef, RankingDataset};
+use crate::error::FastRankError;
 use crate::evaluators::SetEvaluator;
-use crate::model::{ModelEnum, TreeNode, WeightedEnsemble};
-use crate::normalizers::FeatureStats;
+use crate::feature_index::{FeatureColumn, FeatureIndex};
+use crate::instance::FeatureRead;
+use crate::model::{Model, ModelEnum, TreeNode, WeightedEnsemble};
+use crate::monotone::{self, MonotoneConstraints, Monotonicity};
+use crate::randutil;
 use crate::sampling::DatasetSampling;
 use crate::stats;
+use crate::validation::{EarlyStopping, ValidationSet};
 use crate::Scored;
 use crate::{FeatureId, InstanceId};
 use oorandom::Rand64;
 use ordered_float::NotNan;
 use rayon::prelude::*;
 use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::iter;
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum SplitSelectionStrategy {
@@ -29,100 +37,174 @@ pub fn label_stats(
     }
     label_stats.finish()
 }
-fn compute_output(ids: &[InstanceId], dataset: &dyn RankingDataset) -> NotNan<f64> {
-    if ids.len() == 0 {
-        return NotNan::new(0.0).unwrap();
-    }
-    let mut gain_sum = 0.0;
-    for gain in ids.iter().cloned().map(|index| dataset.gain(index)) {
-        gain_sum += gain.into_inner() as f64;
+fn plogp(x: f64) -> f64 {
+    if x == 0.0 {
+        0.0
+    } else {
+        x * x.log2()
     }
-    NotNan::new(gain_sum / (ids.len() as f64)).expect("Leaf output NaN.")
 }
-fn squared_error(ids: &[InstanceId], dataset: &dyn RankingDataset) -> NotNan<f64> {
-    let output = compute_output(ids, dataset);
 
-    let mut sum_sq_errors = 0.0;
-    for gain in ids.iter().cloned().map(|index| dataset.gain(index)) {
-        let diff = output - f64::from(gain.into_inner());
-        sum_sq_errors += (diff * diff).into_inner();
-    }
-    NotNan::new(sum_sq_errors).unwrap()
+/// Running sums of the labels on one side of a split, so every threshold of a sorted feature
+/// can be scored in one pass.
+#[derive(Debug, Clone, Default)]
+struct LabelSums {
+    count: f64,
+    sum: f64,
+    sum_sq: f64,
+    positive: f64,
 }
-fn gini_impurity(ids: &[InstanceId], dataset: &dyn RankingDataset) -> NotNan<f64> {
-    if ids.len() == 0 {
-        return NotNan::new(0.0).unwrap();
+
+impl LabelSums {
+    fn push(&mut self, gain: f64, weight: f64) {
+        self.count += weight;
+        self.sum += weight * gain;
+        self.sum_sq += weight * gain * gain;
+        if gain > 0.0 {
+            self.positive += weight;
+        }
     }
-    let count = ids.len() as f64;
-    let positive = ids
-        .iter()
-        .cloned()
-        .filter(|index| dataset.gain(*index).into_inner() > 0.0)
-        .count();
-    let p_yes = (positive as f64) / count;
-    let p_no = (count - (positive as f64)) / count;
-    let gini = p_yes * (1.0 - p_yes) + p_no * (1.0 - p_no);
-    NotNan::new(gini).expect("gini was NaN")
-}
-fn plogp(x: f64) -> NotNan<f64> {
-    if x == 0.0 {
-        NotNan::new(0.0).unwrap()
-    } else {
-        NotNan::new(x * x.log2()).expect("entropy/plogp returned NaN")
+    fn plus(&self, other: &LabelSums) -> LabelSums {
+        LabelSums {
+            count: self.count + other.count,
+            sum: self.sum + other.sum,
+            sum_sq: self.sum_sq + other.sum_sq,
+            positive: self.positive + other.positive,
+        }
     }
-}
-fn entropy(ids: &[InstanceId], dataset: &dyn RankingDataset) -> NotNan<f64> {
-    if ids.len() == 0 {
-        return NotNan::new(0.0).unwrap();
+    fn minus(&self, other: &LabelSums) -> LabelSums {
+        LabelSums {
+            count: self.count - other.count,
+            sum: self.sum - other.sum,
+            sum_sq: self.sum_sq - other.sum_sq,
+            positive: self.positive - other.positive,
+        }
+    }
+    fn squared_error(&self) -> f64 {
+        if self.count == 0.0 {
+            return 0.0;
+        }
+        // Clamp away the rounding error of sum_sq - sum^2/n.
+        (self.sum_sq - self.sum * self.sum / self.count).max(0.0)
+    }
+    fn gini_impurity(&self) -> f64 {
+        if self.count == 0.0 {
+            return 0.0;
+        }
+        let p_yes = self.positive / self.count;
+        let p_no = 1.0 - p_yes;
+        p_yes * (1.0 - p_yes) + p_no * (1.0 - p_no)
+    }
+    fn entropy(&self) -> f64 {
+        if self.count == 0.0 {
+            return 0.0;
+        }
+        let p_yes = self.positive / self.count;
+        -plogp(p_yes) - plogp(1.0 - p_yes)
+    }
+    fn variance(&self) -> f64 {
+        if self.count <= 1.0 {
+            return 0.0;
+        }
+        self.squared_error() / (self.count - 1.0)
     }
-    let count = ids.len() as f64;
-    let positive = ids
-        .iter()
-        .cloned()
-        .filter(|index| dataset.gain(*index).into_inner() > 0.0)
-        .count();
-    let p_yes = (positive as f64) / count;
-    let p_no = (count - (positive as f64)) / count;
-    let entropy = -plogp(p_yes) - plogp(p_no);
-    entropy
 }
 
 impl SplitSelectionStrategy {
-    fn importance(
-        &self,
-        lhs: &[InstanceId],
-        rhs: &[InstanceId],
-        dataset: &dyn RankingDataset,
-    ) -> NotNan<f64> {
+    /// The impurity of one side of a split, scaled by how much it holds.
+    fn impurity(&self, sums: &LabelSums) -> f64 {
         match self {
-            SplitSelectionStrategy::SquaredError() => {
-                -(squared_error(lhs, dataset) + squared_error(rhs, dataset))
-            }
-            SplitSelectionStrategy::BinaryGiniImpurity() => {
-                let lhs_w = lhs.len() as f64;
-                let rhs_w = rhs.len() as f64;
-                let lhs_gini = gini_impurity(lhs, dataset) * lhs_w;
-                let rhs_gini = gini_impurity(rhs, dataset) * rhs_w;
-                // Negative so that we minimize the impurity across the splits.
-                -(lhs_gini + rhs_gini)
-            }
-            SplitSelectionStrategy::InformationGain() => {
-                let lhs_w = lhs.len() as f64;
-                let rhs_w = rhs.len() as f64;
-                let lhs_e = entropy(lhs, dataset) * lhs_w;
-                let rhs_e = entropy(rhs, dataset) * rhs_w;
-                // Negative so that we minimize the entropy across the splits.
-                -(lhs_e + rhs_e)
-            }
-            SplitSelectionStrategy::TrueVarianceReduction() => {
-                let lhs_w = lhs.len() as f64;
-                let rhs_w = rhs.len() as f64;
-                let lhs_variance = label_stats(lhs, dataset).unwrap().variance * lhs_w;
-                let rhs_variance = label_stats(rhs, dataset).unwrap().variance * rhs_w;
-                -NotNan::new(lhs_variance + rhs_variance).expect("variance NaN")
-            }
+            SplitSelectionStrategy::SquaredError() => sums.squared_error(),
+            SplitSelectionStrategy::BinaryGiniImpurity() => sums.gini_impurity() * sums.count,
+            SplitSelectionStrategy::InformationGain() => sums.entropy() * sums.count,
+            SplitSelectionStrategy::TrueVarianceReduction() => sums.variance() * sums.count,
         }
     }
+    fn importance(&self, lhs: &LabelSums, rhs: &LabelSums) -> NotNan<f64> {
+        let impurity = self.impurity(lhs) + self.impurity(rhs);
+        // Negative so that we minimize the impurity across the splits.
+        -NotNan::new(impurity).expect("Split impurity NaN.")
+    }
+}
+
+/// How candidate thresholds are chosen for a feature at each node.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SplitFinding {
+    /// `split_candidates` evenly spaced thresholds between the node's min and max value.
+    EvenlySpaced(),
+    /// Every boundary between distinct values at the node.
+    Exact(),
+    /// Edges of `split_candidates` quantile bins, computed once per dataset.
+    Histogram(),
+}
+
+impl Default for SplitFinding {
+    fn default() -> Self {
+        SplitFinding::EvenlySpaced()
+    }
+}
+
+/// How many of a tree's features are candidates at each node ("mtry").
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FeaturesPerSplit {
+    /// Every feature the tree was given; only `feature_sampling_rate` subsets them, once per tree.
+    All(),
+    Fraction(f64),
+    /// The square root of the feature count, as Breiman suggests for classification.
+    Sqrt(),
+    Log2(),
+}
+
+impl Default for FeaturesPerSplit {
+    fn default() -> Self {
+        FeaturesPerSplit::All()
+    }
+}
+
+impl FeaturesPerSplit {
+    /// How many of `num_features` to draw at each node; always at least one.
+    pub fn count(&self, num_features: usize) -> usize {
+        let n = num_features as f64;
+        let count = match self {
+            FeaturesPerSplit::All() => return num_features,
+            FeaturesPerSplit::Fraction(frac) => (n * frac) as usize,
+            FeaturesPerSplit::Sqrt() => n.sqrt() as usize,
+            FeaturesPerSplit::Log2() => n.log2() as usize + 1,
+        };
+        cmp::min(num_features, cmp::max(1, count))
+    }
+}
+
+/// Where a split sends instances that lack its feature.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MissingValues {
+    /// Read them as 0.0, like older models do.
+    AsZero(),
+    /// Try them on both sides of each candidate split and keep the better one.
+    Learned(),
+}
+
+impl Default for MissingValues {
+    fn default() -> Self {
+        MissingValues::AsZero()
+    }
+}
+
+/// How each tree draws `instance_sampling_rate` of the training data.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InstanceSampling {
+    /// Whole queries, each at most once.
+    WithoutReplacement(),
+    /// Whole queries, with replacement.
+    QueryBootstrap(),
+    /// Single instances, with replacement; queries may come out partial.
+    InstanceBootstrap(),
+}
+
+impl Default for InstanceSampling {
+    fn default() -> Self {
+        InstanceSampling::WithoutReplacement()
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -133,9 +215,20 @@ pub struct RandomForestParams {
     pub weight_trees: bool,
     pub split_method: SplitSelectionStrategy,
     pub instance_sampling_rate: f64,
+    #[serde(default)]
+    pub instance_sampling: InstanceSampling,
     pub feature_sampling_rate: f64,
     pub min_leaf_support: u32,
     pub split_candidates: u32,
+    #[serde(default)]
+    pub split_finding: SplitFinding,
+    #[serde(default)]
+    pub features_per_split: FeaturesPerSplit,
+    #[serde(default)]
+    pub missing_values: MissingValues,
+    /// Feature name or number to the direction trees must respond in.
+    #[serde(default)]
+    pub monotone_constraints: MonotoneConstraints,
     pub max_depth: u32,
 }
 
@@ -149,16 +242,21 @@ impl Default for RandomForestParams {
             quiet: false,
             num_trees: 100,
             instance_sampling_rate: 0.5,
+            instance_sampling: InstanceSampling::WithoutReplacement(),
             feature_sampling_rate: 0.25,
             min_leaf_support: 10,
             split_candidates: 3,
+            split_finding: SplitFinding::EvenlySpaced(),
+            features_per_split: FeaturesPerSplit::All(),
+            missing_values: MissingValues::AsZero(),
+            monotone_constraints: MonotoneConstraints::new(),
             max_depth: 8,
         }
     }
 }
 
 impl TreeNode {
-    fn depth(&self) -> u32 {
+    pub fn depth(&self) -> u32 {
         match self {
             TreeNode::LeafNode(_) => 1,
             TreeNode::FeatureSplit { lhs, rhs, .. } => 1 + cmp::max(lhs.depth(), rhs.depth()),
@@ -166,123 +264,231 @@ impl TreeNode {
     }
 }
 
-struct RecursionParams {
-    current_depth: u32,
-}
-
-impl RecursionParams {
-    fn subset(&self) -> Self {
-        Self {
-            current_depth: self.current_depth + 1,
-        }
-    }
-    fn done(&self, dataset: &DatasetRef) -> bool {
-        dataset.features().is_empty() || dataset.instances().is_empty()
-    }
-    fn choose_split(
-        &self,
-        dataset: &DatasetRef,
-        fsc: &FeatureSplitCandidate,
-    ) -> (DatasetRef, DatasetRef) {
-        (
-            dataset.with_instances(&fsc.lhs).into_ref(),
-            dataset.with_instances(&fsc.rhs).into_ref(),
-        )
-    }
-    fn to_output(&self, dataset: &DatasetRef) -> NotNan<f64> {
-        compute_output(&dataset.instances(), dataset)
-    }
-}
-
-struct FeatureSplitCandidate {
-    fid: FeatureId,
-    split: NotNan<f64>,
-    lhs: Vec<InstanceId>,
-    rhs: Vec<InstanceId>,
-    importance: NotNan<f64>,
+/// Build the presorted feature index a tree learner needs, with bins if `params` asks for them.
+/// Fails if a monotone constraint names a feature that `dataset` lacks.
+pub fn build_index(
+    params: &RandomForestParams,
+    dataset: &dyn RankingDataset,
+) -> Result<FeatureIndex, Box<dyn Error>> {
+    let monotone = monotone::resolve(&params.monotone_constraints, dataset)?;
+    let index = FeatureIndex::new(dataset).with_monotone(monotone);
+    Ok(match params.split_finding {
+        SplitFinding::Histogram() => index.with_bins(params.split_candidates),
+        _ => index,
+    })
 }
 
+/// The best threshold found for one feature at one node.
 #[derive(Debug)]
 struct SplitCandidate {
+    /// How many of the node's ranked instances (see `generate_split_candidate`) go left.
     pos: usize,
     split: NotNan<f64>,
     importance: NotNan<f64>,
+    /// How much lower the children's impurity is than the node's.
+    gain: f64,
+    missing_left: Option<bool>,
 }
 
 fn generate_split_candidate(
     params: &RandomForestParams,
-    fid: FeatureId,
-    dataset: &dyn RankingDataset,
-    stats: &stats::ComputedStats,
-) -> Option<FeatureSplitCandidate> {
-    let instances = dataset.instances();
-    let label_stats = label_stats(&instances, dataset)?;
-    if label_stats.max == label_stats.min {
+    column: &FeatureColumn,
+    monotone: Option<&Monotonicity>,
+    ids: &[InstanceId],
+    gains: &[f64],
+    weights: &[f64],
+) -> Option<SplitCandidate> {
+    // Like FeatureStats, the range only counts values that are present.
+    let mut num_present = 0;
+    let mut min = f64::MAX;
+    let mut max = f64::MIN;
+    for id in ids.iter().filter(|id| column.is_present(**id)) {
+        let val = column.value(*id);
+        num_present += 1;
+        min = min.min(val);
+        max = max.max(val);
+    }
+    if num_present <= 1 {
         return None;
     }
-    let k = params.split_candidates;
-    let range = stats.max - stats.min;
 
-    let mut instance_feature: Vec<Scored<InstanceId>> = instances
+    // When learning where missing values go, thresholds only rank the present values and the
+    // missing ones are tried on either side; otherwise missing values rank as 0.0.
+    let learned = params.missing_values == MissingValues::Learned();
+    let (ranked, missing): (Vec<InstanceId>, Vec<InstanceId>) = if learned {
+        ids.iter().partition(|id| column.is_present(**id))
+    } else {
+        (ids.to_vec(), Vec::new())
+    };
+    let scores: Vec<NotNan<f64>> = ranked
         .iter()
-        .cloned()
-        .map(|i| Scored::new(dataset.get_feature_value(i, fid).unwrap_or(0.0), i))
+        .map(|id| NotNan::new(column.value(*id)).expect("NaN feature value"))
         .collect();
-    instance_feature.sort_unstable();
 
-    let scores: Vec<NotNan<f64>> = instance_feature.iter().map(|sf| sf.score).collect();
-    let ids: Vec<InstanceId> = instance_feature.into_iter().map(|sf| sf.item).collect();
-
-    // TODO all splits instead...
-    let splits: Vec<NotNan<f64>> = (1..k)
-        .map(|i| (i as f64) / (k as f64))
-        .map(|f| NotNan::new(f * range + stats.min).unwrap())
-        .collect();
-
-    // collect instance index in ids/scores where the "splits" are.
+    // Each candidate is (threshold, position in ranked where the right side starts).
     let mut split_positions: Vec<Scored<usize>> = Vec::new();
-    let mut ids_i = 0;
-    for position in splits.iter() {
-        // linearly classify:
-        while ids_i < ids.len() && scores[ids_i] < *position {
-            ids_i += 1;
+    match params.split_finding {
+        SplitFinding::Exact() => {
+            for i in 1..scores.len() {
+                if scores[i - 1] != scores[i] {
+                    let split = (scores[i - 1] + scores[i]) / 2.0;
+                    split_positions.push(Scored::new(split.into_inner(), i));
+                }
+            }
         }
-        if let Some(prev) = split_positions.last() {
-            if prev.item == ids_i {
-                continue;
+        SplitFinding::Histogram() => {
+            for split in column.bins().iter() {
+                let pos = scores.partition_point(|s| s <= split);
+                if pos == 0 || pos == scores.len() {
+                    continue;
+                }
+                if let Some(prev) = split_positions.last() {
+                    if prev.item == pos {
+                        continue;
+                    }
+                }
+                split_positions.push(Scored::new(split.into_inner(), pos));
+            }
+        }
+        SplitFinding::EvenlySpaced() => {
+            let k = params.split_candidates;
+            let range = max - min;
+            let splits: Vec<NotNan<f64>> = (1..k)
+                .map(|i| (i as f64) / (k as f64))
+                .map(|f| NotNan::new(f * range + min).unwrap())
+                .collect();
+
+            // collect instance index in ids/scores where the "splits" are.
+            let mut ids_i = 0;
+            for position in splits.iter() {
+                // linearly classify:
+                while ids_i < ranked.len() && scores[ids_i] < *position {
+                    ids_i += 1;
+                }
+                if let Some(prev) = split_positions.last() {
+                    if prev.item == ids_i {
+                        continue;
+                    }
+                }
+                split_positions.push(Scored::new(position.into_inner(), ids_i));
             }
         }
-        split_positions.push(Scored::new(position.into_inner(), ids_i));
     }
-    // evaluate the splits!
+
+    // evaluate the splits in one pass over the sorted instances!
+    let mut missing_sums = LabelSums::default();
+    for id in missing.iter() {
+        missing_sums.push(gains[id.to_index()], weights[id.to_index()]);
+    }
+    let mut total = LabelSums::default();
+    for id in ranked.iter() {
+        total.push(gains[id.to_index()], weights[id.to_index()]);
+    }
+    let total = total.plus(&missing_sums);
+    let node_impurity = params.split_method.impurity(&total);
+    let mut left = LabelSums::default();
+    let mut ids_i = 0;
     let mut best = Vec::new();
     for scored_index in split_positions.into_iter() {
         let right_side = scored_index.item;
         let split = scored_index.score;
-        let (lhs, rhs) = ids.split_at(right_side);
-        if lhs.len() < params.min_leaf_support as usize
-            || rhs.len() < params.min_leaf_support as usize
-        {
-            continue;
+        while ids_i < right_side {
+            let id = ranked[ids_i].to_index();
+            left.push(gains[id], weights[id]);
+            ids_i += 1;
+        }
+        let directions = if !learned {
+            vec![None]
+        } else if missing.is_empty() {
+            // Nothing to learn from here; send missing values where 0.0 would go.
+            vec![Some(0.0 <= split.into_inner())]
+        } else {
+            vec![Some(false), Some(true)]
+        };
+        for missing_left in directions {
+            let (lhs, num_left) = if missing_left == Some(true) {
+                (left.plus(&missing_sums), right_side + missing.len())
+            } else {
+                (left.clone(), right_side)
+            };
+            if num_left < params.min_leaf_support as usize
+                || ids.len() - num_left < params.min_leaf_support as usize
+            {
+                continue;
+            }
+            let rhs = total.minus(&lhs);
+            if let Some(monotone) = monotone {
+                if !monotone.allows(lhs.sum / lhs.count, rhs.sum / rhs.count) {
+                    continue;
+                }
+            }
+            let importance = params.split_method.importance(&lhs, &rhs);
+            best.push(SplitCandidate {
+                pos: right_side,
+                split,
+                importance,
+                gain: node_impurity + importance.into_inner(),
+                missing_left,
+            });
         }
-        let importance = params.split_method.importance(lhs, rhs, dataset);
-        best.push(SplitCandidate {
-            pos: right_side,
-            split,
-            importance,
-        });
     }
 
     best.sort_unstable_by_key(|sc| sc.importance);
-    best.last().map(|sc| {
-        let (lhs, rhs) = ids.split_at(sc.pos);
-        FeatureSplitCandidate {
-            fid,
-            split: sc.split,
-            importance: sc.importance,
-            lhs: lhs.to_vec(),
-            rhs: rhs.to_vec(),
+    best.pop()
+}
+
+/// The forest scored only on training queries each tree never saw.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutOfBag {
+    /// For each tree of the forest, in order, the queries left out of its sample.
+    pub tree_queries: Vec<Vec<String>>,
+    /// The training measure on every query some tree left out.
+    pub scores: HashMap<String, f64>,
+    pub mean: f64,
+}
+
+/// The members of a forest that never saw a particular query.
+#[derive(Debug)]
+struct OutOfBagEnsemble<'a> {
+    members: Vec<&'a Scored<ModelEnum>>,
+}
+
+impl<'a> Model for OutOfBagEnsemble<'a> {
+    fn score(&self, features: &dyn FeatureRead) -> NotNan<f64> {
+        let mut output = 0.0;
+        for member in self.members.iter() {
+            output += member.score.into_inner() * member.item.score(features).into_inner();
         }
+        NotNan::new(output).expect("NaN produced by ensemble.")
+    }
+}
+
+fn out_of_bag(
+    members: &[Scored<ModelEnum>],
+    tree_queries: Vec<Vec<String>>,
+    evaluator: &SetEvaluator,
+) -> Option<OutOfBag> {
+    let mut models: HashMap<&str, OutOfBagEnsemble> = HashMap::new();
+    for (member, queries) in members.iter().zip(tree_queries.iter()) {
+        for qid in queries.iter() {
+            models
+                .entry(qid.as_str())
+                .or_insert_with(|| OutOfBagEnsemble {
+                    members: Vec::new(),
+                })
+                .members
+                .push(member);
+        }
+    }
+    if models.is_empty() {
+        return None;
+    }
+    let scores = evaluator.evaluate_to_map_with(|qid| models.get(qid).map(|m| m as &dyn Model));
+    let mean = evaluator.weighted_mean(&scores);
+    Some(OutOfBag {
+        tree_queries,
+        scores,
+        mean,
     })
 }
 
@@ -290,69 +496,172 @@ pub fn learn_ensemble(
     params: &RandomForestParams,
     dataset: &DatasetRef,
     evaluator: &SetEvaluator,
-) -> WeightedEnsemble {
+    validation: Option<&ValidationSet>,
+) -> Result<WeightedEnsemble, Box<dyn Error>> {
+    Ok(learn_ensemble_with_oob(params, dataset, evaluator, validation)?.0)
+}
+
+/// Learn a forest and also estimate its quality from the queries each tree's sample left out.
+/// The estimate is None when every tree saw every query.
+pub fn learn_ensemble_with_oob(
+    params: &RandomForestParams,
+    dataset: &DatasetRef,
+    evaluator: &SetEvaluator,
+    validation: Option<&ValidationSet>,
+) -> Result<(WeightedEnsemble, Option<OutOfBag>), Box<dyn Error>> {
     let mut rand = Rand64::new(params.seed.into());
     let seeds: Vec<(u32, u64)> = (0..params.num_trees)
         .map(|i| (i, rand.rand_u64()))
         .collect();
 
-    let mut trees: Vec<Scored<TreeNode>> = Vec::new();
+    let index = build_index(params, dataset)?;
+    let mut queries = dataset.queries();
+    queries.sort_unstable();
     if !params.quiet {
         println!("-----------------------");
         println!("|{:>7}|{:>7}|{:>7}|", "Tree", "Depth", evaluator.name());
         println!("-----------------------");
     }
 
-    trees.par_extend(seeds.par_iter().map(|(idx, rand_seed)| {
+    let trees = seeds.par_iter().map(|(idx, rand_seed)| {
         let mut local_rand = Rand64::new((*rand_seed).into());
-        let subsample = dataset
-            .random_sample(
-                params.feature_sampling_rate,
-                params.instance_sampling_rate,
-                &mut local_rand,
-            )
-            .into_ref();
-        let tree = learn_decision_tree(params, &subsample);
+        let (frate, srate) = (params.feature_sampling_rate, params.instance_sampling_rate);
+        let subsample = match params.instance_sampling {
+            InstanceSampling::WithoutReplacement() => {
+                dataset.random_sample(frate, srate, &mut local_rand)
+            }
+            InstanceSampling::QueryBootstrap() => {
+                dataset.bootstrap_sample(frate, srate, true, &mut local_rand)
+            }
+            InstanceSampling::InstanceBootstrap() => {
+                dataset.bootstrap_sample(frate, srate, false, &mut local_rand)
+            }
+        }
+        .into_ref();
+        let tree = learn_decision_tree_indexed(params, &subsample, &index, &mut local_rand);
         let eval = evaluator.evaluate_mean(&tree);
         if !params.quiet {
             println!("|{:>7}|{:>7}|{:>7.3}|", idx + 1, tree.depth(), eval);
         }
-        Scored::new(eval, tree)
-    }));
+        let seen: HashSet<String> = subsample.queries().into_iter().collect();
+        let unseen = queries
+            .iter()
+            .filter(|qid| !seen.contains(*qid))
+            .cloned()
+            .collect();
+        Ok((Scored::try_new(eval, tree)?, unseen))
+    });
+    let trees: Vec<(Scored<TreeNode>, Vec<String>)> =
+        trees.collect::<Result<_, FastRankError>>()?;
+
+    let (trees, mut tree_queries): (Vec<Scored<TreeNode>>, Vec<Vec<String>>) =
+        trees.into_iter().unzip();
+    let mut members: Vec<Scored<ModelEnum>> = trees
+        .into_iter()
+        .map(|tree| {
+            let m = ModelEnum::DecisionTree(tree.item);
+            Scored::new(
+                if params.weight_trees {
+                    tree.score.into_inner()
+                } else {
+                    1.0
+                },
+                m,
+            )
+        })
+        .collect();
+
+    // Trees are grown in parallel, so pick the forest size afterwards from its prefixes.
+    let mut stopping = EarlyStopping::new(validation);
+    for size in 1..=members.len() {
+        if stopping.observe(size, || WeightedEnsemble::new(members[..size].to_vec())) {
+            break;
+        }
+    }
+    let size = stopping.best_size().unwrap_or(members.len());
+    members.truncate(size);
+    tree_queries.truncate(size);
+    let oob = out_of_bag(&members, tree_queries, evaluator);
 
     if !params.quiet {
         println!("-----------------------");
+        stopping.print_summary();
+        if let Some(oob) = oob.as_ref() {
+            println!(
+                "Out-of-bag {}: {:.3} over {} queries.",
+                evaluator.name(),
+                oob.mean,
+                oob.scores.len()
+            );
+        }
     }
 
-    WeightedEnsemble::new(
-        trees
-            .into_iter()
-            .map(|tree| {
-                let m = ModelEnum::DecisionTree(tree.item);
-                Scored::new(
-                    if params.weight_trees {
-                        tree.score.into_inner()
-                    } else {
-                        1.0
-                    },
-                    m,
-                )
-            })
-            .collect(),
-    )
+    Ok((WeightedEnsemble::new(members), oob))
 }
 
-pub fn learn_decision_tree(params: &RandomForestParams, dataset: &DatasetRef) -> TreeNode {
-    let step = RecursionParams { current_depth: 1 };
+pub fn learn_decision_tree(
+    params: &RandomForestParams,
+    dataset: &DatasetRef,
+) -> Result<TreeNode, Box<dyn Error>> {
+    let index = build_index(params, dataset)?;
+    let mut rand = Rand64::new(params.seed.into());
+    Ok(learn_decision_tree_indexed(
+        params, dataset, &index, &mut rand,
+    ))
+}
 
-    let root = learn_recursive(params, dataset, &step);
-    match root {
-        Ok(tree) => tree,
-        Err(_e) => {
-            TreeNode::LeafNode(step.to_output(dataset))
-            //panic!("{:?} fids={:?} N={}", _e, step.features, step.instances.len())
-        }
+/// Learn a tree on the instances, features and labels of `dataset`, reading feature values from
+/// `index`; build the index once (see `build_index`) and share it between trees.
+/// `rand` is only drawn from when `params.features_per_split` samples features at each node.
+pub fn learn_decision_tree_indexed(
+    params: &RandomForestParams,
+    dataset: &dyn RankingDataset,
+    index: &FeatureIndex,
+    rand: &mut Rand64,
+) -> TreeNode {
+    let instances = dataset.instances();
+    let mut gains = vec![0.0; index.num_rows()];
+    let mut weights = vec![0.0; index.num_rows()];
+    // A bootstrap sample may hold an instance more than once.
+    let mut copies = vec![0; index.num_rows()];
+    for id in instances.iter() {
+        gains[id.to_index()] = f64::from(dataset.gain(*id).into_inner());
+        weights[id.to_index()] =
+            dataset.instance_weight(*id) * dataset.query_weight(dataset.query_id(*id));
+        copies[id.to_index()] += 1;
     }
+    let columns: Vec<(FeatureId, &FeatureColumn)> = dataset
+        .features()
+        .into_iter()
+        .flat_map(|fid| index.column(fid).map(|column| (fid, column)))
+        .collect();
+    let root = Node {
+        sorted: columns
+            .iter()
+            .map(|(_, column)| {
+                column
+                    .sorted()
+                    .iter()
+                    .flat_map(|id| iter::repeat_n(*id, copies[id.to_index()]))
+                    .collect()
+            })
+            .collect(),
+        instances,
+    };
+
+    let mut learner = TreeLearner {
+        params,
+        columns,
+        gains,
+        weights,
+        goes_left: vec![false; index.num_rows()],
+        monotone: index.monotone(),
+        rand,
+    };
+    let output = learner.output(&root);
+    let mut tree = learner.learn(root, 1).unwrap_or(TreeNode::LeafNode(output));
+    monotone::clamp_leaves(&mut tree, index.monotone());
+    tree
 }
 
 #[derive(Debug, Clone)]
@@ -363,51 +672,148 @@ enum NoTreeReason {
     NoFeatureSplitCandidates,
 }
 
-fn learn_recursive(
-    params: &RandomForestParams,
-    dataset: &DatasetRef,
-    step: &RecursionParams,
-) -> Result<TreeNode, NoTreeReason> {
-    // Gone too deep:
-    if step.done(dataset) {
-        return Err(NoTreeReason::StepDone);
+/// The instances that reach one node, also kept sorted by every candidate feature.
+struct Node {
+    instances: Vec<InstanceId>,
+    /// Parallel to `TreeLearner::columns`.
+    sorted: Vec<Vec<InstanceId>>,
+}
+
+struct TreeLearner<'a> {
+    params: &'a RandomForestParams,
+    columns: Vec<(FeatureId, &'a FeatureColumn)>,
+    gains: Vec<f64>,
+    /// Instance times query weight, by InstanceId.
+    weights: Vec<f64>,
+    /// Scratch space for partitioning a node; all false between splits.
+    goes_left: Vec<bool>,
+    monotone: &'a HashMap<FeatureId, Monotonicity>,
+    rand: &'a mut Rand64,
+}
+
+impl<'a> TreeLearner<'a> {
+    fn output(&self, node: &Node) -> NotNan<f64> {
+        let mut gain_sum = 0.0;
+        let mut weight_sum = 0.0;
+        for id in node.instances.iter() {
+            let weight = self.weights[id.to_index()];
+            gain_sum += weight * self.gains[id.to_index()];
+            weight_sum += weight;
+        }
+        if weight_sum == 0.0 {
+            return NotNan::new(0.0).unwrap();
+        }
+        NotNan::new(gain_sum / weight_sum).expect("Leaf output NaN.")
     }
-    if step.current_depth >= params.max_depth {
-        return Err(NoTreeReason::DepthExceeded);
+
+    /// Positions in `columns` to consider at the next node, in their original order.
+    fn sample_features(&mut self) -> Vec<usize> {
+        let all: Vec<usize> = (0..self.columns.len()).collect();
+        let count = self.params.features_per_split.count(all.len());
+        if count == all.len() {
+            return all;
+        }
+        let mut chosen = randutil::sample_without_replacement(&all, self.rand, count);
+        chosen.sort_unstable();
+        chosen
     }
-    // Cannot split further:
-    if dataset.instances().len() < (params.min_leaf_support) as usize {
-        return Err(NoTreeReason::SplitTooSmall);
+
+    fn labels_differ(&self, node: &Node) -> bool {
+        let mut gains = node.instances.iter().map(|id| self.gains[id.to_index()]);
+        match gains.next() {
+            Some(first) => gains.any(|g| g != first),
+            None => false,
+        }
     }
 
-    let feature_stats = FeatureStats::compute(dataset).feature_stats;
+    /// Split `node` on feature `k` as `best` says, keeping every feature's order.
+    fn partition(&mut self, node: Node, k: usize, best: &SplitCandidate) -> (Node, Node) {
+        let column = self.columns[k].1;
+        let mut rank = 0;
+        for id in node.sorted[k].iter() {
+            let ranked = best.missing_left.is_none() || column.is_present(*id);
+            self.goes_left[id.to_index()] = if ranked {
+                rank += 1;
+                rank <= best.pos
+            } else {
+                best.missing_left == Some(true)
+            };
+        }
+        let mut left = Node {
+            instances: Vec::new(),
+            sorted: Vec::new(),
+        };
+        let mut right = Node {
+            instances: Vec::new(),
+            sorted: Vec::new(),
+        };
+        for ids in node.sorted.into_iter() {
+            let (l, r): (Vec<InstanceId>, Vec<InstanceId>) = ids
+                .into_iter()
+                .partition(|id| self.goes_left[id.to_index()]);
+            left.sorted.push(l);
+            right.sorted.push(r);
+        }
+        left.instances = left.sorted[k].clone();
+        right.instances = right.sorted[k].clone();
+        for id in left.instances.iter() {
+            self.goes_left[id.to_index()] = false;
+        }
+        (left, right)
+    }
 
-    let mut candidates: Vec<FeatureSplitCandidate> = dataset
-        .features()
-        .iter()
-        .flat_map(|fid| {
-            feature_stats
-                .get(fid)
-                .and_then(|stats| generate_split_candidate(params, *fid, dataset, stats))
-        })
-        .collect();
+    fn learn(&mut self, node: Node, depth: u32) -> Result<TreeNode, NoTreeReason> {
+        let params = self.params;
+        // Gone too deep:
+        if self.columns.is_empty() || node.instances.is_empty() {
+            return Err(NoTreeReason::StepDone);
+        }
+        if depth >= params.max_depth {
+            return Err(NoTreeReason::DepthExceeded);
+        }
+        // Cannot split further:
+        if node.instances.len() < (params.min_leaf_support) as usize {
+            return Err(NoTreeReason::SplitTooSmall);
+        }
+        if node.instances.len() <= 1 || !self.labels_differ(&node) {
+            return Err(NoTreeReason::NoFeatureSplitCandidates);
+        }
 
-    candidates.sort_unstable_by_key(|fsc| fsc.importance);
-    if let Some(fsc) = candidates.last() {
-        let (lhs_d, rhs_d) = step.choose_split(dataset, fsc);
+        let mut candidates: Vec<(usize, SplitCandidate)> = self
+            .sample_features()
+            .into_iter()
+            .flat_map(|k| {
+                let (fid, column) = self.columns[k];
+                let monotone = self.monotone.get(&fid);
+                let ids = &node.sorted[k];
+                generate_split_candidate(params, column, monotone, ids, &self.gains, &self.weights)
+                    .map(|sc| (k, sc))
+            })
+            .collect();
+
+        candidates.sort_unstable_by_key(|(_, sc)| sc.importance);
+        let (k, best) = match candidates.pop() {
+            Some(c) => c,
+            None => return Err(NoTreeReason::NoFeatureSplitCandidates),
+        };
+        let (lhs, rhs) = self.partition(node, k, &best);
 
-        let left_child = learn_recursive(params, &lhs_d, &step.subset())
-            .unwrap_or_else(|_| TreeNode::LeafNode(step.to_output(&lhs_d)));
-        let right_child = learn_recursive(params, &rhs_d, &step.subset())
-            .unwrap_or_else(|_| TreeNode::LeafNode(step.to_output(&rhs_d)));
+        let lhs_output = self.output(&lhs);
+        let left_child = self
+            .learn(lhs, depth + 1)
+            .unwrap_or(TreeNode::LeafNode(lhs_output));
+        let rhs_output = self.output(&rhs);
+        let right_child = self
+            .learn(rhs, depth + 1)
+            .unwrap_or(TreeNode::LeafNode(rhs_output));
         Ok(TreeNode::FeatureSplit {
-            fid: fsc.fid,
-            split: fsc.split,
+            fid: self.columns[k].0,
+            split: best.split,
             lhs: Box::new(left_child),
             rhs: Box::new(right_child),
+            missing_left: best.missing_left,
+            gain: best.gain,
         })
-    } else {
-        Err(NoTreeReason::NoFeatureSplitCandidates)
     }
 }
 
@@ -452,7 +858,7 @@ mod test {
         let eval = SetEvaluator::create(&train_dataset, "ndcg@5", None).unwrap();
         let mut means = Vec::new();
         for i in 0..10 {
-            let model = learn_ensemble(&params, &train_dataset, &eval);
+            let model = learn_ensemble(&params, &train_dataset, &eval, None).unwrap();
             means.push(eval.evaluate_mean(&model));
             if i > 0 {
                 assert_float_eq(
@@ -483,7 +889,7 @@ mod test {
             .map(|(i, x)| Instance::new(ys[i], "query".to_string(), None, single_feature(*x)))
             .collect();
 
-        let dataset = DatasetRef::new(training_instances, None);
+        let dataset = DatasetRef::new(training_instances, None).unwrap();
         let params = RandomForestParams {
             num_trees: 1,
             min_leaf_support: 1,
@@ -492,7 +898,7 @@ mod test {
             split_method: SplitSelectionStrategy::SquaredError(),
             ..RandomForestParams::default()
         };
-        let tree = learn_decision_tree(&params, &dataset);
+        let tree = learn_decision_tree(&params, &dataset).unwrap();
 
         for inst in dataset.instances() {
             let py = dataset.score(inst, &tree);
@@ -508,4 +914,229 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_weighted_leaf_output() {
+        // One feature value for everyone, so the tree is a single leaf.
+        let training_instances: Vec<Instance> = [1, 1, 1, 5]
+            .iter()
+            .map(|y| {
+                let gain = NotNan::new(*y as f32).unwrap();
+                Instance::new(gain, "query".to_string(), None, single_feature(1.0))
+            })
+            .collect();
+        let dataset = DatasetRef::new(training_instances, None).unwrap();
+        let weighted =
+            dataset::WeightedDatasetRef::new(&dataset, Some(vec![1.0, 1.0, 1.0, 3.0]), None)
+                .unwrap()
+                .into_ref();
+        let params = RandomForestParams {
+            num_trees: 1,
+            ..RandomForestParams::default()
+        };
+        let plain = learn_decision_tree(&params, &dataset).unwrap();
+        let tree = learn_decision_tree(&params, &weighted).unwrap();
+        let id = dataset.instances()[0];
+        assert_float_eq("unweighted", *dataset.score(id, &plain), 2.0);
+        assert_float_eq("weighted", *dataset.score(id, &tree), 3.0);
+    }
+
+    #[test]
+    fn test_split_finding_on_skewed_feature() {
+        // All of the signal lives between 0 and 2, but one outlier stretches the range to 1000.
+        let xs = [0, 0, 0, 0, 1, 1, 1, 2, 2, 1000];
+        let ys = [0, 0, 0, 0, 5, 5, 5, 9, 9, 9];
+        let training_instances: Vec<Instance> = xs
+            .iter()
+            .zip(ys.iter())
+            .map(|(x, y)| {
+                Instance::new(
+                    NotNan::new(*y as f32).unwrap(),
+                    "query".to_string(),
+                    None,
+                    single_feature(*x as f32),
+                )
+            })
+            .collect();
+        let dataset = DatasetRef::new(training_instances, None).unwrap();
+
+        let fits_exactly = |split_finding: SplitFinding| {
+            let params = RandomForestParams {
+                num_trees: 1,
+                min_leaf_support: 1,
+                // Room for just two levels of splits.
+                max_depth: 3,
+                split_candidates: 4,
+                split_finding,
+                ..RandomForestParams::default()
+            };
+            let tree = learn_decision_tree(&params, &dataset).unwrap();
+            dataset.instances().into_iter().all(|inst| {
+                let diff = dataset.score(inst, &tree).into_inner()
+                    - f64::from(dataset.gain(inst).into_inner());
+                diff.abs() < DELTA
+            })
+        };
+        assert!(!fits_exactly(SplitFinding::EvenlySpaced()));
+        assert!(fits_exactly(SplitFinding::Exact()));
+        assert!(fits_exactly(SplitFinding::Histogram()));
+    }
+
+    #[test]
+    fn test_features_per_split() {
+        assert_eq!(25, FeaturesPerSplit::All().count(25));
+        assert_eq!(5, FeaturesPerSplit::Sqrt().count(25));
+        assert_eq!(5, FeaturesPerSplit::Log2().count(25));
+        assert_eq!(1, FeaturesPerSplit::Fraction(0.01).count(25));
+        assert_eq!(25, FeaturesPerSplit::Fraction(2.0).count(25));
+
+        let train_dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let params = RandomForestParams {
+            num_trees: 5,
+            seed: 42,
+            quiet: true,
+            feature_sampling_rate: 1.0,
+            features_per_split: FeaturesPerSplit::Sqrt(),
+            ..RandomForestParams::default()
+        };
+        let eval = SetEvaluator::create(&train_dataset, "ndcg@5", None).unwrap();
+        let first =
+            eval.evaluate_mean(&learn_ensemble(&params, &train_dataset, &eval, None).unwrap());
+        let second =
+            eval.evaluate_mean(&learn_ensemble(&params, &train_dataset, &eval, None).unwrap());
+        assert_float_eq("same seed, same forest", first, second);
+    }
+
+    #[test]
+    fn test_bootstrap_out_of_bag() {
+        let train_dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let eval = SetEvaluator::create(&train_dataset, "ndcg@5", None).unwrap();
+        let all_queries: HashSet<String> = train_dataset.queries().into_iter().collect();
+        for instance_sampling in &[
+            InstanceSampling::QueryBootstrap(),
+            InstanceSampling::InstanceBootstrap(),
+        ] {
+            let params = RandomForestParams {
+                num_trees: 10,
+                seed: 42,
+                quiet: true,
+                instance_sampling_rate: 1.0,
+                instance_sampling: instance_sampling.clone(),
+                ..RandomForestParams::default()
+            };
+            let (model, oob) =
+                learn_ensemble_with_oob(&params, &train_dataset, &eval, None).unwrap();
+            let oob = oob.expect("A bootstrap should leave some queries out.");
+            assert_eq!(10, model.len());
+            assert_eq!(10, oob.tree_queries.len());
+            for qid in oob.scores.keys() {
+                assert!(all_queries.contains(qid));
+                assert!(oob.tree_queries.iter().any(|qs| qs.contains(qid)));
+            }
+            assert!(oob.mean > 0.0 && oob.mean <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_out_of_bag_mean_uses_query_weights() {
+        let train_dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let query_weights: HashMap<String, f64> = train_dataset
+            .queries()
+            .into_iter()
+            .enumerate()
+            .map(|(i, qid)| (qid, (i % 3) as f64))
+            .collect();
+        let weighted =
+            dataset::WeightedDatasetRef::new(&train_dataset, None, Some(query_weights.clone()))
+                .unwrap()
+                .into_ref();
+        let eval = SetEvaluator::create(&weighted, "ndcg@5", None).unwrap();
+        let params = RandomForestParams {
+            num_trees: 10,
+            seed: 42,
+            quiet: true,
+            instance_sampling_rate: 1.0,
+            instance_sampling: InstanceSampling::QueryBootstrap(),
+            ..RandomForestParams::default()
+        };
+        let (_, oob) = learn_ensemble_with_oob(&params, &weighted, &eval, None).unwrap();
+        let oob = oob.expect("A bootstrap should leave some queries out.");
+        let (sum, total) = oob
+            .scores
+            .iter()
+            .fold((0.0, 0.0), |(sum, total), (qid, s)| {
+                let w = query_weights[qid];
+                (sum + w * s, total + w)
+            });
+        assert!((oob.mean - sum / total).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_learned_missing_direction() {
+        // Missing looks like the high values, not like 0.0, which sits among the low ones.
+        let xs = [
+            Some(-1.0),
+            Some(1.0),
+            Some(2.0),
+            Some(3.0),
+            Some(4.0),
+            None,
+            None,
+        ];
+        let ys = [0, 0, 0, 10, 10, 10, 10];
+        let training_instances: Vec<Instance> = xs
+            .iter()
+            .zip(ys.iter())
+            .map(|(x, y)| {
+                let features = x
+                    .iter()
+                    .map(|x| (FeatureId::from_index(0), *x as f32))
+                    .collect();
+                Instance::new(
+                    NotNan::new(*y as f32).unwrap(),
+                    "query".to_string(),
+                    None,
+                    Features::Sparse32(features),
+                )
+            })
+            .collect();
+        let dataset = DatasetRef::new(training_instances, None).unwrap();
+
+        let learn = |missing_values: MissingValues| {
+            let params = RandomForestParams {
+                num_trees: 1,
+                min_leaf_support: 1,
+                // A single split.
+                max_depth: 2,
+                split_finding: SplitFinding::Exact(),
+                missing_values,
+                ..RandomForestParams::default()
+            };
+            learn_decision_tree(&params, &dataset).unwrap()
+        };
+        let fits_exactly = |tree: &TreeNode| {
+            dataset.instances().into_iter().all(|inst| {
+                let diff = dataset.score(inst, tree).into_inner()
+                    - f64::from(dataset.gain(inst).into_inner());
+                diff.abs() < DELTA
+            })
+        };
+
+        assert!(!fits_exactly(&learn(MissingValues::AsZero())));
+        let tree = learn(MissingValues::Learned());
+        assert!(fits_exactly(&tree));
+        match tree {
+            TreeNode::FeatureSplit { missing_left, .. } => assert_eq!(Some(false), missing_left),
+            TreeNode::LeafNode(_) => panic!("Expected a split!"),
+        }
+    }
 }
@@ -12,13 +12,13 @@ pub fn sample_with_replacement<T: Clone>(data: &[T], rand: &mut Rand64, count: u
 }
 
 pub fn sample_without_replacement<T: Clone>(data: &[T], rand: &mut Rand64, count: usize) -> Vec<T> {
-    let mut in_vec: Vec<T> = data.iter().cloned().collect();
+    let mut in_vec: Vec<T> = data.to_vec();
     shuffle(&mut in_vec, rand);
     in_vec.into_iter().take(count).collect()
 }
 
 /// Shuffle a vector.
-pub fn shuffle<T>(vec: &mut Vec<T>, rand: &mut Rand64) {
+pub fn shuffle<T>(vec: &mut [T], rand: &mut Rand64) {
     let n = vec.len() as u64;
     for i in 0..n {
         let j = rand.rand_range(i..n) as usize;
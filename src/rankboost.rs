@@ -0,0 +1,244 @@
+use crate::dataset::RankingDataset;
+use crate::evaluators::SetEvaluator;
+use crate::model::{ModelEnum, TreeNode, WeightedEnsemble};
+use crate::validation::{EarlyStopping, ValidationSet};
+use crate::{FeatureId, InstanceId, Scored};
+use ordered_float::NotNan;
+use std::collections::HashMap;
+use std::error::Error;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RankBoostParams {
+    pub quiet: bool,
+    pub num_rounds: u32,
+    /// Thresholds tried per feature, taken at evenly spaced ranks of its sorted values.
+    pub threshold_candidates: u32,
+}
+
+impl Default for RankBoostParams {
+    fn default() -> Self {
+        Self {
+            quiet: false,
+            num_rounds: 300,
+            threshold_candidates: 10,
+        }
+    }
+}
+
+/// A crucial pair: `better` should be ranked above `worse`.
+struct CrucialPair {
+    better: usize,
+    worse: usize,
+}
+
+/// A feature's training values, sorted largest first, with the thresholds worth trying.
+struct SortedFeature {
+    fid: FeatureId,
+    /// (value, dense index) largest to smallest.
+    values: Vec<(NotNan<f64>, usize)>,
+    /// Positions in `values` after which a threshold can be placed.
+    cut_points: Vec<usize>,
+}
+
+impl SortedFeature {
+    fn new(fid: FeatureId, ids: &[InstanceId], dataset: &dyn RankingDataset, k: u32) -> Self {
+        let mut values: Vec<(NotNan<f64>, usize)> = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| {
+                let val = dataset.get_feature_value(*id, fid).unwrap_or(0.0);
+                (NotNan::new(val).expect("NaN feature value"), i)
+            })
+            .collect();
+        values.sort_unstable_by(|lhs, rhs| rhs.cmp(lhs));
+
+        // Cut after position i means "values[..=i] fire"; only cut between distinct values.
+        let n = values.len();
+        let mut cut_points: Vec<usize> = (1..=k as usize)
+            .map(|c| (c * n) / (k as usize + 1))
+            .filter(|pos| *pos > 0 && *pos < n)
+            .map(|pos| {
+                let mut i = pos - 1;
+                while i + 1 < n && values[i + 1].0 == values[i].0 {
+                    i += 1;
+                }
+                i
+            })
+            .filter(|i| i + 1 < n)
+            .collect();
+        cut_points.dedup();
+        Self {
+            fid,
+            values,
+            cut_points,
+        }
+    }
+}
+
+/// RankBoost (Freund et al., 2003) with binary threshold weak rankers: h(x) = 1 if x[f] > theta.
+/// Each weak ranker is stored as a decision stump so the output is an ordinary ensemble.
+pub fn learn(
+    params: &RankBoostParams,
+    dataset: &dyn RankingDataset,
+    evaluator: &SetEvaluator,
+    validation: Option<&ValidationSet>,
+) -> Result<ModelEnum, Box<dyn Error>> {
+    let ids = dataset.instances();
+    let dense: HashMap<InstanceId, usize> =
+        ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+    let mut queries: Vec<(String, Vec<InstanceId>)> =
+        dataset.instances_by_query().into_iter().collect();
+    queries.sort_unstable();
+    let mut pairs: Vec<CrucialPair> = Vec::new();
+    for (_qid, docs) in queries.iter() {
+        for i in docs.iter() {
+            for j in docs.iter() {
+                if dataset.gain(*i) > dataset.gain(*j) {
+                    pairs.push(CrucialPair {
+                        better: dense[i],
+                        worse: dense[j],
+                    });
+                }
+            }
+        }
+    }
+    let mut distribution = vec![1.0 / (pairs.len() as f64); pairs.len()];
+
+    let mut fids = dataset.features();
+    fids.sort_unstable();
+    let sorted_features: Vec<SortedFeature> = fids
+        .iter()
+        .map(|fid| SortedFeature::new(*fid, &ids, dataset, params.threshold_candidates))
+        .collect();
+
+    let mut members: Vec<Scored<ModelEnum>> = Vec::new();
+    let mut stopping = EarlyStopping::new(validation);
+
+    if !params.quiet {
+        println!("----------------------------------------");
+        println!(
+            "|{:>7}|{:<16}|{:>8}|{:>7}|",
+            "Round",
+            "Feature",
+            "Theta",
+            evaluator.name()
+        );
+        println!("----------------------------------------");
+    }
+
+    for round in 0..params.num_rounds {
+        if pairs.is_empty() {
+            break;
+        }
+        // pi(x) is how much the distribution wants x pushed up.
+        let mut potential = vec![0.0; ids.len()];
+        for (pair, d) in pairs.iter().zip(distribution.iter()) {
+            potential[pair.better] += d;
+            potential[pair.worse] -= d;
+        }
+
+        // r = sum of pi(x) over instances that fire; pick the threshold with the largest |r|.
+        let mut best: Option<Scored<(usize, usize, f64)>> = None;
+        for (f, sf) in sorted_features.iter().enumerate() {
+            let mut r = 0.0;
+            let mut next_cut = 0;
+            for (pos, (_, index)) in sf.values.iter().enumerate() {
+                if next_cut >= sf.cut_points.len() {
+                    break;
+                }
+                r += potential[*index];
+                if pos == sf.cut_points[next_cut] {
+                    next_cut += 1;
+                    let candidate = (f, pos, r);
+                    match best.as_mut() {
+                        None => best = Some(Scored::try_new(r.abs(), candidate)?),
+                        Some(b) => {
+                            b.replace_if_better(r.abs(), candidate);
+                        }
+                    }
+                }
+            }
+        }
+        let (f, pos, r) = match best {
+            Some(b) => b.item,
+            None => break,
+        };
+        let sf = &sorted_features[f];
+        let r = r.clamp(-1.0 + 1e-10, 1.0 - 1e-10);
+        let alpha = 0.5 * ((1.0 + r) / (1.0 - r)).ln();
+        // Put the threshold halfway between the last firing value and the first silent one.
+        let theta = (sf.values[pos].0 + sf.values[pos + 1].0) / 2.0;
+
+        let mut fires = vec![false; ids.len()];
+        for (_, index) in sf.values[..=pos].iter() {
+            fires[*index] = true;
+        }
+        let h = |index: usize| if fires[index] { 1.0 } else { 0.0 };
+        let mut total = 0.0;
+        for (pair, d) in pairs.iter().zip(distribution.iter_mut()) {
+            *d *= (alpha * (h(pair.worse) - h(pair.better))).exp();
+            total += *d;
+        }
+        for d in distribution.iter_mut() {
+            *d /= total;
+        }
+
+        let stump = TreeNode::FeatureSplit {
+            fid: sf.fid,
+            split: theta,
+            lhs: Box::new(TreeNode::LeafNode(NotNan::new(0.0).unwrap())),
+            rhs: Box::new(TreeNode::LeafNode(NotNan::new(1.0).unwrap())),
+            missing_left: None,
+            gain: r.abs(),
+        };
+        members.push(Scored::try_new(alpha, ModelEnum::DecisionTree(stump))?);
+
+        if !params.quiet {
+            let ensemble = WeightedEnsemble::new(members.clone());
+            println!(
+                "|{:>7}|{:<16}|{:>8.3}|{:>7.3}|",
+                round + 1,
+                dataset.feature_name(sf.fid),
+                theta,
+                evaluator.evaluate_mean(&ensemble)
+            );
+        }
+        if stopping.observe(members.len(), || WeightedEnsemble::new(members.clone())) {
+            break;
+        }
+    }
+
+    if !params.quiet {
+        println!("----------------------------------------");
+        stopping.print_summary();
+    }
+
+    if let Some(size) = stopping.best_size() {
+        members.truncate(size);
+    }
+    Ok(ModelEnum::Ensemble(WeightedEnsemble::new(members)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+
+    #[test]
+    fn test_rankboost_beats_single_feature() {
+        let train_dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let eval = SetEvaluator::create(&train_dataset, "ndcg@5", None).unwrap();
+        let params = RankBoostParams {
+            quiet: true,
+            num_rounds: 50,
+            ..RankBoostParams::default()
+        };
+        let model = learn(&params, &train_dataset, &eval, None).unwrap();
+        // para-fraction alone gets 0.439 NDCG@5 on this data.
+        assert!(eval.evaluate_mean(&model) > 0.439);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::dataset::RankingDataset;
+use crate::error::FastRankError;
+use crate::evaluators::SetEvaluator;
+use crate::model::{DenseLinearRankingModel, ModelEnum};
+use crate::neural::Standardizer;
+use crate::validation::{EarlyStopping, ValidationSet};
+use crate::InstanceId;
+use oorandom::Rand64;
+use ordered_float::NotNan;
+use std::error::Error;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RankSVMParams {
+    pub seed: u64,
+    pub quiet: bool,
+    /// The L2 regularization strength (lambda in Pegasos).
+    pub regularization: f64,
+    /// Each epoch takes as many SGD steps as there are training instances.
+    pub num_epochs: u32,
+}
+
+impl Default for RankSVMParams {
+    fn default() -> Self {
+        let mut rand = Rand64::new(0xdeadbeef);
+        Self {
+            seed: rand.rand_u64(),
+            quiet: false,
+            regularization: 1e-4,
+            num_epochs: 20,
+        }
+    }
+}
+
+/// A query with at least one preference pair to learn from.
+struct PairableQuery {
+    docs: Vec<InstanceId>,
+    gains: Vec<NotNan<f32>>,
+}
+
+impl PairableQuery {
+    /// Rejection-sample a (better, worse) pair; terminates because gains are not all equal.
+    fn sample_pair(&self, rand: &mut Rand64) -> (InstanceId, InstanceId) {
+        let n = self.docs.len() as u64;
+        loop {
+            let i = rand.rand_range(0..n) as usize;
+            let j = rand.rand_range(0..n) as usize;
+            if self.gains[i] > self.gains[j] {
+                return (self.docs[i], self.docs[j]);
+            }
+        }
+    }
+}
+
+/// Pegasos-style SGD on the pairwise hinge loss: lambda/2 |w|^2 + max(0, 1 - w.(x_better - x_worse)).
+pub fn learn(
+    params: &RankSVMParams,
+    dataset: &dyn RankingDataset,
+    evaluator: &SetEvaluator,
+    validation: Option<&ValidationSet>,
+) -> Result<ModelEnum, Box<dyn Error>> {
+    // The step size is 1/(lambda t), so lambda must be positive for the weights to stay real.
+    if !(params.regularization > 0.0 && params.regularization.is_finite()) {
+        Err(FastRankError::InvalidParameter(format!(
+            "regularization must be positive and finite, found {}",
+            params.regularization
+        )))?;
+    }
+    if params.num_epochs == 0 {
+        Err(FastRankError::InvalidParameter(
+            "num_epochs must be at least 1".to_owned(),
+        ))?;
+    }
+    let mut rand = Rand64::new(params.seed.into());
+    let standardizer = Standardizer::new(dataset);
+    let n_dim = standardizer.n_dim(dataset);
+
+    let mut queries: Vec<(String, Vec<InstanceId>)> =
+        dataset.instances_by_query().into_iter().collect();
+    // Sort first so sampling only depends on the seed.
+    queries.sort_unstable();
+    let queries: Vec<PairableQuery> = queries
+        .into_iter()
+        .map(|(_, docs)| {
+            let gains = docs.iter().map(|id| dataset.gain(*id)).collect();
+            PairableQuery { docs, gains }
+        })
+        .filter(|q| q.gains.iter().any(|g| *g != q.gains[0]))
+        .collect();
+
+    let mut weights = vec![0.0; standardizer.fids.len()];
+    let to_model = |weights: &[f64]| {
+        let (weights, _bias) = standardizer.unscale(weights, n_dim);
+        ModelEnum::Linear(DenseLinearRankingModel { weights })
+    };
+    if queries.is_empty() {
+        return Ok(to_model(&weights));
+    }
+
+    if !params.quiet {
+        println!("-----------------------");
+        println!("|{:>7}|{:>15}|", "Epoch", evaluator.name());
+        println!("-----------------------");
+    }
+
+    let steps_per_epoch = dataset.instances().len();
+    let lambda = params.regularization;
+    let mut t = 0;
+    let mut stopping = EarlyStopping::new(validation);
+    let mut best_weights = None;
+    for epoch in 0..params.num_epochs {
+        for _ in 0..steps_per_epoch {
+            t += 1;
+            let query = &queries[rand.rand_range(0..queries.len() as u64) as usize];
+            let (better, worse) = query.sample_pair(&mut rand);
+            let diff: Vec<f64> = standardizer
+                .transform(dataset, better)
+                .into_iter()
+                .zip(standardizer.transform(dataset, worse))
+                .map(|(b, w)| b - w)
+                .collect();
+            let margin: f64 = weights.iter().zip(diff.iter()).map(|(w, d)| w * d).sum();
+
+            let eta = 1.0 / (lambda * t as f64);
+            for w in weights.iter_mut() {
+                *w *= 1.0 - eta * lambda;
+            }
+            if margin < 1.0 {
+                for (w, d) in weights.iter_mut().zip(diff.iter()) {
+                    *w += eta * d;
+                }
+            }
+            // Optional Pegasos projection onto the ball of radius 1/sqrt(lambda).
+            let norm = weights.iter().map(|w| w * w).sum::<f64>().sqrt();
+            let radius = 1.0 / lambda.sqrt();
+            if norm > radius {
+                for w in weights.iter_mut() {
+                    *w *= radius / norm;
+                }
+            }
+        }
+
+        if !params.quiet {
+            println!(
+                "|{:>7}|{:>15.3}|",
+                epoch + 1,
+                evaluator.evaluate_mean(&to_model(&weights))
+            );
+        }
+        let epochs = epoch as usize + 1;
+        let stop = stopping.observe(epochs, || to_model(&weights));
+        if stopping.best_size() == Some(epochs) {
+            best_weights = Some(weights.clone());
+        }
+        if stop {
+            break;
+        }
+    }
+
+    if !params.quiet {
+        println!("-----------------------");
+        stopping.print_summary();
+    }
+
+    Ok(to_model(best_weights.as_ref().unwrap_or(&weights)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+
+    #[test]
+    fn test_ranksvm_learns() {
+        let train_dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let eval = SetEvaluator::create(&train_dataset, "ndcg@5", None).unwrap();
+        let params = RankSVMParams {
+            seed: 42,
+            quiet: true,
+            num_epochs: 5,
+            ..RankSVMParams::default()
+        };
+        let model = learn(&params, &train_dataset, &eval, None).unwrap();
+        // para-fraction alone gets 0.439 NDCG@5 on this data.
+        assert!(eval.evaluate_mean(&model) > 0.439);
+
+        for bad in &[
+            RankSVMParams {
+                regularization: 0.0,
+                ..params.clone()
+            },
+            RankSVMParams {
+                num_epochs: 0,
+                ..params.clone()
+            },
+        ] {
+            let err = learn(bad, &train_dataset, &eval, None).err().unwrap();
+            assert_eq!("invalid_parameter", crate::error::error_code(err.as_ref()));
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::dataset::{DatasetRef, RankingDataset, SampledDatasetRef};
+use crate::error::FastRankError;
 use crate::randutil;
 use crate::FeatureId;
 use crate::InstanceId;
@@ -11,6 +12,16 @@ pub trait DatasetSampling {
     /// At least one feature and one instance is selected no matter how small the percentage.
     fn random_sample(&self, frate: f64, srate: f64, rand: &mut Rand64) -> SampledDatasetRef;
 
+    /// Like random_sample, but draws srate percent of queries (or of instances, if not
+    /// `by_query`) with replacement, so a sample may hold several copies of an instance.
+    fn bootstrap_sample(
+        &self,
+        frate: f64,
+        srate: f64,
+        by_query: bool,
+        rand: &mut Rand64,
+    ) -> SampledDatasetRef;
+
     /// This represents a deterministic sampling of instances.
     fn with_instances(&self, instances: &[InstanceId]) -> SampledDatasetRef;
 
@@ -19,13 +30,14 @@ pub trait DatasetSampling {
 
     /// This represents a dataset sample with a deterministic subset of features.
     /// Errors when no features remaining or features to keep not available.
-    fn with_features(&self, features: &[FeatureId]) -> Result<SampledDatasetRef, String>;
+    fn with_features(&self, features: &[FeatureId]) -> Result<SampledDatasetRef, FastRankError>;
 
+    /// Split queries into (train, test); errors unless both sides get at least one query.
     fn train_test(
         &self,
         test_fraction: f64,
         rand: &mut Rand64,
-    ) -> (SampledDatasetRef, SampledDatasetRef);
+    ) -> Result<(SampledDatasetRef, SampledDatasetRef), FastRankError>;
 }
 
 impl DatasetRef {
@@ -64,6 +76,40 @@ impl DatasetSampling for DatasetRef {
             instances,
         }
     }
+    fn bootstrap_sample(
+        &self,
+        frate: f64,
+        srate: f64,
+        by_query: bool,
+        rand: &mut Rand64,
+    ) -> SampledDatasetRef {
+        let mut features = self.features();
+        features.sort_unstable();
+        let n_features = cmp::max(1, ((features.len() as f64) * frate) as usize);
+        let features = randutil::sample_without_replacement(&features, rand, n_features);
+
+        let instances = if by_query {
+            let mut queries = self.queries();
+            queries.sort_unstable();
+            let n_queries = cmp::max(1, ((queries.len() as f64) * srate) as usize);
+            let by_query = self.instances_by_query();
+            randutil::sample_with_replacement(&queries, rand, n_queries)
+                .iter()
+                .flat_map(|qid| by_query[qid].iter().cloned())
+                .collect()
+        } else {
+            let mut instances = self.instances();
+            instances.sort_unstable();
+            let n_instances = cmp::max(1, ((instances.len() as f64) * srate) as usize);
+            randutil::sample_with_replacement(&instances, rand, n_instances)
+        };
+
+        SampledDatasetRef {
+            parent: self.clone(),
+            features,
+            instances,
+        }
+    }
     fn with_instances(&self, instances: &[InstanceId]) -> SampledDatasetRef {
         SampledDatasetRef {
             parent: self.get_ref_or_clone(),
@@ -73,7 +119,7 @@ impl DatasetSampling for DatasetRef {
     }
 
     /// This represents a dataset sample with a deterministic subset of features.
-    fn with_features(&self, features: &[FeatureId]) -> Result<SampledDatasetRef, String> {
+    fn with_features(&self, features: &[FeatureId]) -> Result<SampledDatasetRef, FastRankError> {
         let valid_features: HashSet<FeatureId> = self.features().into_iter().collect();
         let mut keep_features = HashSet::new();
         let mut missing_features = HashSet::new();
@@ -84,10 +130,13 @@ impl DatasetSampling for DatasetRef {
                 missing_features.insert(fid);
             }
         }
-        if missing_features.len() > 0 {
-            Err(format!("Missing Features: {:?}", missing_features))
-        } else if keep_features.len() == 0 {
-            Err(format!("No Features!"))
+        if !missing_features.is_empty() {
+            Err(FastRankError::MissingFeature(format!(
+                "{:?}",
+                missing_features
+            )))
+        } else if keep_features.is_empty() {
+            Err(FastRankError::EmptyDataset("No Features!".to_string()))
         } else {
             Ok(SampledDatasetRef {
                 parent: self.get_ref_or_clone(),
@@ -118,23 +167,23 @@ impl DatasetSampling for DatasetRef {
         &self,
         test_fraction: f64,
         rand: &mut Rand64,
-    ) -> (SampledDatasetRef, SampledDatasetRef) {
+    ) -> Result<(SampledDatasetRef, SampledDatasetRef), FastRankError> {
         let mut qs = self.queries();
         let n_test_qs = ((qs.len() as f64) * test_fraction) as usize;
-        if n_test_qs <= 0 {
-            panic!(
-                "Must be some testing data selected: frac={}!",
-                test_fraction
-            );
-        }
-        if n_test_qs >= qs.len() {
-            panic!("Must be some training data left!");
+        if n_test_qs == 0 || n_test_qs >= qs.len() {
+            Err(FastRankError::InvalidParameter(format!(
+                "Holding out {} of {} queries leaves one side of the split empty.",
+                test_fraction,
+                qs.len()
+            )))?;
         }
 
+        // Sort first, as in random_sample, so the split only depends on the RNG.
+        qs.sort_unstable();
         randutil::shuffle(&mut qs, rand);
-        let test_qs = qs.split_off(n_test_qs);
-        let train_qs = qs;
+        let train_qs = qs.split_off(n_test_qs);
+        let test_qs = qs;
 
-        (self.with_queries(&train_qs), self.with_queries(&test_qs))
+        Ok((self.with_queries(&train_qs), self.with_queries(&test_qs)))
     }
 }
@@ -43,8 +43,8 @@ impl Default for StreamingStats {
             num_elements: 0,
             mean: 0.0,
             s_value: 0.0,
-            max: std::f64::MIN,
-            min: std::f64::MAX,
+            max: f64::MIN,
+            min: f64::MAX,
             total: 0.0,
         }
     }
@@ -52,18 +52,14 @@ impl Default for StreamingStats {
 
 impl StreamingStats {
     pub fn finish(&self) -> Option<ComputedStats> {
-        if let Some(var) = self.get_variance() {
-            Some(ComputedStats {
-                num_elements: self.num_elements,
-                mean: self.mean,
-                max: self.max,
-                min: self.min,
-                variance: var,
-                total: self.total,
-            })
-        } else {
-            None
-        }
+        self.get_variance().map(|var| ComputedStats {
+            num_elements: self.num_elements,
+            mean: self.mean,
+            max: self.max,
+            min: self.min,
+            variance: var,
+            total: self.total,
+        })
     }
     pub fn new() -> Self {
         Self::default()
@@ -144,7 +140,7 @@ impl PercentileStats {
         self.percentile(0.5)
     }
     pub fn percentile(&self, percentile: f64) -> f64 {
-        if percentile < 0.0 || percentile > 1.0 {
+        if !(0.0..=1.0).contains(&percentile) {
             panic!("Bad percentile: {}, should be 0<x<1", percentile);
         }
         let n = percentile * ((self.dataset.len() - 1) as f64);
@@ -155,7 +151,7 @@ impl PercentileStats {
             return self.dataset[lhs].into_inner();
         }
         // LERP:
-        (interp * self.dataset[lhs].into_inner()) + (1.0 - interp) * self.dataset[rhs].into_inner()
+        ((1.0 - interp) * self.dataset[lhs].into_inner()) + interp * self.dataset[rhs].into_inner()
     }
     pub fn summary(&self) -> (f64, f64, f64, f64, f64) {
         (
@@ -199,5 +195,7 @@ mod tests {
         assert_float_eq("median", data.median(), 4.5);
         let data = PercentileStats::new(&(0..9).map(|i| i as f64).collect::<Vec<_>>());
         assert_float_eq("median", data.median(), 4.0);
+        let data = PercentileStats::new(&(0..10).map(|i| i as f64).collect::<Vec<_>>());
+        assert_float_eq("p25", data.percentile(0.25), 2.25);
     }
 }
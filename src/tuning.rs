@@ -0,0 +1,390 @@
+use crate::cross_validation::{self, CrossValidationRequest};
+use crate::dataset::DatasetRef;
+use crate::error::{self, FastRankError};
+use crate::evaluators::SetEvaluator;
+use crate::json_api::{self, FastRankModelParams, TrainRequest};
+use crate::validation::holdout_split;
+use oorandom::Rand64;
+use ordered_float::NotNan;
+use rayon::prelude::*;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// The values one parameter may take, named by its field in the learner's params (e.g., "max_depth").
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ParamDistribution {
+    /// Any JSON value the field accepts.
+    Choice(Vec<Value>),
+    /// Every integer from low to high, inclusive.
+    IntRange {
+        low: i64,
+        high: i64,
+    },
+    Uniform {
+        low: f64,
+        high: f64,
+    },
+    /// Uniform in log-space; suits learning rates and regularization.
+    LogUniform {
+        low: f64,
+        high: f64,
+    },
+}
+
+impl ParamDistribution {
+    /// Reject bounds that would sample nothing, or nonsense like the log of a negative number.
+    fn validate(&self, name: &str) -> Result<(), FastRankError> {
+        let ok = match self {
+            ParamDistribution::Choice(values) => !values.is_empty(),
+            ParamDistribution::IntRange { low, high } => {
+                low <= high && high.checked_sub(*low).is_some()
+            }
+            ParamDistribution::Uniform { low, high } => {
+                low.is_finite() && high.is_finite() && low <= high
+            }
+            ParamDistribution::LogUniform { low, high } => {
+                *low > 0.0 && high.is_finite() && low <= high
+            }
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(FastRankError::InvalidParameter(format!(
+                "Bad distribution for {}: {:?}",
+                name, self
+            )))
+        }
+    }
+    fn grid(&self) -> Result<Vec<Value>, Box<dyn Error>> {
+        Ok(match self {
+            ParamDistribution::Choice(values) => values.clone(),
+            ParamDistribution::IntRange { low, high } => (*low..=*high).map(Value::from).collect(),
+            other => Err(FastRankError::InvalidParameter(format!(
+                "Cannot enumerate {:?} for a grid search.",
+                other
+            )))?,
+        })
+    }
+    fn sample(&self, rand: &mut Rand64) -> Result<Value, Box<dyn Error>> {
+        Ok(match self {
+            ParamDistribution::Choice(values) => {
+                values[rand.rand_range(0..values.len() as u64) as usize].clone()
+            }
+            ParamDistribution::IntRange { low, high } => {
+                // validate() keeps high - low from overflowing; the +1 then fits in a u64.
+                let width = (high - low) as u64 + 1;
+                Value::from(low + rand.rand_range(0..width) as i64)
+            }
+            ParamDistribution::Uniform { low, high } => {
+                Value::from(low + rand.rand_float() * (high - low))
+            }
+            ParamDistribution::LogUniform { low, high } => {
+                let (low, high) = (low.ln(), high.ln());
+                Value::from((low + rand.rand_float() * (high - low)).exp())
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SearchStrategy {
+    /// Every combination of the (discrete) distributions.
+    Grid,
+    Random {
+        num_samples: u32,
+    },
+}
+
+/// How each configuration is scored.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TuningValidation {
+    /// Train on the rest, score on a fraction of held-out queries.
+    Holdout {
+        fraction: f64,
+    },
+    CrossValidation {
+        num_folds: u32,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TuningRequest {
+    /// The starting point; every configuration overrides some of its params.
+    pub train_request: TrainRequest,
+    pub space: BTreeMap<String, ParamDistribution>,
+    pub strategy: SearchStrategy,
+    pub validation: TuningValidation,
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TuningTrial {
+    pub settings: BTreeMap<String, Value>,
+    pub score: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TuningResult {
+    /// Every configuration tried, best first.
+    pub leaderboard: Vec<TuningTrial>,
+    pub best: TrainRequest,
+}
+
+/// Like override_params, but configurations train side-by-side, so they are always made quiet.
+pub fn apply_settings(
+    params: &FastRankModelParams,
+    settings: &BTreeMap<String, Value>,
+) -> Result<FastRankModelParams, Box<dyn Error>> {
+    override_fields(params, settings, true)
+}
+
+/// Override fields of the learner's params by going through their JSON form.
+/// A setting the learner does not have is an error.
+pub fn override_params(
+    params: &FastRankModelParams,
+    settings: &BTreeMap<String, Value>,
+) -> Result<FastRankModelParams, Box<dyn Error>> {
+    override_fields(params, settings, false)
+}
+
+fn override_fields(
+    params: &FastRankModelParams,
+    settings: &BTreeMap<String, Value>,
+    make_quiet: bool,
+) -> Result<FastRankModelParams, Box<dyn Error>> {
+    let mut json = serde_json::to_value(params)?;
+    let inner = json
+        .as_object_mut()
+        .and_then(|variant| variant.values_mut().next())
+        .and_then(|inner| inner.as_object_mut())
+        .ok_or("Learner params should serialize to a JSON object.")?;
+    for (name, value) in settings.iter() {
+        match inner.get_mut(name) {
+            Some(field) => *field = value.clone(),
+            None => Err(FastRankError::InvalidParameter(format!(
+                "Unknown parameter for this learner: {}",
+                name
+            )))?,
+        }
+    }
+    if make_quiet {
+        if let Some(quiet) = inner.get_mut("quiet") {
+            *quiet = Value::Bool(true);
+        }
+    }
+    // serde_json's Value deserializer rejects empty tuple variants like SquaredError(); text does not.
+    Ok(serde_json::from_str(&json.to_string())?)
+}
+
+fn configurations(request: &TuningRequest) -> Result<Vec<BTreeMap<String, Value>>, Box<dyn Error>> {
+    for (name, dist) in request.space.iter() {
+        dist.validate(name)?;
+    }
+    match request.strategy {
+        SearchStrategy::Grid => {
+            let mut configs = vec![BTreeMap::new()];
+            for (name, dist) in request.space.iter() {
+                let values = dist.grid()?;
+                configs = configs
+                    .into_iter()
+                    .flat_map(|config| {
+                        values.iter().map(move |value| {
+                            let mut next = config.clone();
+                            next.insert(name.clone(), value.clone());
+                            next
+                        })
+                    })
+                    .collect();
+            }
+            Ok(configs)
+        }
+        SearchStrategy::Random { num_samples } => {
+            let mut rand = Rand64::new(request.seed.into());
+            (0..num_samples)
+                .map(|_| {
+                    request
+                        .space
+                        .iter()
+                        .map(|(name, dist)| Ok((name.clone(), dist.sample(&mut rand)?)))
+                        .collect()
+                })
+                .collect()
+        }
+    }
+}
+
+fn score_configuration(
+    train_request: &TrainRequest,
+    request: &TuningRequest,
+    holdout: &Option<(DatasetRef, DatasetRef)>,
+    dataset: &DatasetRef,
+) -> Result<f64, Box<dyn Error>> {
+    if let Some((train, test)) = holdout {
+        let model = json_api::do_training(train_request.clone(), train, None)?;
+        let evaluator = SetEvaluator::create(
+            test,
+            train_request.measure.as_str(),
+            train_request.judgments.clone(),
+        )?;
+        return Ok(evaluator.evaluate_mean(&model));
+    }
+    let num_folds = match request.validation {
+        TuningValidation::CrossValidation { num_folds } => num_folds,
+        TuningValidation::Holdout { .. } => Err(FastRankError::InvalidParameter(
+            "Holdout split is missing.".to_owned(),
+        ))?,
+    };
+    let result = cross_validation::cross_validate(
+        CrossValidationRequest {
+            train_request: train_request.clone(),
+            num_folds,
+            seed: request.seed,
+        },
+        dataset,
+    )?;
+    Ok(result.mean)
+}
+
+/// Score every configuration in parallel and rank them by the training measure.
+pub fn tune(request: TuningRequest, dataset: &DatasetRef) -> Result<TuningResult, Box<dyn Error>> {
+    let configs = configurations(&request)?;
+    if configs.is_empty() {
+        Err(FastRankError::InvalidParameter(
+            "Search space produced no configurations.".to_owned(),
+        ))?;
+    }
+    let requests: Vec<TrainRequest> = configs
+        .iter()
+        .map(|settings| {
+            Ok(TrainRequest {
+                params: apply_settings(&request.train_request.params, settings)?,
+                ..request.train_request.clone()
+            })
+        })
+        .collect::<Result<_, Box<dyn Error>>>()?;
+
+    let holdout = match request.validation {
+        TuningValidation::Holdout { fraction } => {
+            Some(holdout_split(dataset, fraction, request.seed)?)
+        }
+        TuningValidation::CrossValidation { .. } => None,
+    };
+
+    let scores: Vec<NotNan<f64>> = requests
+        .par_iter()
+        .map(|train_request| {
+            let score = score_configuration(train_request, &request, &holdout, dataset)
+                .map_err(error::into_send)?;
+            NotNan::new(score).map_err(|_| {
+                FastRankError::NaN("score of a tuning configuration".to_owned()).into()
+            })
+        })
+        .collect::<Result<_, Box<dyn Error + Send + Sync>>>()
+        .map_err(|e| e as Box<dyn Error>)?;
+    // A stable sort, so ties go to the configuration tried first.
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by_key(|i| Reverse(scores[*i]));
+    let best = requests[order[0]].clone();
+    let leaderboard = order
+        .into_iter()
+        .map(|i| TuningTrial {
+            settings: configs[i].clone(),
+            score: scores[i].into_inner(),
+        })
+        .collect();
+
+    Ok(TuningResult { leaderboard, best })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataset;
+    use crate::random_forest::RandomForestParams;
+
+    #[test]
+    fn test_grid_search() {
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let mut space = BTreeMap::new();
+        space.insert(
+            "num_trees".to_owned(),
+            ParamDistribution::Choice(vec![Value::from(1), Value::from(5)]),
+        );
+        space.insert(
+            "max_depth".to_owned(),
+            ParamDistribution::IntRange { low: 2, high: 4 },
+        );
+        let request = TuningRequest {
+            train_request: TrainRequest {
+                measure: "ndcg@5".to_owned(),
+                params: FastRankModelParams::RandomForest(RandomForestParams {
+                    seed: 42,
+                    ..RandomForestParams::default()
+                }),
+                ..TrainRequest::default()
+            },
+            space,
+            strategy: SearchStrategy::Grid,
+            validation: TuningValidation::Holdout { fraction: 0.3 },
+            seed: 42,
+        };
+
+        let result = tune(request.clone(), &dataset).unwrap();
+        assert_eq!(6, result.leaderboard.len());
+        for pair in result.leaderboard.windows(2) {
+            assert!(pair[0].score >= pair[1].score);
+        }
+        match result.best.params {
+            FastRankModelParams::RandomForest(params) => {
+                let top = &result.leaderboard[0].settings;
+                assert_eq!(top["num_trees"], Value::from(params.num_trees));
+                assert_eq!(top["max_depth"], Value::from(params.max_depth));
+                assert!(params.quiet);
+            }
+            _ => panic!("Tuning should not change the learner!"),
+        }
+
+        let mut typo = request;
+        typo.space.insert(
+            "num_tres".to_owned(),
+            ParamDistribution::Choice(vec![Value::from(1)]),
+        );
+        assert!(tune(typo, &dataset).is_err());
+    }
+
+    #[test]
+    fn test_bad_distributions_are_errors() {
+        let bad = vec![
+            ParamDistribution::Choice(Vec::new()),
+            ParamDistribution::IntRange { low: 4, high: 2 },
+            ParamDistribution::IntRange { low: 4, high: 3 },
+            ParamDistribution::IntRange {
+                low: i64::MIN,
+                high: i64::MAX,
+            },
+            ParamDistribution::Uniform {
+                low: 0.0,
+                high: f64::NAN,
+            },
+            ParamDistribution::LogUniform {
+                low: 0.0,
+                high: 1.0,
+            },
+            ParamDistribution::LogUniform {
+                low: -1.0,
+                high: 1.0,
+            },
+        ];
+        for dist in bad {
+            assert_eq!("invalid_parameter", dist.validate("x").unwrap_err().code());
+        }
+        let one = ParamDistribution::IntRange { low: 3, high: 3 };
+        assert!(one.validate("x").is_ok());
+        assert_eq!(Value::from(3), one.sample(&mut Rand64::new(1)).unwrap());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::dataset::DatasetRef;
+use crate::error::FastRankError;
+use crate::evaluators::SetEvaluator;
+use crate::model::Model;
+use crate::sampling::DatasetSampling;
+use crate::Scored;
+use oorandom::Rand64;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ValidationParams {
+    /// Fraction of the training queries to hold out when no validation dataset is given.
+    pub fraction: f64,
+    pub seed: u64,
+    /// Stop after this many rounds in a row fail to improve the validation measure.
+    pub patience: Option<u32>,
+}
+
+impl Default for ValidationParams {
+    fn default() -> Self {
+        let mut rand = Rand64::new(0xdeadbeef);
+        Self {
+            fraction: 0.2,
+            seed: rand.rand_u64(),
+            patience: Some(10),
+        }
+    }
+}
+
+/// Hold out a fraction of the queries, seeded; see `DatasetSampling::train_test`.
+pub fn holdout_split(
+    dataset: &DatasetRef,
+    fraction: f64,
+    seed: u64,
+) -> Result<(DatasetRef, DatasetRef), FastRankError> {
+    let (train, test) = dataset.train_test(fraction, &mut Rand64::new(seed.into()))?;
+    Ok((train.into_ref(), test.into_ref()))
+}
+
+/// Held-out queries that learners use to choose how much of a model to keep.
+#[derive(Clone)]
+pub struct ValidationSet {
+    pub evaluator: SetEvaluator,
+    pub patience: Option<u32>,
+}
+
+/// Follows the validation measure across the rounds (trees, epochs, ...) of one training run.
+/// Without a validation set it never stops training and never picks a size.
+pub struct EarlyStopping<'v> {
+    validation: Option<&'v ValidationSet>,
+    best: Option<Scored<usize>>,
+    since_best: u32,
+}
+
+impl<'v> EarlyStopping<'v> {
+    pub fn new(validation: Option<&'v ValidationSet>) -> Self {
+        Self {
+            validation,
+            best: None,
+            since_best: 0,
+        }
+    }
+
+    /// Score the model as it stands at `size`; returns true when training should stop.
+    /// The model is only built when there is a validation set to score it on.
+    pub fn observe<M: Model, F: FnOnce() -> M>(&mut self, size: usize, model: F) -> bool {
+        let validation = match self.validation {
+            Some(v) => v,
+            None => return false,
+        };
+        let score = validation.evaluator.evaluate_mean(&model());
+        let improved = match self.best.as_mut() {
+            Some(best) => best.replace_if_better(score, size),
+            None => {
+                self.best = Some(Scored::new(score, size));
+                true
+            }
+        };
+        if improved {
+            self.since_best = 0;
+        } else {
+            self.since_best += 1;
+        }
+        match validation.patience {
+            Some(patience) => self.since_best >= patience,
+            None => false,
+        }
+    }
+
+    /// The size that scored best on the validation set so far.
+    pub fn best_size(&self) -> Option<usize> {
+        self.best.as_ref().map(|b| b.item)
+    }
+
+    pub fn print_summary(&self) {
+        if let (Some(validation), Some(best)) = (self.validation, self.best.as_ref()) {
+            println!(
+                "Validation {}: best {:.3} at {}.",
+                validation.evaluator.name(),
+                best.score,
+                best.item
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coordinate_ascent::CoordinateAscentParams;
+    use crate::dataset;
+    use crate::json_api::{self, FastRankModelParams, TrainRequest};
+    use crate::lambdamart::LambdaMARTParams;
+    use crate::model::ModelEnum;
+
+    #[test]
+    fn test_validation_picks_best_prefix() {
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let (train, test) = holdout_split(&dataset, 0.3, 42).unwrap();
+        let test_eval = SetEvaluator::create(&test, "ndcg@5", None).unwrap();
+
+        let request = |validation: Option<ValidationParams>| TrainRequest {
+            measure: "ndcg@5".to_owned(),
+            params: FastRankModelParams::LambdaMART(LambdaMARTParams {
+                num_trees: 30,
+                seed: 42,
+                quiet: true,
+                ..LambdaMARTParams::default()
+            }),
+            judgments: None,
+            validation,
+        };
+        let full = json_api::do_training(request(None), &train, None).unwrap();
+        let selected = json_api::do_training(
+            request(Some(ValidationParams {
+                patience: None,
+                ..ValidationParams::default()
+            })),
+            &train,
+            Some(&test),
+        )
+        .unwrap();
+
+        let size = |model: &ModelEnum| match model {
+            ModelEnum::Ensemble(e) => e.len(),
+            _ => panic!("Expected an ensemble!"),
+        };
+        assert!(size(&selected) <= size(&full));
+        // The full ensemble is one of the prefixes considered, so selection can't do worse.
+        assert!(test_eval.evaluate_mean(&selected) >= test_eval.evaluate_mean(&full));
+    }
+
+    #[test]
+    fn test_validation_picks_restarts_for_ensemble() {
+        let dataset =
+            dataset::LoadedRankingDataset::load_libsvm("examples/trec_news_2018.train", None)
+                .unwrap()
+                .into_ref();
+        let (train, test) = holdout_split(&dataset, 0.3, 42).unwrap();
+        let test_eval = SetEvaluator::create(&test, "ndcg@5", None).unwrap();
+        let params = CoordinateAscentParams {
+            num_restarts: 4,
+            num_max_iterations: 5,
+            output_ensemble: true,
+            seed: 42,
+            quiet: true,
+            ..CoordinateAscentParams::default()
+        };
+        let validation = ValidationSet {
+            evaluator: test_eval.clone(),
+            patience: None,
+        };
+        let train_eval = SetEvaluator::create(&train, "ndcg@5", None).unwrap();
+        let full = params.learn(&train, &train_eval, None).unwrap();
+        let selected = params
+            .learn(&train, &train_eval, Some(&validation))
+            .unwrap();
+
+        let members = |model: &ModelEnum| match model {
+            ModelEnum::Ensemble(e) => e.iter().map(|(_, m)| m.clone()).collect::<Vec<_>>(),
+            _ => panic!("Expected an ensemble!"),
+        };
+        assert_eq!(4, members(&full).len());
+        let kept = members(&selected);
+        assert!(!kept.is_empty() && kept.len() <= 4);
+        // Separate runs may break ties differently, so only compare within this one:
+        // restarts are kept best-first, and the best one alone was a candidate size.
+        let singles: Vec<f64> = kept.iter().map(|m| test_eval.evaluate_mean(m)).collect();
+        assert!(singles.windows(2).all(|w| w[0] >= w[1] - 1e-9));
+        assert!(test_eval.evaluate_mean(&selected) >= singles[0] - 1e-9);
+    }
+}
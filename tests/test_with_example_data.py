@@ -200,6 +200,100 @@ class TestRustAPI(unittest.TestCase):
             # SemVer change-detection: need to bump major version if this is no longer true!
             self.assertAlmostEqual(m, 0.436791451738704)
 
+    def test_validation_dataset(self):
+        rd = TestRustAPI.rd
+        queries = sorted(rd.queries())
+        train = rd.subsample_queries(queries[:35])
+        validate = rd.subsample_queries(queries[35:])
+        train_req = TrainRequest.lambdamart()
+        train_req.measure = "ndcg@5"
+        train_req.params.num_trees = 30
+        train_req.params.seed = 42
+        train_req.params.quiet = True
+        full = train.train_model(train_req)
+        selected = train.train_model(train_req, validation=validate)
+        self.assertLessEqual(
+            len(selected.to_dict()["Ensemble"]["weights"]),
+            len(full.to_dict()["Ensemble"]["weights"]),
+        )
+        self.assertGreaterEqual(
+            mean(list(validate.evaluate(selected, "ndcg@5").values())),
+            mean(list(validate.evaluate(full, "ndcg@5").values())),
+        )
+
+    def test_cross_validate(self):
+        rd = TestRustAPI.rd
+        train_req = TrainRequest.random_forest()
+        train_req.measure = "ndcg@5"
+        train_req.params.num_trees = 5
+        train_req.params.quiet = True
+        result = rd.cross_validate(train_req, num_folds=5, seed=42)
+        self.assertEqual(len(result["folds"]), 5)
+        tested = [q for fold in result["folds"] for q in fold["test_queries"]]
+        self.assertEqual(sorted(tested), sorted(rd.queries()))
+        self.assertAlmostEqual(result["mean"], mean(list(result["scores"].values())))
+
+    def test_tune(self):
+        rd = TestRustAPI.rd
+        train_req = TrainRequest.random_forest()
+        train_req.measure = "ndcg@5"
+        train_req.params.seed = 42
+        result = rd.tune(train_req, {"num_trees": [1, 5], "max_depth": [2, 4]})
+        self.assertEqual(len(result["leaderboard"]), 4)
+        best = result["leaderboard"][0]["settings"]
+        self.assertEqual(result["best"].params.num_trees, best["num_trees"])
+        self.assertEqual(result["best"].params.max_depth, best["max_depth"])
+
+    def test_feature_importance(self):
+        rd = TestRustAPI.rd
+        report = TestRustAPI.model.feature_importance(rd)
+        self.assertTrue(set(report.keys()) <= _EXPECTED_FEATURE_NAMES)
+        self.assertAlmostEqual(sum(fi["importance"] for fi in report.values()), 1.0)
+
+    def test_permutation_importance(self):
+        rd = TestRustAPI.rd
+        result = rd.permutation_importance(
+            TestRustAPI.model, "ndcg@5", features=["pagerank"], num_repeats=2
+        )
+        self.assertEqual(set(result["features"].keys()), set(["pagerank"]))
+        drop = result["features"]["pagerank"]
+        self.assertLessEqual(drop["lower"], drop["upper"])
+
+    def test_explain(self):
+        rd = TestRustAPI.rd
+        train_req = TrainRequest.random_forest()
+        train_req.params.num_trees = 5
+        train_req.params.quiet = True
+        model = rd.train_model(train_req)
+        ids = sorted(rd.predict_scores(model).keys())[:10]
+        for e in model.explain(rd, ids):
+            total = e["expected_value"] + sum(e["contributions"].values())
+            self.assertAlmostEqual(total, e["score"])
+
+    def test_partial_dependence(self):
+        rd = TestRustAPI.rd
+        curve = rd.partial_dependence(TestRustAPI.model, "pagerank", num_points=5, ice=True)
+        self.assertEqual(len(curve["grid"]), len(curve["average"]))
+        self.assertEqual(len(curve["ice"]), _EXPECTED_N)
+
+    def test_select_features(self):
+        rd = TestRustAPI.rd
+        train_req = TrainRequest.coordinate_ascent()
+        train_req.params.num_restarts = 1
+        train_req.params.quiet = True
+        result = rd.select_features(train_req, features=["pagerank", "caption_count"])
+        self.assertEqual(len(result["trajectory"]), 2)
+        self.assertTrue(set(result["selected"]) <= set(["pagerank", "caption_count"]))
+
+    def test_error_codes(self):
+        rd = TestRustAPI.rd
+        with self.assertRaises(fastrank.FastRankError) as context:
+            rd.partial_dependence(TestRustAPI.model, "no_such_feature")
+        self.assertEqual(context.exception.code, "missing_feature")
+        with self.assertRaises(fastrank.FastRankError) as context:
+            rd.evaluate(TestRustAPI.model, "not_a_measure")
+        self.assertEqual(context.exception.code, "invalid_parameter")
+
     def test_model_serialization(self):
         rd = TestRustAPI.rd
         model = TestRustAPI.model
@@ -240,6 +334,23 @@ class TestRustAPI(unittest.TestCase):
         assert len(scores) - 1 in scores
         assert len(scores) == len(train_y)
 
+    def test_from_numpy_layouts(self):
+        X = np.asarray(TestRustAPI.train_X.todense())
+        y = TestRustAPI.train_y
+        qid = TestRustAPI.train_qid
+        model = TestRustAPI.model
+        expected = model.predict_scores(CDataset.from_numpy(X, y, qid))
+
+        # float64, Fortran order, string qids and a copy should all score the same:
+        names = np.array(["q{0}".format(q) for q in qid], dtype=object)
+        for dataset in [
+            CDataset.from_numpy(np.asfortranarray(X), y, qid.astype(np.int32)),
+            CDataset.from_numpy(X.astype(np.float64), y, names, copy=True),
+        ]:
+            self.assertEqual(expected, model.predict_scores(dataset))
+        strs = CDataset.from_numpy(X, y, names)
+        assert strs.queries() == set("q{0}".format(q) for q in _EXPECTED_QUERIES)
+
     def test_evaluate(self):
         rd = TestRustAPI.rd
         model = TestRustAPI.model
@@ -278,6 +389,26 @@ class TestRustAPI(unittest.TestCase):
                 str(context.exception), "Dataset does not contain document ids"
             )
 
+    def test_trecrun_from_numpy(self):
+        X = np.asarray(TestRustAPI.train_X.todense())
+        (N, D) = X.shape
+        docids = ["doc{0}".format(i) for i in range(N)]
+        names = ["f{0}".format(i) for i in range(D)]
+        dataset = CDataset.from_numpy(
+            X,
+            TestRustAPI.train_y,
+            TestRustAPI.train_qid,
+            docids=docids,
+            feature_names=names,
+        )
+        assert dataset.feature_names() == set(names)
+        assert dataset.subsample_feature_names(["f0", "f1"]).num_features() == 2
+        with tempfile.NamedTemporaryFile(mode="r") as tmpf:
+            written = dataset.predict_trecrun(TestRustAPI.model, tmpf.name, depth=1)
+            self.assertEqual(len(_EXPECTED_QUERIES), written)
+            for line in tmpf:
+                self.assertIn(line.split()[2], docids)
+
     def train_req_object(self):
         rust = TrainRequest.from_dict(query_json("coordinate_ascent_defaults"))
         py = TrainRequest()